@@ -0,0 +1,197 @@
+//! Human readable and json output for palette analysis.
+
+use std::fmt::Write;
+
+use lutgen::analysis::{Analysis, Distribution, HueGap, NEAR_DUPLICATE};
+use serde_json::{json, Value};
+
+use crate::color::Color;
+
+/// Approximate Oklab hue angles of common color names
+const HUES: [(f32, &str); 10] = [
+    (0., "pink"),
+    (29., "red"),
+    (55., "orange"),
+    (100., "yellow"),
+    (125., "lime"),
+    (142., "green"),
+    (195., "cyan"),
+    (264., "blue"),
+    (300., "violet"),
+    (330., "magenta"),
+];
+
+/// Closest color name for an Oklab hue angle
+fn hue_name(hue: f32) -> &'static str {
+    let hue = hue.rem_euclid(360.);
+    HUES.iter()
+        .min_by(|a, b| {
+            let diff = |h: f32| (h - hue).abs().min(360. - (h - hue).abs());
+            diff(a.0).total_cmp(&diff(b.0))
+        })
+        .unwrap()
+        .1
+}
+
+fn grade(wcag: f32) -> &'static str {
+    match wcag {
+        r if r >= 7. => "AAA",
+        r if r >= 4.5 => "AA",
+        r if r >= 3. => "AA large",
+        _ => "fail",
+    }
+}
+
+fn sparkline(dist: &Distribution) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = dist.histogram.iter().copied().max().unwrap_or(0).max(1);
+    dist.histogram
+        .iter()
+        .map(|&n| match n {
+            0 => ' ',
+            n => BARS[n * (BARS.len() - 1) / max],
+        })
+        .collect()
+}
+
+fn gap_range(gap: &HueGap) -> (f32, f32) {
+    (gap.start, (gap.start + gap.size).rem_euclid(360.))
+}
+
+/// Analysis as text, with 24-bit ansi swatches if enabled.
+pub fn text(name: &str, analysis: &Analysis, ansi: bool) -> String {
+    let color = |c: [u8; 3]| {
+        if ansi {
+            Color(c).swatch()
+        } else {
+            Color(c).to_string()
+        }
+    };
+    let mut out = String::new();
+
+    if ansi {
+        writeln!(out, "\x1b[4m{name}\x1b[0m").unwrap();
+    } else {
+        writeln!(out, "{name}").unwrap();
+    }
+
+    writeln!(out, "\nContrast").unwrap();
+    let mut bg = None;
+    for c in &analysis.contrast {
+        if bg != Some(c.bg) {
+            writeln!(out, "  on {}", color(c.bg)).unwrap();
+            bg = Some(c.bg);
+        }
+        writeln!(
+            out,
+            "    {}  WCAG {:>5.2} {:<8}  APCA {:>6.1}",
+            color(c.fg),
+            c.wcag,
+            grade(c.wcag),
+            c.apca
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "\nDistinctness").unwrap();
+    if let Some(pair) = analysis.closest {
+        writeln!(
+            out,
+            "  closest pair     {} {}  ΔE {:.2}",
+            color(pair.a),
+            color(pair.b),
+            pair.delta_e
+        )
+        .unwrap();
+    }
+    if analysis.near_duplicates.is_empty() {
+        writeln!(out, "  near duplicates  none under ΔE {NEAR_DUPLICATE}").unwrap();
+    } else {
+        writeln!(out, "  near duplicates  under ΔE {NEAR_DUPLICATE}").unwrap();
+        for pair in &analysis.near_duplicates {
+            let (a, b) = (color(pair.a), color(pair.b));
+            writeln!(out, "    {a} {b}  ΔE {:.2}", pair.delta_e).unwrap();
+        }
+    }
+
+    writeln!(out, "\nDistribution").unwrap();
+    for (label, dist) in [
+        ("lightness", &analysis.lightness),
+        ("chroma", &analysis.chroma),
+    ] {
+        writeln!(
+            out,
+            "  {label:<10} {:.2} – {:.2}, mean {:.2}  [{}]",
+            dist.min,
+            dist.max,
+            dist.mean,
+            sparkline(dist)
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "\nHue gaps").unwrap();
+    if analysis.hue_gaps.is_empty() {
+        writeln!(out, "  none").unwrap();
+    }
+    for gap in &analysis.hue_gaps {
+        let (start, end) = gap_range(gap);
+        writeln!(
+            out,
+            "  {start:>3.0}° – {end:>3.0}°  {:>3.0}° wide, {} to {}",
+            gap.size,
+            hue_name(start),
+            hue_name(end)
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        out,
+        "\nRemapping\n  mean ΔE {:.2} over an sRGB grid",
+        analysis.remap_error
+    )
+    .unwrap();
+    for region in &analysis.poor_regions {
+        writeln!(
+            out,
+            "    {} → {}  ΔE {:.2}",
+            color(region.color),
+            color(region.mapped),
+            region.delta_e
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+/// Analysis as a json object.
+pub fn json(analysis: &Analysis) -> Value {
+    let hex = |c: [u8; 3]| Color(c).to_string();
+    let pair =
+        |p: &lutgen::analysis::Pair| json!({ "a": hex(p.a), "b": hex(p.b), "delta_e": p.delta_e });
+    let dist = |d: &Distribution| json!({ "min": d.min, "mean": d.mean, "max": d.max, "histogram": d.histogram });
+    json!({
+        "contrast": analysis.contrast.iter().map(|c| json!({
+            "fg": hex(c.fg),
+            "bg": hex(c.bg),
+            "wcag": c.wcag,
+            "apca": c.apca,
+        })).collect::<Vec<_>>(),
+        "closest": analysis.closest.as_ref().map(pair),
+        "near_duplicates": analysis.near_duplicates.iter().map(pair).collect::<Vec<_>>(),
+        "lightness": dist(&analysis.lightness),
+        "chroma": dist(&analysis.chroma),
+        "hue_gaps": analysis.hue_gaps.iter().map(|g| {
+            let (start, end) = gap_range(g);
+            json!({ "start": start, "end": end, "size": g.size })
+        }).collect::<Vec<_>>(),
+        "remap_error": analysis.remap_error,
+        "poor_regions": analysis.poor_regions.iter().map(|r| json!({
+            "color": hex(r.color),
+            "mapped": hex(r.mapped),
+            "delta_e": r.delta_e,
+        })).collect::<Vec<_>>(),
+    })
+}
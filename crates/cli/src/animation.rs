@@ -0,0 +1,311 @@
+use std::fmt::Write;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::path::Path;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::webp::WebPEncoder;
+use image::metadata::LoopCount;
+use image::{AnimationDecoder, ExtendedColorType, Frame, ImageFormat};
+use rayon::prelude::*;
+
+use crate::output::write_output;
+
+/// Name of the ffconcat list written alongside a frame sequence.
+pub const FRAME_LIST: &str = "frames.txt";
+
+/// Decoded animation, keeping the frame delays and loop count of the source.
+pub struct Animation {
+    /// Container the animation was decoded from
+    pub format: ImageFormat,
+    pub frames: Vec<Frame>,
+    pub loop_count: LoopCount,
+}
+
+impl Animation {
+    /// Decode every frame of an animation, composited to the full canvas.
+    pub fn decode<'a>(
+        format: ImageFormat,
+        decoder: impl AnimationDecoder<'a>,
+    ) -> Result<Self, String> {
+        let loop_count = decoder.loop_count();
+        let frames = decoder
+            .into_frames()
+            .collect_frames()
+            .map_err(|e| format!("failed to decode frames: {e}"))?;
+        Ok(Self {
+            format,
+            frames,
+            loop_count,
+        })
+    }
+
+    /// Encode the animation into a single file. Gif, png (as APNG), and webp are supported.
+    pub fn encode(&self, format: ImageFormat) -> Result<Vec<u8>, String> {
+        match format {
+            ImageFormat::Gif => self.gif(),
+            ImageFormat::Png => self.apng(),
+            ImageFormat::WebP => self.webp(),
+            _ => Err(format!(
+                "{} does not support animation, use gif, png, or webp, or write frames instead",
+                format.extensions_str()[0]
+            )),
+        }
+    }
+
+    /// Write each frame as a numbered image in a directory, along with an ffconcat list of the
+    /// frame durations. Returns the number of frames written.
+    pub fn write_frames(&self, dir: &Path, format: ImageFormat) -> Result<usize, String> {
+        let digits = self.frames.len().to_string().len().max(4);
+        let extension = format.extensions_str()[0];
+        let name = |i: usize| format!("{:0digits$}.{extension}", i + 1);
+
+        self.frames
+            .par_iter()
+            .enumerate()
+            .try_for_each(|(i, frame)| {
+                let mut buf = Cursor::new(Vec::new());
+                frame
+                    .buffer()
+                    .write_to(&mut buf, format)
+                    .map_err(|e| format!("failed to encode frame {}: {e}", i + 1))?;
+                write_output(&dir.join(name(i)), buf.get_ref())
+            })?;
+
+        let mut list = String::from("ffconcat version 1.0\n");
+        for (i, frame) in self.frames.iter().enumerate() {
+            let seconds = delay_ms(frame) / 1000.;
+            writeln!(list, "file {}\nduration {seconds}", name(i)).unwrap();
+        }
+        write_output(&dir.join(FRAME_LIST), list.as_bytes())?;
+        Ok(self.frames.len())
+    }
+
+    fn gif(&self) -> Result<Vec<u8>, String> {
+        let mut buf = Vec::new();
+        let mut encoder = GifEncoder::new(&mut buf);
+        encoder
+            .set_repeat(match self.loop_count {
+                LoopCount::Infinite => Repeat::Infinite,
+                LoopCount::Finite(n) => Repeat::Finite(n.get().try_into().unwrap_or(u16::MAX)),
+            })
+            .map_err(|e| format!("failed to encode gif: {e}"))?;
+        encoder
+            .encode_frames(self.frames.iter().cloned())
+            .map_err(|e| format!("failed to encode frame: {e}"))?;
+        drop(encoder);
+        Ok(buf)
+    }
+
+    fn apng(&self) -> Result<Vec<u8>, String> {
+        let (width, height) = self.dimensions()?;
+        let err = |e: png::EncodingError| format!("failed to encode apng: {e}");
+        let mut buf = Vec::new();
+        let mut encoder = png::Encoder::new(&mut buf, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .set_animated(self.frames.len() as u32, self.plays())
+            .map_err(err)?;
+
+        let mut writer = encoder.write_header().map_err(err)?;
+        for frame in &self.frames {
+            // Delays are stored as a fraction of a second
+            let ms = delay_ms(frame).round().clamp(0., u16::MAX as f64) as u16;
+            writer.set_frame_delay(ms, 1000).map_err(err)?;
+            writer.write_image_data(frame.buffer()).map_err(err)?;
+        }
+        writer.finish().map_err(err)?;
+        Ok(buf)
+    }
+
+    /// Animated webp, with each frame encoded losslessly in parallel and wrapped in an
+    /// extended container with ANIM and ANMF chunks.
+    fn webp(&self) -> Result<Vec<u8>, String> {
+        let (width, height) = self.dimensions()?;
+        let frames = self
+            .frames
+            .par_iter()
+            .map(|frame| {
+                let mut buf = Vec::new();
+                let (w, h) = frame.buffer().dimensions();
+                WebPEncoder::new_lossless(&mut buf)
+                    .encode(frame.buffer(), w, h, ExtendedColorType::Rgba8)
+                    .map_err(|e| format!("failed to encode webp frame: {e}"))?;
+                let bitstream = chunks(&buf[12..])
+                    .find(|(id, _)| id == b"VP8L")
+                    .map(|(_, data)| data.to_vec())
+                    .ok_or("encoded webp frame has no VP8L chunk")?;
+                Ok((frame, bitstream))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let mut body = b"WEBP".to_vec();
+        // Animation and alpha flags, then the canvas size
+        let mut vp8x = vec![0x12, 0, 0, 0];
+        vp8x.extend_from_slice(&u24(width - 1));
+        vp8x.extend_from_slice(&u24(height - 1));
+        chunk(&mut body, b"VP8X", &vp8x);
+
+        // Transparent background color, then the loop count
+        let mut anim = vec![0; 4];
+        anim.extend_from_slice(&(self.plays().min(u16::MAX as u32) as u16).to_le_bytes());
+        chunk(&mut body, b"ANIM", &anim);
+
+        for (frame, bitstream) in frames {
+            let (w, h) = frame.buffer().dimensions();
+            let ms = delay_ms(frame).round().clamp(0., 0xff_ffff as f64) as u32;
+            let mut anmf = Vec::new();
+            anmf.extend_from_slice(&u24(frame.left() / 2));
+            anmf.extend_from_slice(&u24(frame.top() / 2));
+            anmf.extend_from_slice(&u24(w - 1));
+            anmf.extend_from_slice(&u24(h - 1));
+            anmf.extend_from_slice(&u24(ms));
+            // Frames are already composited, so they replace the canvas without blending
+            anmf.push(0b10);
+            chunk(&mut anmf, b"VP8L", &bitstream);
+            chunk(&mut body, b"ANMF", &anmf);
+        }
+
+        let mut buf = b"RIFF".to_vec();
+        buf.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&body);
+        Ok(buf)
+    }
+
+    /// Canvas size, from the first frame.
+    fn dimensions(&self) -> Result<(u32, u32), String> {
+        self.frames
+            .first()
+            .map(|f| f.buffer().dimensions())
+            .ok_or_else(|| "animation has no frames".to_string())
+    }
+
+    /// Number of times to play the animation, where 0 is forever.
+    fn plays(&self) -> u32 {
+        match self.loop_count {
+            LoopCount::Infinite => 0,
+            LoopCount::Finite(n) => n.get(),
+        }
+    }
+}
+
+/// Count the frames of an animated gif, png, or webp from the container, without decoding any
+/// image data. Returns `None` for other formats or unreadable files.
+pub fn frame_count(path: &Path, format: ImageFormat) -> Option<u64> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    match format {
+        ImageFormat::Gif => gif_frames(&mut reader).ok(),
+        ImageFormat::Png => {
+            let info = png::Decoder::new(reader).read_info().ok()?;
+            Some(
+                info.info()
+                    .animation_control
+                    .map_or(1, |a| a.num_frames as u64),
+            )
+        },
+        ImageFormat::WebP => {
+            let mut header = [0; 12];
+            reader.read_exact(&mut header).ok()?;
+            let mut frames = 0;
+            let mut chunk = [0; 8];
+            while reader.read_exact(&mut chunk).is_ok() {
+                if &chunk[..4] == b"ANMF" {
+                    frames += 1;
+                }
+                let len = u32::from_le_bytes(chunk[4..].try_into().unwrap()) as i64;
+                reader.seek_relative(len + len % 2).ok()?;
+            }
+            Some(frames.max(1))
+        },
+        _ => None,
+    }
+}
+
+/// Walk the blocks of a gif, counting image descriptors.
+fn gif_frames(reader: &mut impl BufRead) -> std::io::Result<u64> {
+    // Header and logical screen descriptor
+    let mut header = [0; 13];
+    reader.read_exact(&mut header)?;
+    skip_color_table(reader, header[10])?;
+
+    let mut frames = 0;
+    loop {
+        match read_byte(reader)? {
+            // Extension label, then its data
+            0x21 => {
+                read_byte(reader)?;
+                skip_sub_blocks(reader)?;
+            },
+            // Image descriptor, local color table, lzw code size, then the image data
+            0x2c => {
+                let mut descriptor = [0; 9];
+                reader.read_exact(&mut descriptor)?;
+                skip_color_table(reader, descriptor[8])?;
+                read_byte(reader)?;
+                skip_sub_blocks(reader)?;
+                frames += 1;
+            },
+            // Trailer
+            _ => return Ok(frames),
+        }
+    }
+}
+
+fn read_byte(reader: &mut impl Read) -> std::io::Result<u8> {
+    let mut buf = [0];
+    reader.read_exact(&mut buf).map(|_| buf[0])
+}
+
+fn skip(reader: &mut impl Read, len: u64) -> std::io::Result<()> {
+    std::io::copy(&mut reader.take(len), &mut std::io::sink()).map(|_| ())
+}
+
+/// Skip a gif color table, if the packed fields say one is present.
+fn skip_color_table(reader: &mut impl Read, packed: u8) -> std::io::Result<()> {
+    if packed & 0x80 != 0 {
+        skip(reader, 3 << ((packed & 7) + 1))?;
+    }
+    Ok(())
+}
+
+/// Skip gif data sub-blocks, up to and including the terminator.
+fn skip_sub_blocks(reader: &mut impl Read) -> std::io::Result<()> {
+    loop {
+        match read_byte(reader)? {
+            0 => return Ok(()),
+            len => skip(reader, len as u64)?,
+        }
+    }
+}
+
+fn delay_ms(frame: &Frame) -> f64 {
+    let (numer, denom) = frame.delay().numer_denom_ms();
+    numer as f64 / denom.max(1) as f64
+}
+
+fn u24(value: u32) -> [u8; 3] {
+    let [a, b, c, _] = value.to_le_bytes();
+    [a, b, c]
+}
+
+/// Append a RIFF chunk, padded to an even length.
+fn chunk(buf: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    buf.extend_from_slice(id);
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        buf.push(0);
+    }
+}
+
+/// Iterate over RIFF chunks as ids and data.
+fn chunks(mut buf: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let id: [u8; 4] = buf.get(..4)?.try_into().ok()?;
+        let len = u32::from_le_bytes(buf.get(4..8)?.try_into().ok()?) as usize;
+        let data = buf.get(8..8 + len)?;
+        buf = buf.get(8 + len + len % 2..).unwrap_or_default();
+        Some((id, data))
+    })
+}
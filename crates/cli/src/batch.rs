@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+
+use crate::animation;
+use crate::event::Event;
+
+/// Shared memory budget for images being processed concurrently.
+///
+/// Jobs block until enough of the budget is available. A job larger than the entire budget is
+/// still allowed to run, but only once nothing else is in flight.
+pub struct MemoryBudget {
+    total: u64,
+    used: Mutex<u64>,
+    freed: Condvar,
+}
+
+impl MemoryBudget {
+    pub fn new(total: u64) -> Self {
+        Self {
+            total,
+            used: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Reserve some bytes from the budget, blocking until they are available.
+    /// The reservation is released when the returned guard is dropped.
+    pub fn reserve(&self, bytes: u64) -> Reservation<'_> {
+        let bytes = bytes.min(self.total);
+        let mut used = self.used.lock().unwrap();
+        while *used > 0 && *used + bytes > self.total {
+            used = self.freed.wait(used).unwrap();
+        }
+        *used += bytes;
+        Reservation {
+            budget: self,
+            bytes,
+        }
+    }
+
+    /// Estimate the memory needed to process an image, by reading its dimensions from the header.
+    /// Animations keep every frame in memory, so their frames are counted from the container.
+    pub fn estimate(path: &Path) -> u64 {
+        let Ok(reader) = image::ImageReader::open(path).and_then(|r| r.with_guessed_format())
+        else {
+            return 0;
+        };
+        let format = reader.format();
+        let Ok((width, height)) = reader.into_dimensions() else {
+            return 0;
+        };
+        let frames = format
+            .and_then(|format| animation::frame_count(path, format))
+            .unwrap_or(1);
+        // decoded rgba frames, plus a copy for encoding
+        width as u64 * height as u64 * 4 * (frames + 1)
+    }
+}
+
+pub struct Reservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        *self.budget.used.lock().unwrap() -= self.bytes;
+        self.budget.freed.notify_all();
+    }
+}
+
+/// Buffers events from concurrent jobs, emitting them in job order as soon as all previous jobs
+/// have finished.
+#[derive(Default)]
+pub struct OrderedLog {
+    state: Mutex<(usize, BTreeMap<usize, Vec<Event>>)>,
+}
+
+impl OrderedLog {
+    /// Submit the events for a finished job, and emit any events that are ready.
+    pub fn finish(&self, index: usize, events: Vec<Event>) {
+        let mut state = self.state.lock().unwrap();
+        let (next, pending) = &mut *state;
+        pending.insert(index, events);
+
+        while let Some(events) = pending.remove(next) {
+            events.into_iter().for_each(Event::emit);
+            *next += 1;
+        }
+    }
+}
@@ -2,6 +2,7 @@ use std::fmt::{Debug, Display};
 use std::str::FromStr;
 
 use bpaf::{positional, Parser};
+use oklab::{srgb_to_oklab, Oklab};
 
 /// Utility for easily parsing from bpaf
 #[derive(Clone, Hash)]
@@ -67,4 +68,17 @@ impl Color {
             .parse(|s| Color::from_str(&s))
             .many()
     }
+
+    /// Color code printed on a 24-bit ansi background of the color, with a foreground chosen
+    /// based on luminocity.
+    pub fn swatch(&self) -> String {
+        let [r, g, b] = self.0;
+        let Oklab { l, .. } = srgb_to_oklab(self.0.into());
+        let fg = if l < 0.5 {
+            "\x1b[38;2;255;255;255m"
+        } else {
+            "\x1b[38;2;0;0;0m"
+        };
+        format!("\x1b[48;2;{r};{g};{b}m{fg}{self}\x1b[0m")
+    }
 }
@@ -0,0 +1,306 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use bpaf::{construct, short, Parser};
+use serde_json::{json, Value};
+
+/// Amount of status output to print.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    #[default]
+    Normal,
+    Verbose,
+}
+
+pub fn verbosity() -> impl Parser<Verbosity> {
+    let quiet = short('q')
+        .long("quiet")
+        .help("Only print errors.")
+        .req_flag(Verbosity::Quiet);
+    let verbose = short('v')
+        .long("verbose")
+        .help("Print additional details.")
+        .req_flag(Verbosity::Verbose);
+    construct!([quiet, verbose]).fallback(Verbosity::Normal)
+}
+
+struct Config {
+    json: bool,
+    verbosity: Verbosity,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+
+/// Configure how events are printed. Must be called before emitting any events.
+pub fn init(json: bool, verbosity: Verbosity) {
+    CONFIG.set(Config { json, verbosity }).ok();
+}
+
+/// Check if events are emitted as json.
+pub fn is_json() -> bool {
+    CONFIG.get().is_some_and(|c| c.json)
+}
+
+/// Mark stdout as used for output data, so json events are written to stderr instead.
+pub fn reserve_stdout() {
+    STDOUT_RESERVED.store(true, Ordering::Relaxed);
+}
+
+/// Status event emitted by commands.
+///
+/// Printed as a human readable status line on stderr, or as a single line json object when
+/// `--json` is used.
+#[derive(Clone, Debug)]
+pub enum Event {
+    Found {
+        count: usize,
+    },
+    Loaded {
+        path: PathBuf,
+        elapsed: Duration,
+    },
+    Generated {
+        name: String,
+        elapsed: Duration,
+    },
+    Cached {
+        name: String,
+        path: PathBuf,
+        elapsed: Duration,
+    },
+    Extracted {
+        colors: usize,
+        elapsed: Duration,
+    },
+    Applied {
+        path: PathBuf,
+        elapsed: Duration,
+    },
+    Encoded {
+        frames: usize,
+        elapsed: Duration,
+    },
+    Saved {
+        path: PathBuf,
+        elapsed: Duration,
+    },
+    UpToDate {
+        path: PathBuf,
+    },
+    Skipped {
+        count: usize,
+    },
+    Failed {
+        path: PathBuf,
+        error: String,
+    },
+    Patched {
+        path: PathBuf,
+        replacements: u32,
+        elapsed: Duration,
+    },
+    Wrote {
+        path: PathBuf,
+    },
+    Reverted {
+        path: PathBuf,
+        replacements: usize,
+    },
+    Diff {
+        path: PathBuf,
+        diff: String,
+        elapsed: Duration,
+    },
+    Palette {
+        name: String,
+        colors: Option<Vec<String>>,
+    },
+    Mapping {
+        from: String,
+        to: String,
+        count: usize,
+    },
+    Analysis {
+        name: String,
+        report: Value,
+    },
+    Warning(String),
+    Error(String),
+    Finished {
+        action: String,
+        elapsed: Duration,
+    },
+}
+
+impl Event {
+    /// Minimum verbosity needed to print the event.
+    fn verbosity(&self) -> Verbosity {
+        match self {
+            Event::Failed { .. }
+            | Event::Error(_)
+            | Event::Palette { .. }
+            | Event::Mapping { .. }
+            | Event::Analysis { .. } => Verbosity::Quiet,
+            Event::Found { .. } | Event::UpToDate { .. } => Verbosity::Verbose,
+            _ => Verbosity::Normal,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Event::Found { .. } => "found",
+            Event::Loaded { .. } => "loaded",
+            Event::Generated { .. } => "generated",
+            Event::Cached { .. } => "cached",
+            Event::Extracted { .. } => "extracted",
+            Event::Applied { .. } => "applied",
+            Event::Encoded { .. } => "encoded",
+            Event::Saved { .. } => "saved",
+            Event::UpToDate { .. } => "up_to_date",
+            Event::Skipped { .. } => "skipped",
+            Event::Failed { .. } => "failed",
+            Event::Patched { .. } => "patched",
+            Event::Wrote { .. } => "wrote",
+            Event::Reverted { .. } => "reverted",
+            Event::Diff { .. } => "diff",
+            Event::Palette { .. } => "palette",
+            Event::Mapping { .. } => "mapping",
+            Event::Analysis { .. } => "analysis",
+            Event::Warning(_) => "warning",
+            Event::Error(_) => "error",
+            Event::Finished { .. } => "finished",
+        }
+    }
+
+    /// Human readable status line. Palettes, mappings, analyses, and diffs are data rather than
+    /// status, and are printed to stdout by the commands themselves.
+    fn text(&self) -> Option<String> {
+        Some(match self {
+            Event::Found { count } => format!("✔ Found {count} input file(s)"),
+            Event::Loaded { path, elapsed } => format!("✔ Loaded {path:?} in {elapsed:.2?}"),
+            Event::Generated { name, elapsed } => {
+                format!("✔ Generated \"{name}\" LUT in {elapsed:.2?}")
+            },
+            Event::Cached { name, elapsed, .. } => {
+                format!("✔ Cached \"{name}\" LUT in {elapsed:.2?}")
+            },
+            Event::Extracted { colors, elapsed } => {
+                format!("✔ Extracted {colors} colors in {elapsed:.2?}")
+            },
+            Event::Applied { path, elapsed } => {
+                format!("✔ Applied LUT to {path:?} in {elapsed:.2?}")
+            },
+            Event::Encoded { frames, elapsed } => {
+                format!("✔ Encoded {frames} frames in {elapsed:.2?}")
+            },
+            Event::Saved { path, elapsed } => {
+                format!("✔ Saved output to {path:?} in {elapsed:.2?}")
+            },
+            Event::UpToDate { path } => format!("✔ Skipped {path:?}, output is up to date"),
+            Event::Skipped { count } => format!("✔ Skipped {count} up to date output(s)"),
+            Event::Failed { path, error } => format!("✘ Failed to apply LUT to {path:?}: {error}"),
+            Event::Patched {
+                path,
+                replacements,
+                elapsed,
+            } => format!("✔ Replaced {replacements} colors in {path:?} in {elapsed:?}"),
+            Event::Wrote { path } => format!("✔ Wrote changes to {path:?}"),
+            Event::Reverted { path, replacements } => {
+                format!("✔ Reverted {replacements} colors in {path:?}")
+            },
+            Event::Diff { elapsed, .. } => format!("✔ Computed diff in {elapsed:.2?}"),
+            Event::Warning(message) => format!("warning: {message}"),
+            Event::Error(message) => format!("error: {message}"),
+            Event::Finished { action, elapsed } => format!("\nFinished {action}in {elapsed:.2?}"),
+            Event::Palette { .. } | Event::Mapping { .. } | Event::Analysis { .. } => return None,
+        })
+    }
+
+    /// Json object for the event, tagged with an `event` field.
+    pub fn json(&self) -> Value {
+        let ms = |elapsed: &Duration| elapsed.as_secs_f64() * 1000.;
+        let mut value = match self {
+            Event::Found { count } | Event::Skipped { count } => json!({ "count": count }),
+            Event::Loaded { path, elapsed }
+            | Event::Applied { path, elapsed }
+            | Event::Saved { path, elapsed } => {
+                json!({ "path": path.to_string_lossy(), "elapsed_ms": ms(elapsed) })
+            },
+            Event::Generated { name, elapsed } => {
+                json!({ "name": name, "elapsed_ms": ms(elapsed) })
+            },
+            Event::Cached {
+                name,
+                path,
+                elapsed,
+            } => json!({ "name": name, "path": path.to_string_lossy(), "elapsed_ms": ms(elapsed) }),
+            Event::Extracted { colors, elapsed } => {
+                json!({ "colors": colors, "elapsed_ms": ms(elapsed) })
+            },
+            Event::Encoded { frames, elapsed } => {
+                json!({ "frames": frames, "elapsed_ms": ms(elapsed) })
+            },
+            Event::UpToDate { path } | Event::Wrote { path } => {
+                json!({ "path": path.to_string_lossy() })
+            },
+            Event::Failed { path, error } => {
+                json!({ "path": path.to_string_lossy(), "error": error })
+            },
+            Event::Patched {
+                path,
+                replacements,
+                elapsed,
+            } => json!({
+                "path": path.to_string_lossy(),
+                "replacements": replacements,
+                "elapsed_ms": ms(elapsed),
+            }),
+            Event::Reverted { path, replacements } => {
+                json!({ "path": path.to_string_lossy(), "replacements": replacements })
+            },
+            Event::Diff {
+                path,
+                diff,
+                elapsed,
+            } => json!({ "path": path.to_string_lossy(), "diff": diff, "elapsed_ms": ms(elapsed) }),
+            Event::Palette { name, colors } => json!({ "name": name, "colors": colors }),
+            Event::Mapping { from, to, count } => json!({ "from": from, "to": to, "count": count }),
+            Event::Analysis { name, report } => json!({ "name": name, "analysis": report }),
+            Event::Warning(message) | Event::Error(message) => json!({ "message": message }),
+            Event::Finished { action, elapsed } => {
+                json!({ "action": action.trim(), "elapsed_ms": ms(elapsed) })
+            },
+        };
+        value["event"] = self.name().into();
+        value
+    }
+
+    /// Print the event, if allowed by the configured verbosity.
+    pub fn emit(self) {
+        let (json, verbosity) = CONFIG
+            .get()
+            .map(|c| (c.json, c.verbosity))
+            .unwrap_or_default();
+        if self.verbosity() > verbosity {
+            return;
+        }
+
+        if json {
+            let line = format!("{}\n", self.json());
+            if STDOUT_RESERVED.load(Ordering::Relaxed) {
+                std::io::stderr().lock().write_all(line.as_bytes()).ok();
+            } else {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(line.as_bytes()).ok();
+                stdout.flush().ok();
+            }
+        } else if let Some(text) = self.text() {
+            eprintln!("{text}");
+        }
+    }
+}
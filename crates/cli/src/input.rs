@@ -0,0 +1,206 @@
+use std::hash::Hash;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use image::{ImageFormat, ImageReader};
+use regex::Regex;
+
+/// Check if a path is `-`, used to read from stdin or write to stdout.
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Case-insensitive glob pattern, used to filter files found in input directories.
+///
+/// Supports `*`, `**`, `?`, `[abc]`, and alternations written as `{a,b}` or `(a|b)`.
+/// Patterns without a `/` are matched against the file name, otherwise against the path
+/// relative to the input directory.
+#[derive(Clone, Debug)]
+pub struct Glob {
+    pattern: String,
+    regex: Regex,
+}
+impl Hash for Glob {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.pattern.hash(state);
+    }
+}
+impl FromStr for Glob {
+    type Err = String;
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let mut re = String::from("(?i)^");
+        let mut chars = pattern.chars().peekable();
+        let mut depth = 0;
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        // `**/` matches zero or more directories
+                        chars.next();
+                        re.push_str("(?:.*/)?");
+                    } else {
+                        re.push_str(".*");
+                    }
+                },
+                '*' => re.push_str("[^/]*"),
+                '?' => re.push_str("[^/]"),
+                '{' | '(' => {
+                    depth += 1;
+                    re.push_str("(?:");
+                },
+                '}' | ')' if depth > 0 => {
+                    depth -= 1;
+                    re.push(')');
+                },
+                ',' | '|' if depth > 0 => re.push('|'),
+                '[' => {
+                    re.push('[');
+                    if chars.peek() == Some(&'!') {
+                        chars.next();
+                        re.push('^');
+                    }
+                    for c in chars.by_ref() {
+                        if c == ']' {
+                            break;
+                        }
+                        if c == '\\' || c == '[' {
+                            re.push('\\');
+                        }
+                        re.push(c);
+                    }
+                    re.push(']');
+                },
+                c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+        }
+        re.push('$');
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex: Regex::new(&re).map_err(|e| format!("invalid glob `{pattern}`: {e}"))?,
+        })
+    }
+}
+impl Glob {
+    /// Check if a path, relative to the directory it was found in, matches the glob.
+    pub fn is_match(&self, relative: &Path) -> bool {
+        let path = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if self.pattern.contains('/') {
+            self.regex.is_match(&path)
+        } else {
+            self.regex
+                .is_match(path.rsplit('/').next().unwrap_or_default())
+        }
+    }
+}
+
+/// A file to process, either given directly or found while walking an input directory.
+#[derive(Clone, Debug)]
+pub struct InputFile {
+    pub path: PathBuf,
+    /// Path relative to the input directory the file was found in
+    pub relative: Option<PathBuf>,
+}
+
+/// Expand input paths into a flat list of files.
+///
+/// Files are always included, and directories are walked recursively, keeping files matching at
+/// least one `include` glob (or all when empty) and none of the `exclude` globs.
+pub fn collect_inputs(
+    inputs: Vec<PathBuf>,
+    include: &[Glob],
+    exclude: &[Glob],
+) -> Result<Vec<InputFile>, String> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            walk(&input, &input, include, exclude, &mut files)?;
+        } else {
+            files.push(InputFile {
+                path: input,
+                relative: None,
+            });
+        }
+    }
+    Ok(files)
+}
+
+fn walk(
+    root: &Path,
+    dir: &Path,
+    include: &[Glob],
+    exclude: &[Glob],
+    files: &mut Vec<InputFile>,
+) -> Result<(), String> {
+    let mut entries = std::fs::read_dir(dir)
+        .map_err(|e| format!("failed to read directory {dir:?}: {e}"))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("failed to read directory {dir:?}: {e}"))?;
+    entries.sort();
+
+    for path in entries {
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+        if exclude.iter().any(|glob| glob.is_match(&relative)) {
+            continue;
+        }
+
+        // Don't follow symlinked directories to avoid cycles
+        let is_symlink = path.symlink_metadata().is_ok_and(|m| m.is_symlink());
+        if path.is_dir() {
+            if !is_symlink && path.file_name().is_some_and(|name| name != ".git") {
+                walk(root, &path, include, exclude, files)?;
+            }
+        } else if include.is_empty() || include.iter().any(|glob| glob.is_match(&relative)) {
+            files.push(InputFile {
+                path,
+                relative: Some(relative),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Check if an output exists and was modified after the input.
+pub fn is_up_to_date(input: &Path, output: &Path) -> bool {
+    if is_stdio(input) || is_stdio(output) {
+        return false;
+    }
+    let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+    match (modified(input), modified(output)) {
+        (Some(input), Some(output)) => output >= input,
+        _ => false,
+    }
+}
+
+/// Open an image from a file, or stdin when the path is `-`.
+///
+/// The format is detected from the magic bytes, falling back to the file extension for formats
+/// without a signature.
+pub fn read_image(path: &Path) -> Result<ImageReader<Cursor<Vec<u8>>>, String> {
+    let bytes = if is_stdio(path) {
+        let mut bytes = Vec::new();
+        std::io::stdin()
+            .lock()
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("failed to read stdin: {e}"))?;
+        bytes
+    } else {
+        std::fs::read(path).map_err(|e| format!("failed to open image: {e}"))?
+    };
+
+    let mut reader = ImageReader::new(Cursor::new(bytes));
+    if let Ok(format) = ImageFormat::from_path(path) {
+        reader.set_format(format);
+    }
+    reader
+        .with_guessed_format()
+        .map_err(|e| format!("failed to guess image format: {e}"))
+}
@@ -0,0 +1,906 @@
+//! Tokenizer for color literals in text, used when patching files.
+//!
+//! Each supported syntax is a [`Notation`], which finds literals and knows how to rewrite them with
+//! a new color, in the same notation and precision, keeping any alpha value untouched.
+
+use std::cmp::Reverse;
+use std::ops::Range;
+
+use oklab::{oklab_to_srgb, srgb_to_oklab, Oklab};
+use regex::Regex;
+
+/// A color literal found in some text.
+pub struct Literal {
+    /// Byte range of the literal in the text
+    pub range: Range<usize>,
+    /// Color of the literal, without alpha
+    pub color: [u8; 3],
+    rewrite: Box<dyn Fn([u8; 3]) -> String>,
+}
+
+impl Literal {
+    /// Rewrite the literal with a new color, in its original notation and precision.
+    pub fn rewrite(&self, color: [u8; 3]) -> String {
+        (self.rewrite)(color)
+    }
+}
+
+/// A color literal syntax that can be found and rewritten in text.
+pub trait Notation {
+    /// Short name used to select the notation from the command line
+    fn name(&self) -> &'static str;
+    /// Find all literals of this notation in some text
+    fn find(&self, text: &str) -> Vec<Literal>;
+}
+
+/// Set of notations used to find color literals in text.
+pub struct Tokenizer {
+    notations: Vec<Box<dyn Notation>>,
+    /// Notations only matched when they make up an entire value in a structured config
+    values: Vec<Box<dyn Notation>>,
+}
+
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Self::new(vec![
+            Box::new(Hex::default()),
+            Box::new(HexNumber::default()),
+            Box::new(Function::rgb()),
+            Box::new(Function::hsl()),
+            Box::new(Function::oklab()),
+            Box::new(Function::oklch()),
+        ])
+    }
+}
+
+impl Tokenizer {
+    pub fn new(notations: Vec<Box<dyn Notation>>) -> Self {
+        Self {
+            notations,
+            values: vec![Box::new(BareHex::default()), Box::new(Named::default())],
+        }
+    }
+
+    /// Notations which match ordinary words, and are only used anywhere in the text when enabled
+    fn optional() -> Vec<Box<dyn Notation>> {
+        vec![Box::new(Named::default())]
+    }
+
+    /// Error for an unknown notation name, listing every supported notation
+    fn unknown(name: &str) -> String {
+        let tokenizer = Self::default();
+        let names = tokenizer
+            .notations
+            .iter()
+            .chain(&tokenizer.values)
+            .map(|n| n.name())
+            .collect::<Vec<_>>();
+        format!(
+            "unknown color notation `{name}`, expected one of: {}",
+            names.join(", ")
+        )
+    }
+
+    /// Match optional notations anywhere in the text, by name
+    pub fn with(mut self, names: &[String]) -> Result<Self, String> {
+        let mut optional = Self::optional();
+        for name in names {
+            match optional.iter().position(|n| n.name() == name) {
+                Some(i) => self.notations.push(optional.swap_remove(i)),
+                None if self.notations.iter().any(|n| n.name() == name) => {},
+                None => {
+                    return Err(format!(
+                        "`{name}` is not an optional color notation, expected one of: {}",
+                        Self::optional()
+                            .iter()
+                            .map(|n| n.name())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ))
+                },
+            }
+        }
+        Ok(self)
+    }
+
+    /// Remove notations from the tokenizer by name
+    pub fn without(mut self, names: &[String]) -> Result<Self, String> {
+        for name in names {
+            let len = self.notations.len() + self.values.len();
+            self.notations.retain(|n| n.name() != name);
+            self.values.retain(|n| n.name() != name);
+            if self.notations.len() + self.values.len() == len {
+                return Err(Self::unknown(name));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Find a literal making up an entire value in a structured config, using the notations
+    /// which are otherwise ambiguous, like bare hex and named colors.
+    pub fn tokenize_value(&self, value: &str) -> Option<Literal> {
+        self.values
+            .iter()
+            .flat_map(|n| n.find(value))
+            .find(|l| l.range == (0..value.len()))
+    }
+
+    /// Find all non-overlapping color literals in some text, ordered by position.
+    /// When literals overlap, the one starting first (or the longest) is kept.
+    pub fn tokenize(&self, text: &str) -> Vec<Literal> {
+        let mut literals = self
+            .notations
+            .iter()
+            .flat_map(|n| n.find(text))
+            .collect::<Vec<_>>();
+        literals.sort_by_key(|l| (l.range.start, Reverse(l.range.end)));
+
+        let mut end = 0;
+        literals.retain(|l| {
+            let keep = l.range.start >= end;
+            if keep {
+                end = l.range.end;
+            }
+            keep
+        });
+        literals
+    }
+}
+
+/// A rewritten color literal.
+#[derive(Clone, Debug)]
+pub struct Replacement {
+    /// Byte range of the literal in the original text
+    pub range: Range<usize>,
+    /// Byte offset of the rewritten literal in the new text
+    pub offset: usize,
+    pub original: String,
+    pub replaced: String,
+    /// Original color, without alpha
+    pub from: [u8; 3],
+    /// New color, without alpha
+    pub to: [u8; 3],
+}
+
+/// Replace literals in some text with their rewritten colors.
+pub fn rewrite(
+    text: &str,
+    literals: &[Literal],
+    mut map: impl FnMut(&Literal) -> [u8; 3],
+) -> (String, Vec<Replacement>) {
+    let mut buf = String::with_capacity(text.len());
+    let mut replacements = Vec::with_capacity(literals.len());
+    let mut last = 0;
+    for literal in literals {
+        buf.push_str(&text[last..literal.range.start]);
+        let to = map(literal);
+        let replaced = literal.rewrite(to);
+        replacements.push(Replacement {
+            range: literal.range.clone(),
+            offset: buf.len(),
+            original: text[literal.range.clone()].to_string(),
+            replaced: replaced.clone(),
+            from: literal.color,
+            to,
+        });
+        buf.push_str(&replaced);
+        last = literal.range.end;
+    }
+    buf.push_str(&text[last..]);
+    (buf, replacements)
+}
+
+/// Check if hex digits are written in uppercase
+fn is_uppercase(digits: &str) -> bool {
+    digits.chars().any(|c| c.is_ascii_uppercase())
+        && !digits.chars().any(|c| c.is_ascii_lowercase())
+}
+
+/// Css hex colors: `#rgb`, `#rgba`, `#rrggbb`, and `#rrggbbaa`
+pub struct Hex(Regex);
+
+impl Default for Hex {
+    fn default() -> Self {
+        Self(
+            Regex::new(r"#([0-9a-fA-F]{8}|[0-9a-fA-F]{6}|[0-9a-fA-F]{4}|[0-9a-fA-F]{3})\b")
+                .expect("valid regex"),
+        )
+    }
+}
+
+impl Notation for Hex {
+    fn name(&self) -> &'static str {
+        "hex"
+    }
+
+    fn find(&self, text: &str) -> Vec<Literal> {
+        self.0
+            .captures_iter(text)
+            .map(|caps| {
+                let digits = caps.get(1).unwrap().as_str();
+                let short = digits.len() < 6;
+                let channel = |i: usize| match short {
+                    true => u8::from_str_radix(&digits[i..i + 1], 16).unwrap() * 17,
+                    false => u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).unwrap(),
+                };
+                let alpha = match digits.len() {
+                    4 => Some(digits[3..].to_string()),
+                    8 => Some(digits[6..].to_string()),
+                    _ => None,
+                };
+                let uppercase = is_uppercase(digits);
+
+                Literal {
+                    range: caps.get(0).unwrap().range(),
+                    color: [channel(0), channel(1), channel(2)],
+                    rewrite: Box::new(move |[r, g, b]| {
+                        // Keep the short form when the new color can be represented by it
+                        let keep_short = short && [r, g, b].iter().all(|c| c % 17 == 0);
+                        let mut hex = if keep_short {
+                            format!("#{:x}{:x}{:x}", r / 17, g / 17, b / 17)
+                        } else {
+                            format!("#{r:02x}{g:02x}{b:02x}")
+                        };
+                        if uppercase {
+                            hex.make_ascii_uppercase();
+                        }
+                        if let Some(alpha) = &alpha {
+                            hex.push_str(alpha);
+                            if short && !keep_short {
+                                hex.push_str(alpha);
+                            }
+                        }
+                        hex
+                    }),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Hex colors without a `#`: `rrggbb` and `rrggbbaa`.
+///
+/// Bare hex can't be told apart from other text, so this only matches when the entire text is a
+/// color, and is only used for values in structured configs.
+pub struct BareHex(Regex);
+
+impl Default for BareHex {
+    fn default() -> Self {
+        Self(Regex::new(r"^(?:[0-9a-fA-F]{8}|[0-9a-fA-F]{6})$").expect("valid regex"))
+    }
+}
+
+impl Notation for BareHex {
+    fn name(&self) -> &'static str {
+        "bare-hex"
+    }
+
+    fn find(&self, text: &str) -> Vec<Literal> {
+        self.0
+            .find(text)
+            .map(|m| {
+                let digits = m.as_str();
+                let alpha = digits[6..].to_string();
+                let uppercase = is_uppercase(digits);
+                let channel = |i: usize| u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).unwrap();
+
+                Literal {
+                    range: m.range(),
+                    color: [channel(0), channel(1), channel(2)],
+                    rewrite: Box::new(move |[r, g, b]| {
+                        let mut hex = format!("{r:02x}{g:02x}{b:02x}");
+                        if uppercase {
+                            hex.make_ascii_uppercase();
+                        }
+                        hex.push_str(&alpha);
+                        hex
+                    }),
+                }
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Hex numbers, as used by some terminals and window managers: `0xRRGGBB`
+pub struct HexNumber(Regex);
+
+impl Default for HexNumber {
+    fn default() -> Self {
+        Self(Regex::new(r"\b0[xX]([0-9a-fA-F]{6})\b").expect("valid regex"))
+    }
+}
+
+impl Notation for HexNumber {
+    fn name(&self) -> &'static str {
+        "0x"
+    }
+
+    fn find(&self, text: &str) -> Vec<Literal> {
+        self.0
+            .captures_iter(text)
+            .map(|caps| {
+                let literal = caps.get(0).unwrap();
+                let digits = caps.get(1).unwrap().as_str();
+                let prefix = literal.as_str()[..2].to_string();
+                let uppercase = is_uppercase(digits);
+                let channel = |i: usize| u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).unwrap();
+
+                Literal {
+                    range: literal.range(),
+                    color: [channel(0), channel(1), channel(2)],
+                    rewrite: Box::new(move |[r, g, b]| match uppercase {
+                        true => format!("{prefix}{r:02X}{g:02X}{b:02X}"),
+                        false => format!("{prefix}{r:02x}{g:02x}{b:02x}"),
+                    }),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Numeric argument to a color function, ie `50%` or `120deg`
+#[derive(Clone, Debug)]
+struct Channel {
+    /// Absolute byte range in the text
+    range: Range<usize>,
+    value: f64,
+    unit: Unit,
+    decimals: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Unit {
+    None,
+    Percent,
+    Deg,
+    Rad,
+    Grad,
+    Turn,
+}
+
+impl Channel {
+    fn parse(token: &str, range: Range<usize>) -> Option<Self> {
+        let split = token
+            .find(|c: char| c.is_ascii_alphabetic() && c != 'e' && c != 'E' || c == '%')
+            .unwrap_or(token.len());
+        let (number, unit) = token.split_at(split);
+        let unit = match unit.to_ascii_lowercase().as_str() {
+            "" => Unit::None,
+            "%" => Unit::Percent,
+            "deg" => Unit::Deg,
+            "rad" => Unit::Rad,
+            "grad" => Unit::Grad,
+            "turn" => Unit::Turn,
+            _ => return None,
+        };
+        let mantissa = number.split(['e', 'E']).next().unwrap_or_default();
+        Some(Self {
+            // Only the number is rewritten, keeping the unit
+            range: range.start..range.start + number.len(),
+            value: number.parse().ok()?,
+            unit,
+            decimals: mantissa.split_once('.').map(|(_, d)| d.len()).unwrap_or(0),
+        })
+    }
+
+    /// Hue in degrees
+    fn degrees(&self) -> f64 {
+        match self.unit {
+            Unit::Rad => self.value.to_degrees(),
+            Unit::Grad => self.value * 0.9,
+            Unit::Turn => self.value * 360.,
+            _ => self.value,
+        }
+    }
+
+    /// Convert a hue in degrees into the channel's unit
+    fn to_unit(&self, degrees: f64) -> f64 {
+        match self.unit {
+            Unit::Rad => degrees.to_radians(),
+            Unit::Grad => degrees / 0.9,
+            Unit::Turn => degrees / 360.,
+            _ => degrees,
+        }
+    }
+
+    /// Value scaled to `0..=max`, where percentages are relative to `max`.
+    fn scaled(&self, max: f64) -> f64 {
+        match self.unit {
+            Unit::Percent => self.value / 100. * max,
+            _ => self.value,
+        }
+    }
+
+    /// Convert a value in `0..=max` back into the channel's unit
+    fn unscaled(&self, value: f64, max: f64) -> f64 {
+        match self.unit {
+            Unit::Percent => value / max * 100.,
+            _ => value,
+        }
+    }
+
+    /// Format a new value with the same precision, or at least `min` decimals.
+    fn format(&self, value: f64, min: usize) -> String {
+        let decimals = self.decimals.max(min);
+        let mut s = format!("{value:.decimals$}");
+        if s.starts_with('-') && s[1..].chars().all(|c| c == '0' || c == '.') {
+            s.remove(0);
+        }
+        s
+    }
+}
+
+/// Color space of a css color function
+#[derive(Clone, Copy)]
+enum Space {
+    Rgb,
+    Hsl,
+    Oklab,
+    Oklch,
+}
+
+impl Space {
+    fn to_rgb(self, [x, y, z]: &[Channel; 3]) -> [u8; 3] {
+        match self {
+            Space::Rgb => [x, y, z].map(|c| c.scaled(255.).round().clamp(0., 255.) as u8),
+            Space::Hsl => {
+                // Saturation and lightness are percentages, with or without the unit
+                hsl_to_rgb(x.degrees(), y.value / 100., z.value / 100.)
+            },
+            Space::Oklab => {
+                let rgb = oklab_to_srgb(Oklab {
+                    l: x.scaled(1.) as f32,
+                    a: y.scaled(0.4) as f32,
+                    b: z.scaled(0.4) as f32,
+                });
+                [rgb.r, rgb.g, rgb.b]
+            },
+            Space::Oklch => {
+                let (c, h) = (y.scaled(0.4), z.degrees().to_radians());
+                let rgb = oklab_to_srgb(Oklab {
+                    l: x.scaled(1.) as f32,
+                    a: (c * h.cos()) as f32,
+                    b: (c * h.sin()) as f32,
+                });
+                [rgb.r, rgb.g, rgb.b]
+            },
+        }
+    }
+
+    /// Rewrite each channel with a new color, in the channel's unit and precision.
+    fn channels_for(self, rgb: [u8; 3], [x, y, z]: &[Channel; 3]) -> [String; 3] {
+        match self {
+            Space::Rgb => {
+                let [r, g, b] = rgb.map(f64::from);
+                [
+                    x.format(x.unscaled(r, 255.), 0),
+                    y.format(y.unscaled(g, 255.), 0),
+                    z.format(z.unscaled(b, 255.), 0),
+                ]
+            },
+            Space::Hsl => {
+                let (h, s, l) = rgb_to_hsl(rgb);
+                // Keep the original hue for achromatic colors
+                let h = h.map(|h| x.to_unit(h)).unwrap_or(x.value);
+                [
+                    x.format(h, hue_decimals(x.unit)),
+                    y.format(s * 100., 0),
+                    z.format(l * 100., 0),
+                ]
+            },
+            Space::Oklab => {
+                let Oklab { l, a, b } = srgb_to_oklab(rgb.into());
+                [
+                    x.format(x.unscaled(l as f64, 1.), lightness_decimals(x.unit)),
+                    y.format(y.unscaled(a as f64, 0.4), chroma_decimals(y.unit)),
+                    z.format(z.unscaled(b as f64, 0.4), chroma_decimals(z.unit)),
+                ]
+            },
+            Space::Oklch => {
+                let Oklab { l, a, b } = srgb_to_oklab(rgb.into());
+                let c = (a as f64).hypot(b as f64);
+                // Keep the original hue for achromatic colors
+                let h = match c < 1e-4 {
+                    true => z.value,
+                    false => z.to_unit((b as f64).atan2(a as f64).to_degrees().rem_euclid(360.)),
+                };
+                [
+                    x.format(x.unscaled(l as f64, 1.), lightness_decimals(x.unit)),
+                    y.format(y.unscaled(c, 0.4), chroma_decimals(y.unit)),
+                    z.format(h, hue_decimals(z.unit)),
+                ]
+            },
+        }
+    }
+}
+
+/// Minimum decimals for hues, so small units can still express 8-bit colors
+fn hue_decimals(unit: Unit) -> usize {
+    match unit {
+        Unit::Rad => 2,
+        Unit::Turn => 3,
+        _ => 0,
+    }
+}
+
+/// Minimum decimals for oklab lightness
+fn lightness_decimals(unit: Unit) -> usize {
+    match unit {
+        Unit::Percent => 0,
+        _ => 2,
+    }
+}
+
+/// Minimum decimals for oklab chroma and a/b axes
+fn chroma_decimals(unit: Unit) -> usize {
+    match unit {
+        Unit::Percent => 0,
+        _ => 3,
+    }
+}
+
+fn hsl_to_rgb(h: f64, s: f64, l: f64) -> [u8; 3] {
+    let (s, l) = (s.clamp(0., 1.), l.clamp(0., 1.));
+    let c = (1. - (2. * l - 1.).abs()) * s;
+    let h = h.rem_euclid(360.) / 60.;
+    let x = c * (1. - (h % 2. - 1.).abs());
+    let (r, g, b) = match h as u8 {
+        0 => (c, x, 0.),
+        1 => (x, c, 0.),
+        2 => (0., c, x),
+        3 => (0., x, c),
+        4 => (x, 0., c),
+        _ => (c, 0., x),
+    };
+    let m = l - c / 2.;
+    [r, g, b].map(|v| ((v + m) * 255.).round().clamp(0., 255.) as u8)
+}
+
+/// Convert rgb into hue (in degrees, none for achromatic colors), saturation, and lightness.
+fn rgb_to_hsl(rgb: [u8; 3]) -> (Option<f64>, f64, f64) {
+    let [r, g, b] = rgb.map(|c| c as f64 / 255.);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.;
+    let d = max - min;
+    if d == 0. {
+        return (None, 0., l);
+    }
+
+    let s = d / (1. - (2. * l - 1.).abs());
+    let h = if max == r {
+        ((g - b) / d).rem_euclid(6.)
+    } else if max == g {
+        (b - r) / d + 2.
+    } else {
+        (r - g) / d + 4.
+    };
+    (Some(h * 60.), s, l)
+}
+
+/// Css color functions: `rgb()`/`rgba()`, `hsl()`/`hsla()`, `oklab()`, and `oklch()`.
+///
+/// Supports both the legacy comma separated syntax, and the space separated syntax with an
+/// optional `/ alpha`. Only the color channels are rewritten, so separators and alpha are kept.
+pub struct Function {
+    name: &'static str,
+    space: Space,
+    regex: Regex,
+}
+
+impl Function {
+    fn new(name: &'static str, functions: &str, space: Space) -> Self {
+        Self {
+            name,
+            space,
+            regex: Regex::new(&format!(r"(?i)\b(?:{functions})\(([^()]*)\)")).expect("valid regex"),
+        }
+    }
+
+    pub fn rgb() -> Self {
+        Self::new("rgb", "rgba?", Space::Rgb)
+    }
+
+    pub fn hsl() -> Self {
+        Self::new("hsl", "hsla?", Space::Hsl)
+    }
+
+    pub fn oklab() -> Self {
+        Self::new("oklab", "oklab", Space::Oklab)
+    }
+
+    pub fn oklch() -> Self {
+        Self::new("oklch", "oklch", Space::Oklch)
+    }
+
+    /// Parse the color channels from function arguments
+    fn channels(args: &str, offset: usize) -> Option<[Channel; 3]> {
+        let slash = args.find('/');
+        let mut channels = Vec::new();
+        let mut alpha = 0;
+        for (start, token) in args
+            .split(|c: char| c.is_ascii_whitespace() || c == ',' || c == '/')
+            .scan(0, |pos, token| {
+                let start = *pos;
+                *pos += token.len() + 1;
+                Some((start, token))
+            })
+            .filter(|(_, token)| !token.is_empty())
+        {
+            if slash.is_some_and(|slash| start > slash) || channels.len() == 3 {
+                alpha += 1;
+                continue;
+            }
+            channels.push(Channel::parse(
+                token,
+                offset + start..offset + start + token.len(),
+            )?);
+        }
+
+        if alpha > 1 {
+            return None;
+        }
+        channels.try_into().ok()
+    }
+}
+
+impl Notation for Function {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn find(&self, text: &str) -> Vec<Literal> {
+        let space = self.space;
+        self.regex
+            .captures_iter(text)
+            .filter_map(|caps| {
+                let literal = caps.get(0).unwrap();
+                let args = caps.get(1).unwrap();
+                let channels = Self::channels(args.as_str(), args.start())?;
+                let original = literal.as_str().to_string();
+                let start = literal.start();
+
+                Some(Literal {
+                    range: literal.range(),
+                    color: space.to_rgb(&channels),
+                    rewrite: Box::new(move |rgb| {
+                        // Replace the channels in place, keeping everything else as is
+                        let mut buf = String::new();
+                        let mut last = 0;
+                        for (channel, value) in
+                            channels.iter().zip(space.channels_for(rgb, &channels))
+                        {
+                            buf.push_str(&original[last..channel.range.start - start]);
+                            buf.push_str(&value);
+                            last = channel.range.end - start;
+                        }
+                        buf.push_str(&original[last..]);
+                        buf
+                    }),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Css named colors, ie `rebeccapurple`.
+///
+/// Names are common words, so by default they are only matched as entire values in structured
+/// configs, and anywhere in the text when enabled with `--notation named`. Names used as keys
+/// (followed by `=` or `:`) or as part of identifiers are ignored. Colors without an exact named
+/// equivalent are rewritten as hex.
+pub struct Named(Regex);
+
+impl Default for Named {
+    fn default() -> Self {
+        let mut names = NAMED_COLORS
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        // Prefer longer names when matching
+        names.sort_by_key(|name| Reverse(name.len()));
+        Self(Regex::new(&format!(r"(?i)\b(?:{})\b", names.join("|"))).expect("valid regex"))
+    }
+}
+
+impl Notation for Named {
+    fn name(&self) -> &'static str {
+        "named"
+    }
+
+    fn find(&self, text: &str) -> Vec<Literal> {
+        self.0
+            .find_iter(text)
+            .filter(|m| {
+                let before = text[..m.start()].chars().next_back();
+                let after = text[m.end()..]
+                    .trim_start_matches([' ', '\t'])
+                    .chars()
+                    .next();
+                !matches!(before, Some('-' | '.' | '#' | '$' | '@' | '/' | '\\'))
+                    && !matches!(text[m.end()..].chars().next(), Some('-' | '(' | '/'))
+                    && !matches!(after, Some('=' | ':'))
+            })
+            .map(|m| {
+                let name = m.as_str().to_string();
+                let color = NAMED_COLORS
+                    .iter()
+                    .find(|(n, _)| n.eq_ignore_ascii_case(&name))
+                    .map(|(_, c)| *c)
+                    .unwrap();
+                let uppercase = name.chars().all(|c| c.is_ascii_uppercase());
+
+                Literal {
+                    range: m.range(),
+                    color,
+                    rewrite: Box::new(move |rgb| {
+                        if rgb == color {
+                            return name.clone();
+                        }
+                        match NAMED_COLORS.iter().find(|(_, c)| *c == rgb) {
+                            Some((n, _)) if uppercase => n.to_ascii_uppercase(),
+                            Some((n, _)) => n.to_string(),
+                            None => {
+                                let [r, g, b] = rgb;
+                                format!("#{r:02x}{g:02x}{b:02x}")
+                            },
+                        }
+                    }),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Css named colors, from the CSS Color Module Level 4
+const NAMED_COLORS: &[(&str, [u8; 3])] = &[
+    ("aliceblue", [240, 248, 255]),
+    ("antiquewhite", [250, 235, 215]),
+    ("aqua", [0, 255, 255]),
+    ("aquamarine", [127, 255, 212]),
+    ("azure", [240, 255, 255]),
+    ("beige", [245, 245, 220]),
+    ("bisque", [255, 228, 196]),
+    ("black", [0, 0, 0]),
+    ("blanchedalmond", [255, 235, 205]),
+    ("blue", [0, 0, 255]),
+    ("blueviolet", [138, 43, 226]),
+    ("brown", [165, 42, 42]),
+    ("burlywood", [222, 184, 135]),
+    ("cadetblue", [95, 158, 160]),
+    ("chartreuse", [127, 255, 0]),
+    ("chocolate", [210, 105, 30]),
+    ("coral", [255, 127, 80]),
+    ("cornflowerblue", [100, 149, 237]),
+    ("cornsilk", [255, 248, 220]),
+    ("crimson", [220, 20, 60]),
+    ("cyan", [0, 255, 255]),
+    ("darkblue", [0, 0, 139]),
+    ("darkcyan", [0, 139, 139]),
+    ("darkgoldenrod", [184, 134, 11]),
+    ("darkgray", [169, 169, 169]),
+    ("darkgreen", [0, 100, 0]),
+    ("darkgrey", [169, 169, 169]),
+    ("darkkhaki", [189, 183, 107]),
+    ("darkmagenta", [139, 0, 139]),
+    ("darkolivegreen", [85, 107, 47]),
+    ("darkorange", [255, 140, 0]),
+    ("darkorchid", [153, 50, 204]),
+    ("darkred", [139, 0, 0]),
+    ("darksalmon", [233, 150, 122]),
+    ("darkseagreen", [143, 188, 143]),
+    ("darkslateblue", [72, 61, 139]),
+    ("darkslategray", [47, 79, 79]),
+    ("darkslategrey", [47, 79, 79]),
+    ("darkturquoise", [0, 206, 209]),
+    ("darkviolet", [148, 0, 211]),
+    ("deeppink", [255, 20, 147]),
+    ("deepskyblue", [0, 191, 255]),
+    ("dimgray", [105, 105, 105]),
+    ("dimgrey", [105, 105, 105]),
+    ("dodgerblue", [30, 144, 255]),
+    ("firebrick", [178, 34, 34]),
+    ("floralwhite", [255, 250, 240]),
+    ("forestgreen", [34, 139, 34]),
+    ("fuchsia", [255, 0, 255]),
+    ("gainsboro", [220, 220, 220]),
+    ("ghostwhite", [248, 248, 255]),
+    ("gold", [255, 215, 0]),
+    ("goldenrod", [218, 165, 32]),
+    ("gray", [128, 128, 128]),
+    ("green", [0, 128, 0]),
+    ("greenyellow", [173, 255, 47]),
+    ("grey", [128, 128, 128]),
+    ("honeydew", [240, 255, 240]),
+    ("hotpink", [255, 105, 180]),
+    ("indianred", [205, 92, 92]),
+    ("indigo", [75, 0, 130]),
+    ("ivory", [255, 255, 240]),
+    ("khaki", [240, 230, 140]),
+    ("lavender", [230, 230, 250]),
+    ("lavenderblush", [255, 240, 245]),
+    ("lawngreen", [124, 252, 0]),
+    ("lemonchiffon", [255, 250, 205]),
+    ("lightblue", [173, 216, 230]),
+    ("lightcoral", [240, 128, 128]),
+    ("lightcyan", [224, 255, 255]),
+    ("lightgoldenrodyellow", [250, 250, 210]),
+    ("lightgray", [211, 211, 211]),
+    ("lightgreen", [144, 238, 144]),
+    ("lightgrey", [211, 211, 211]),
+    ("lightpink", [255, 182, 193]),
+    ("lightsalmon", [255, 160, 122]),
+    ("lightseagreen", [32, 178, 170]),
+    ("lightskyblue", [135, 206, 250]),
+    ("lightslategray", [119, 136, 153]),
+    ("lightslategrey", [119, 136, 153]),
+    ("lightsteelblue", [176, 196, 222]),
+    ("lightyellow", [255, 255, 224]),
+    ("lime", [0, 255, 0]),
+    ("limegreen", [50, 205, 50]),
+    ("linen", [250, 240, 230]),
+    ("magenta", [255, 0, 255]),
+    ("maroon", [128, 0, 0]),
+    ("mediumaquamarine", [102, 205, 170]),
+    ("mediumblue", [0, 0, 205]),
+    ("mediumorchid", [186, 85, 211]),
+    ("mediumpurple", [147, 112, 219]),
+    ("mediumseagreen", [60, 179, 113]),
+    ("mediumslateblue", [123, 104, 238]),
+    ("mediumspringgreen", [0, 250, 154]),
+    ("mediumturquoise", [72, 209, 204]),
+    ("mediumvioletred", [199, 21, 133]),
+    ("midnightblue", [25, 25, 112]),
+    ("mintcream", [245, 255, 250]),
+    ("mistyrose", [255, 228, 225]),
+    ("moccasin", [255, 228, 181]),
+    ("navajowhite", [255, 222, 173]),
+    ("navy", [0, 0, 128]),
+    ("oldlace", [253, 245, 230]),
+    ("olive", [128, 128, 0]),
+    ("olivedrab", [107, 142, 35]),
+    ("orange", [255, 165, 0]),
+    ("orangered", [255, 69, 0]),
+    ("orchid", [218, 112, 214]),
+    ("palegoldenrod", [238, 232, 170]),
+    ("palegreen", [152, 251, 152]),
+    ("paleturquoise", [175, 238, 238]),
+    ("palevioletred", [219, 112, 147]),
+    ("papayawhip", [255, 239, 213]),
+    ("peachpuff", [255, 218, 185]),
+    ("peru", [205, 133, 63]),
+    ("pink", [255, 192, 203]),
+    ("plum", [221, 160, 221]),
+    ("powderblue", [176, 224, 230]),
+    ("purple", [128, 0, 128]),
+    ("rebeccapurple", [102, 51, 153]),
+    ("red", [255, 0, 0]),
+    ("rosybrown", [188, 143, 143]),
+    ("royalblue", [65, 105, 225]),
+    ("saddlebrown", [139, 69, 19]),
+    ("salmon", [250, 128, 114]),
+    ("sandybrown", [244, 164, 96]),
+    ("seagreen", [46, 139, 87]),
+    ("seashell", [255, 245, 238]),
+    ("sienna", [160, 82, 45]),
+    ("silver", [192, 192, 192]),
+    ("skyblue", [135, 206, 235]),
+    ("slateblue", [106, 90, 205]),
+    ("slategray", [112, 128, 144]),
+    ("slategrey", [112, 128, 144]),
+    ("snow", [255, 250, 250]),
+    ("springgreen", [0, 255, 127]),
+    ("steelblue", [70, 130, 180]),
+    ("tan", [210, 180, 140]),
+    ("teal", [0, 128, 128]),
+    ("thistle", [216, 191, 216]),
+    ("tomato", [255, 99, 71]),
+    ("turquoise", [64, 224, 208]),
+    ("violet", [238, 130, 238]),
+    ("wheat", [245, 222, 179]),
+    ("white", [255, 255, 255]),
+    ("whitesmoke", [245, 245, 245]),
+    ("yellow", [255, 255, 0]),
+    ("yellowgreen", [154, 205, 50]),
+];
@@ -1,20 +1,36 @@
+mod analysis;
+mod animation;
+mod batch;
 mod color;
+mod event;
+mod input;
+mod literal;
+mod manifest;
+mod output;
 mod palette;
+mod preview;
+mod report;
+mod sheet;
+mod structured;
+mod video;
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Debug, Display};
 use std::hash::{DefaultHasher, Hash, Hasher};
-use std::io::{stdout, IsTerminal, Seek, Write};
+use std::io::{stdout, Cursor, ErrorKind, IsTerminal, Seek};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 use std::time::Instant;
 
 use bpaf::doc::Style;
 use bpaf::{construct, long, short, Bpaf, Doc, Parser, ShellComp};
-use image::codecs::gif::{GifDecoder, GifEncoder};
+use image::codecs::gif::GifDecoder;
 use image::codecs::png::PngDecoder;
 use image::codecs::webp::WebPDecoder;
-use image::{AnimationDecoder, DynamicImage, Frame};
+use image::{DynamicImage, ImageFormat};
+use lutgen::extract::{self, ExtractOptions, Quantizer};
 use lutgen::identity::{correct_pixel, detect_level};
 use lutgen::interpolation::{
     GaussianBlurRemapper,
@@ -23,15 +39,35 @@ use lutgen::interpolation::{
     NearestNeighborRemapper,
     ShepardRemapper,
 };
-use lutgen::{GenerateLut, RgbImage, RgbaImage};
-use lutgen_palettes::Palette;
-use oklab::{srgb_to_oklab, Oklab};
-use quantette::{ColorSpace, PalettePipeline, QuantizeMethod};
+use lutgen::theme::{self, Scheme, ThemeOptions};
+use lutgen::{ops, GenerateLut, RgbImage, RgbaImage};
+use lutgen_palettes::export::{self, Format as ExportFormat};
+use lutgen_palettes::{Palette, Variant};
 use rayon::iter::Either;
-use regex::{Captures, Regex};
+use rayon::prelude::*;
 
+use crate::animation::{Animation, FRAME_LIST};
+use crate::batch::{MemoryBudget, OrderedLog};
 use crate::color::Color;
-use crate::palette::DynamicPalette;
+use crate::event::{verbosity, Event, Verbosity};
+use crate::input::{collect_inputs, is_stdio, is_up_to_date, read_image, Glob, InputFile};
+use crate::literal::Tokenizer;
+use crate::manifest::Manifest;
+use crate::output::{
+    create_dir,
+    image_format,
+    output_extension,
+    write_output,
+    OutputFormat,
+    OutputTemplate,
+    TemplateVars,
+};
+use crate::palette::{theme_variant, variant_filter, DynamicPalette};
+use crate::preview::Protocol;
+use crate::report::Report;
+use crate::sheet::{contact_sheet, Cell};
+use crate::structured::FileFormat;
+use crate::video::{FrameSize, Stream};
 
 const IMAGE_GLOB: &str = "*.(avif|bmp|dds|exr|ff|gif|hdr|ico|jpg|jpeg|png|pnm|qoi|tga|tiff|webp)";
 
@@ -302,6 +338,45 @@ fn hald_clut_or_algorithm() -> impl Parser<LutAlgorithm> {
 }
 
 impl LutAlgorithm {
+    /// Kebab-case name of the algorithm, used for output templates
+    fn name(&self) -> &'static str {
+        match self {
+            LutAlgorithm::GaussianBlur { .. } => "gaussian-blur",
+            LutAlgorithm::GaussianRbf { .. } => "gaussian-rbf",
+            LutAlgorithm::GaussianSampling { .. } => "gaussian-sampling",
+            LutAlgorithm::ShepardsMethod { .. } => "shepards-method",
+            LutAlgorithm::NearestNeighbor { .. } => "nearest-neighbor",
+            LutAlgorithm::HaldClut { .. } => "hald-clut",
+        }
+    }
+
+    /// Set the hald clut level to generate. No effect on an external hald clut.
+    fn with_level(mut self, level: u8) -> Self {
+        match &mut self {
+            LutAlgorithm::GaussianBlur { common, .. }
+            | LutAlgorithm::GaussianRbf { common, .. }
+            | LutAlgorithm::GaussianSampling { common, .. }
+            | LutAlgorithm::ShepardsMethod { common, .. }
+            | LutAlgorithm::NearestNeighbor { common, .. } => common.level = level,
+            LutAlgorithm::HaldClut { .. } => {},
+        }
+        self
+    }
+
+    /// Parse algorithm flags from a string, ie `-R -s 64`
+    fn from_flags(flags: &str) -> Result<Self, String> {
+        let args: Vec<&str> = flags.split_whitespace().collect();
+        lut_algorithm()
+            .to_options()
+            .run_inner(&args[..])
+            .map_err(|e| match e {
+                bpaf::ParseFailure::Stderr(doc) => {
+                    format!("invalid algorithm `{flags}`: {}", doc.monochrome(false))
+                },
+                _ => format!("invalid algorithm `{flags}`"),
+            })
+    }
+
     fn generate(&self, name: &str, colors: Vec<[u8; 3]>) -> Result<(RgbImage, u8), String> {
         if let Self::HaldClut { file } = &self {
             let image = load_image(file).map(|i| i.to_rgb8())?;
@@ -401,25 +476,176 @@ impl LutAlgorithm {
             ),
             _ => unreachable!(),
         };
-        println!("✔ Generated \"{name}\" LUT in {:.2?}", time.elapsed());
+        Event::Generated {
+            name: name.to_string(),
+            elapsed: time.elapsed(),
+        }
+        .emit();
 
         Ok((lut, level))
     }
 }
 
+/// Score palettes by how closely they match a set of dominant colors, best matches first.
+fn rank_palettes(
+    colors: &[extract::WeightedColor],
+    candidates: Vec<DynamicPalette>,
+) -> Vec<(f32, DynamicPalette)> {
+    let mut ranked: Vec<_> = candidates
+        .into_par_iter()
+        .map(|palette| (ops::match_error(colors, palette.get()), palette))
+        .collect();
+    ranked.sort_by(|a, b| a.0.total_cmp(&b.0));
+    ranked
+}
+
 #[derive(Bpaf, Clone, Debug, Hash)]
 enum PaletteArgs {
     /// Print all palette names. Useful for scripting and searching.
     #[bpaf(command)]
-    Names,
+    Names {
+        #[bpaf(external(variant_filter))]
+        variant: Option<Variant>,
+        /// Only include palettes in a family, ie `gruvbox`.
+        #[bpaf(long, argument("FAMILY"))]
+        family: Option<String>,
+    },
     /// Print all palette names and colors.
     #[bpaf(command)]
     All,
-    Palettes(
+    /// Rank builtin and custom palettes by how closely they match an image or a set of colors.
+    #[bpaf(command("match"))]
+    Match {
+        /// Number of palettes to show.
+        #[bpaf(short('n'), long, argument("N"), fallback(5), display_fallback)]
+        top: usize,
+        /// Images to match, or colors such as `#282828 #cc241d`.
+        #[bpaf(
+            positional("IMAGE|COLORS"),
+            some("missing an image or colors to match")
+        )]
+        inputs: Vec<String>,
+    },
+    /// Analyze palette contrast, distinctness, hue coverage, and which colors remap poorly with
+    /// the selected algorithm.
+    #[bpaf(command)]
+    Analyze {
+        #[bpaf(external)]
+        lut_algorithm: LutAlgorithm,
+        /// Palettes to analyze.
+        #[bpaf(external(DynamicPalette::arg_parser))]
+        palettes: Vec<DynamicPalette>,
+    },
+    /// Derive a new palette from existing palettes. Operations are applied in the order: merge,
+    /// ramp, tints, shades, dedupe, max-colors.
+    #[bpaf(command)]
+    Derive {
+        /// Merge colors from another palette. Can be repeated.
+        #[bpaf(long, argument("PALETTE"), many)]
+        merge: Vec<DynamicPalette>,
+        /// Insert N interpolated colors between each pair of consecutive colors.
+        #[bpaf(long, argument("N"))]
+        ramp: Option<usize>,
+        /// Add N tints of each color, mixed towards white.
+        #[bpaf(long, argument("N"))]
+        tints: Option<usize>,
+        /// Add N shades of each color, mixed towards black.
+        #[bpaf(long, argument("N"))]
+        shades: Option<usize>,
+        /// Drop colors within a perceptual difference (Oklab ΔE * 100) of an earlier color.
+        #[bpaf(long, argument("DE"))]
+        dedupe: Option<Hashed<f32>>,
+        /// Keep only the N most distinct colors.
+        #[bpaf(long, argument("N"))]
+        max_colors: Option<usize>,
+        /// Save the palette to the custom palette directory, as `<NAME>.txt`.
+        #[bpaf(long, argument("NAME"))]
+        save: Option<String>,
+        /// Palettes to derive from.
+        #[bpaf(external(DynamicPalette::arg_parser))]
+        palettes: Vec<DynamicPalette>,
+    },
+    Palettes {
+        /// Export palettes to a file format instead of printing them.
+        /// One of: gpl, ase, json, css-vars, scss, base16-yaml, kitty, alacritty, xresources, svg,
+        /// png-swatch
+        #[bpaf(long, argument("FORMAT"))]
+        format: Option<ExportFormat>,
+        /// Path to write an exported palette to, defaulting to stdout. When exporting multiple
+        /// palettes, a directory to write `<PALETTE>.<EXT>` files to.
+        #[bpaf(short, long, argument("PATH"))]
+        output: Option<PathBuf>,
         /// Palettes to print color previews for.
         #[bpaf(external(DynamicPalette::arg_parser))]
-        Vec<DynamicPalette>,
-    ),
+        palettes: Vec<DynamicPalette>,
+    },
+}
+
+#[derive(Bpaf, Clone, Debug, Hash)]
+#[allow(clippy::large_enum_variant)]
+enum PatchArgs {
+    Revert {
+        /// Restore files patched with --write, using a manifest saved with --manifest.
+        #[bpaf(long, argument("MANIFEST"), complete_shell(ShellComp::File { mask: Some("*.json") }))]
+        revert: PathBuf,
+    },
+    Files {
+        /// Write changes directly to the files.
+        #[bpaf(short, long)]
+        write: bool,
+        /// Disable computing and printing the patch. Usually paired with --write.
+        #[bpaf(short, long)]
+        no_patch: bool,
+        /// Save a manifest of every replaced color, which can be used with --revert.
+        /// Requires --write.
+        #[bpaf(long, argument("PATH"))]
+        manifest: Option<PathBuf>,
+        /// Print each distinct source color and the color it was mapped to, instead of the patch.
+        #[bpaf(long)]
+        report: bool,
+        /// Save a report of mapped colors with their counts per file, as .html or .svg
+        #[bpaf(long, argument("PATH"))]
+        report_file: Option<PathBuf>,
+        /// Extra color notation to patch anywhere in the text. Color names (ie `red`) are
+        /// otherwise only patched as entire values in structured configs. Can be used
+        /// multiple times. One of: named
+        #[bpaf(long, argument("NOTATION"))]
+        notation: Vec<String>,
+        /// Color notation to leave untouched. Can be used multiple times.
+        /// One of: hex, 0x, rgb, hsl, oklab, oklch, bare-hex, named
+        #[bpaf(long, argument("NOTATION"))]
+        skip_notation: Vec<String>,
+        /// Only patch values in structured config files, instead of the entire file.
+        /// Detected from the file name by default.
+        /// One of: plain, toml, json, yaml, ini, xresources, kitty, alacritty, foot
+        #[bpaf(long, argument("FORMAT"))]
+        format: Option<FileFormat>,
+        /// Map colors by role from a source palette (ie `red` to `red`) before using the LUT.
+        /// Both palettes must have color roles.
+        #[bpaf(long, argument("SOURCE_PALETTE"))]
+        by_role: Option<DynamicPalette>,
+        #[bpaf(optional, external(DynamicPalette::flag_parser))]
+        palette: Option<DynamicPalette>,
+        /// Only patch files in input directories matching a glob. Can be used multiple times.
+        #[bpaf(long, argument("GLOB"))]
+        include: Vec<Glob>,
+        /// Skip files in input directories matching a glob. Can be used multiple times.
+        #[bpaf(long, argument("GLOB"))]
+        exclude: Vec<Glob>,
+        #[bpaf(external)]
+        hald_clut_or_algorithm: LutAlgorithm,
+        /// Text files to generate patches for. Directories are searched recursively.
+        #[bpaf(
+            positional::<PathBuf>("FILES"),
+            non_strict,
+            guard(|path| path.exists(), "No such file or directory"),
+            complete_shell(ShellComp::File { mask: None }),
+            some("At least one file is needed to patch"),
+        )]
+        input: Vec<PathBuf>,
+        #[bpaf(external(Color::extra_colors))]
+        extra_colors: Vec<Color>,
+    },
 }
 
 /// Concat an optional palette and extra colors, as well as constructing a name tag.
@@ -449,7 +675,7 @@ fn concat_colors(
     (name, colors)
 }
 
-#[derive(Bpaf, Clone, Debug, Hash)]
+#[derive(Bpaf, Clone, Debug)]
 #[bpaf(
     options,
     version,
@@ -479,11 +705,22 @@ fn concat_colors(
         doc
     }),
 )]
+struct Options {
+    /// Print status as json events, one per line, instead of human readable messages.
+    #[bpaf(long)]
+    json: bool,
+    #[bpaf(external)]
+    verbosity: Verbosity,
+    #[bpaf(external)]
+    lutgen: Lutgen,
+}
+
+#[derive(Bpaf, Clone, Debug, Hash)]
 enum Lutgen {
     /// Generate and save a Hald CLUT to disk.
     #[bpaf(command, short('g'), fallback_to_usage)]
     Generate {
-        /// Path to write output to.
+        /// Path to write output to. Use `-` to write to stdout.
         #[bpaf(short, long, argument("PATH"), complete_shell(ShellComp::File { mask: Some(IMAGE_GLOB) }))]
         output: Option<PathBuf>,
         #[bpaf(optional, external(DynamicPalette::flag_parser))]
@@ -501,6 +738,33 @@ enum Lutgen {
         /// Palette size to extract from an image
         #[bpaf(long, fallback(128), display_fallback)]
         color_count: u8,
+        /// Quantizer used to extract colors: kmeans, median-cut, wu, or octree
+        #[bpaf(
+            long,
+            argument("QUANTIZER"),
+            fallback(Quantizer::KMeans),
+            display_fallback
+        )]
+        quantizer: Quantizer,
+        /// Count every distinct color the same, instead of weighting colors by their area.
+        /// Favors small details over large areas.
+        #[bpaf(long)]
+        unweighted: bool,
+        /// Ignore near black colors
+        #[bpaf(long)]
+        ignore_black: bool,
+        /// Ignore near white colors
+        #[bpaf(long)]
+        ignore_white: bool,
+        /// Merge extracted colors with a smaller perceptual difference (Oklab ΔE)
+        #[bpaf(long, argument("DELTA_E"), fallback(Hashed(0.)), display_fallback)]
+        min_delta_e: Hashed<f32>,
+        /// Seed for k-means initialization, for reproducible results
+        #[bpaf(long, argument("SEED"), fallback(0), display_fallback)]
+        extract_seed: u64,
+        /// Save the extracted colors to the custom palette directory, as `<NAME>.txt`.
+        #[bpaf(long, argument("NAME"))]
+        save_palette: Option<String>,
         /// Path to write output to
         #[bpaf(short, long, argument("PATH"), complete_shell(ShellComp::File { mask: Some(IMAGE_GLOB) }))]
         output: Option<PathBuf>,
@@ -516,6 +780,66 @@ enum Lutgen {
         )]
         input: Vec<PathBuf>,
     },
+    /// Generate a terminal or base16 theme from the colors of image(s).
+    /// Writes a palette file with roles, usable with `patch --by-role`, and theme files.
+    #[bpaf(
+        command,
+        short('t'),
+        header({
+            let mut doc = Doc::default();
+            doc.emphasis("Examples:");
+            doc.text("\n  $ ");
+            doc.literal("lutgen theme wallpaper.jpg --format kitty --format alacritty");
+            doc.text("\n  $ ");
+            doc.literal("lutgen theme wallpaper.jpg --scheme base16 --format base16-yaml --save");
+            doc
+        }),
+        fallback_to_usage
+    )]
+    Theme {
+        /// Roles to assign colors to: terminal (bg, fg, and color0-15) or base16 (base00-0f)
+        #[bpaf(long, argument("SCHEME"), fallback(Scheme::Terminal), display_fallback)]
+        scheme: Scheme,
+        #[bpaf(external(theme_variant))]
+        variant: Option<Variant>,
+        /// Minimum WCAG contrast ratio of text colors against the background
+        #[bpaf(long, argument("RATIO"), fallback(Hashed(4.5)), display_fallback)]
+        min_contrast: Hashed<f32>,
+        /// Number of colors to extract before assigning roles
+        #[bpaf(long, fallback(32), display_fallback)]
+        color_count: u8,
+        /// Quantizer used to extract colors: kmeans, median-cut, wu, or octree
+        #[bpaf(
+            long,
+            argument("QUANTIZER"),
+            fallback(Quantizer::KMeans),
+            display_fallback
+        )]
+        quantizer: Quantizer,
+        /// Theme file format to write, can be used multiple times.
+        /// One of: gpl, ase, json, css-vars, scss, base16-yaml, kitty, alacritty, xresources, svg,
+        /// png-swatch
+        #[bpaf(long, argument("FORMAT"))]
+        format: Vec<ExportFormat>,
+        /// Theme name, defaulting to the file stem of the first image
+        #[bpaf(long, argument("NAME"))]
+        name: Option<String>,
+        /// Also save the palette to the custom palette directory, as `<NAME>.txt`.
+        #[bpaf(long)]
+        save: bool,
+        /// Directory to write the palette and theme files to
+        #[bpaf(short, long, argument("DIR"), fallback(".".into()))]
+        output: PathBuf,
+        /// Images to extract colors from
+        #[bpaf(
+            positional("IMAGES"),
+            non_strict,
+            guard(|v| v.exists(), "No such file or directory"),
+            complete_shell(ShellComp::File { mask: Some(IMAGE_GLOB) }),
+            some("At least one image is needed to generate a theme"),
+        )]
+        input: Vec<PathBuf>,
+    },
     /// Apply a generated or provided Hald CLUT to images.
     #[bpaf(command, short('a'), fallback_to_usage)]
     Apply {
@@ -523,21 +847,58 @@ enum Lutgen {
         /// (matches output behavior for multiple files)
         #[bpaf(short, long)]
         dir: bool,
-        /// Path to write output to.
+        /// Path to write output to. Use `-` to write to stdout.
         #[bpaf(short, long, argument("PATH"), complete_shell(ShellComp::File { mask: Some(IMAGE_GLOB) }))]
         output: Option<PathBuf>,
+        /// Template for output paths, relative to `--output` when provided.
+        /// Available tokens: {dir}, {stem}, {ext}, {palette}, {algorithm}, {level}, {hash}
+        #[bpaf(long, argument("TEMPLATE"))]
+        output_template: Option<OutputTemplate>,
+        /// Force the output image encoding, instead of inferring it from the file extension.
+        #[bpaf(long, argument("png|jpg|webp|avif|gif"))]
+        format: Option<OutputFormat>,
+        /// Write animations as numbered frames in a directory named after the output, with an
+        /// ffconcat list of the frame durations. Frames are png unless `--format` is given.
+        #[bpaf(long)]
+        frames: bool,
+        /// Read the input as a raw rgb24 video stream with frames of this size, like ffmpeg's
+        /// `-f rawvideo -pix_fmt rgb24`. Y4M streams are detected automatically.
+        #[bpaf(long, argument("WIDTHxHEIGHT"))]
+        raw_video: Option<FrameSize>,
         #[bpaf(optional, external(DynamicPalette::flag_parser))]
         palette: Option<DynamicPalette>,
         /// Cache generated LUT. No effect when using an external LUT.
         #[bpaf(short, long)]
         cache: bool,
+        /// Skip images with an existing output that is newer than the input.
+        #[bpaf(short, long)]
+        update: bool,
+        /// Print a before and after preview of each image in the terminal. Can't be used with
+        /// --json.
+        #[bpaf(long)]
+        preview: bool,
+        /// Number of images to process concurrently. 0 uses all available cores.
+        #[bpaf(short, long, argument("JOBS"), fallback(0), display_fallback)]
+        jobs: usize,
+        /// Approximate memory budget in MiB for images being processed concurrently.
+        #[bpaf(long, argument("MIB"), fallback(2048), display_fallback)]
+        max_memory: u64,
+        /// Only apply to files in input directories matching a glob. Can be used multiple times.
+        /// Defaults to all supported image formats.
+        #[bpaf(long, argument("GLOB"))]
+        include: Vec<Glob>,
+        /// Skip files in input directories matching a glob. Can be used multiple times.
+        #[bpaf(long, argument("GLOB"))]
+        exclude: Vec<Glob>,
         #[bpaf(external)]
         hald_clut_or_algorithm: LutAlgorithm,
         /// Images to correct, using the generated or provided hald clut.
+        /// Directories are searched recursively, and mirrored in the output directory.
+        /// Use `-` to read from stdin.
         #[bpaf(
             positional("IMAGES"),
             non_strict,
-            guard(|v| v.exists(), "No such file or directory"),
+            guard(|v| is_stdio(v) || v.exists(), "No such file or directory"),
             complete_shell(ShellComp::File { mask: Some(IMAGE_GLOB) }),
             some("At least one image is needed to apply"),
         )]
@@ -545,32 +906,110 @@ enum Lutgen {
         #[bpaf(external(Color::extra_colors))]
         extra_colors: Vec<Color>,
     },
-    /// Generate a patch for colors inside text files.
-    #[bpaf(command, short('p'), fallback_to_usage)]
-    Patch {
-        /// Write changes directly to the files.
-        #[bpaf(short, long)]
-        write: bool,
-        /// Disable computing and printing the patch. Usually paired with --write.
-        #[bpaf(short, long)]
-        no_patch: bool,
+    /// Preview a LUT in the terminal, before and after applying it to images or a test chart.
+    #[bpaf(
+        command,
+        header({
+            let mut doc = Doc::default();
+            doc.emphasis("Examples:");
+            doc.text("\n  $ ");
+            doc.literal("lutgen preview -p gruvbox-dark");
+            doc.text("\n  $ ");
+            doc.literal("lutgen preview -p nord --protocol blocks my-image.png");
+            doc
+        })
+    )]
+    Preview {
+        /// Terminal graphics protocol: kitty, sixel, or blocks.
+        /// Detected from the terminal by default.
+        #[bpaf(long, argument("PROTOCOL"))]
+        protocol: Option<Protocol>,
+        /// Maximum width of the preview in terminal columns. Defaults to `$COLUMNS`, or 80.
+        #[bpaf(long, argument("COLUMNS"))]
+        columns: Option<u32>,
         #[bpaf(optional, external(DynamicPalette::flag_parser))]
         palette: Option<DynamicPalette>,
         #[bpaf(external)]
         hald_clut_or_algorithm: LutAlgorithm,
-        /// Text files to generate patches for.
+        /// Images to preview. Uses a test chart when none are given.
         #[bpaf(
-            positional::<PathBuf>("FILES"),
+            positional("IMAGES"),
             non_strict,
-            guard(|path| path.exists(), "No such file or directory"),
-            complete_shell(ShellComp::File { mask: None }),
-            parse(|path| std::fs::read_to_string(&path).map(|v| (path, v))),
-            some("At least one file is needed to patch"),
+            guard(|v| v.exists(), "No such file or directory"),
+            complete_shell(ShellComp::File { mask: Some(IMAGE_GLOB) }),
+            many,
         )]
-        input: Vec<(PathBuf, String)>,
+        input: Vec<PathBuf>,
         #[bpaf(external(Color::extra_colors))]
         extra_colors: Vec<Color>,
     },
+    /// Compare palettes and algorithm settings on an image, as a labeled contact sheet.
+    #[bpaf(
+        command,
+        header({
+            let mut doc = Doc::default();
+            doc.emphasis("Examples:");
+            doc.text("\n  $ ");
+            doc.literal("lutgen compare -p nord -p gruvbox-dark -p carburetor image.jpg -o sheet.png");
+            doc.text("\n  $ ");
+            doc.literal("lutgen compare --all-palettes --filter dark image.jpg");
+            doc.text("\n  $ ");
+            doc.literal("lutgen compare -p nord --algorithm '-R -s 64' --algorithm '-N' image.jpg");
+            doc
+        }),
+        fallback_to_usage
+    )]
+    Compare {
+        /// Palettes to compare. Can be used multiple times.
+        #[bpaf(external(DynamicPalette::flag_parser), many)]
+        palettes: Vec<DynamicPalette>,
+        /// Also compare every builtin palette.
+        #[bpaf(long)]
+        all_palettes: bool,
+        /// Only include builtin palettes matching a term: a variant (dark or light), a family, or
+        /// part of the name. Can be used multiple times, to match every term.
+        #[bpaf(long, argument("TERM"))]
+        filter: Vec<String>,
+        /// Algorithm flags to compare, ie `-R -s 64`. Can be used multiple times, for one row
+        /// per palette. Defaults to the algorithm flags given to the command.
+        #[bpaf(long("algorithm"), argument("FLAGS"))]
+        algorithms: Vec<String>,
+        #[bpaf(external)]
+        lut_algorithm: LutAlgorithm,
+        /// Hald clut level to generate for each thumbnail. Low levels are much faster to
+        /// generate, and good enough for small thumbnails.
+        #[bpaf(
+            long,
+            argument("2-16"),
+            fallback(6),
+            display_fallback,
+            guard(|v| (2..=16).contains(v), "hald level must between 2-16")
+        )]
+        thumbnail_level: u8,
+        /// Width of each thumbnail in pixels.
+        #[bpaf(long, argument("PIXELS"), fallback(256), display_fallback)]
+        thumbnail_width: u32,
+        /// Number of thumbnails in each row. Defaults to one per algorithm when comparing
+        /// algorithms, otherwise a square grid.
+        #[bpaf(long, argument("N"))]
+        columns: Option<usize>,
+        /// Path to write the contact sheet to. Use `-` to write to stdout.
+        #[bpaf(short, long, argument("PATH"), fallback("compare.png".into()), debug_fallback, complete_shell(ShellComp::File { mask: Some(IMAGE_GLOB) }))]
+        output: PathBuf,
+        /// Image to compare palettes on.
+        #[bpaf(
+            positional("IMAGE"),
+            guard(|v| v.exists(), "No such file or directory"),
+            complete_shell(ShellComp::File { mask: Some(IMAGE_GLOB) }),
+        )]
+        input: PathBuf,
+    },
+    /// Generate a patch for colors inside text files.
+    #[bpaf(command, short('p'), fallback_to_usage)]
+    Patch {
+        #[bpaf(external(patch_args))]
+        args: PatchArgs,
+    },
     /// Print palette names and colors
     #[bpaf(
         command,
@@ -586,6 +1025,14 @@ enum Lutgen {
             doc.literal("lutgen palette oxocarbon-dark oxocarbon-light");
             doc.text("\n  $ ");
             doc.literal("lutgen palette carburetor > palette.txt");
+            doc.text("\n  $ ");
+            doc.literal("lutgen palette gruvbox-dark --format kitty > gruvbox.conf");
+            doc.text("\n  $ ");
+            doc.literal("lutgen palette match wallpaper.jpg -n 3");
+            doc.text("\n  $ ");
+            doc.literal("lutgen palette analyze my-palette --nearest-neighbor");
+            doc.text("\n  $ ");
+            doc.literal("lutgen palette derive nord --merge gruvbox-dark --tints 2 --max-colors 16 --save nord-plus");
             doc
         }),
         fallback_to_usage
@@ -602,71 +1049,77 @@ enum Lutgen {
 fn load_image<P: AsRef<Path>>(path: P) -> Result<DynamicImage, String> {
     let path = path.as_ref();
     let time = Instant::now();
-    let lut = image::ImageReader::open(path)
-        .map_err(|e| format!("failed to open image: {e}"))?
-        .with_guessed_format()
-        .map_err(|e| format!("failed to guess image format: {e}"))?
+    let lut = read_image(path)?
         .decode()
         .map_err(|e| format!("failed to decode image: {e}"))?;
-    println!("✔ Loaded {path:?} in {:.2?}", time.elapsed());
+    Event::Loaded {
+        path: path.to_path_buf(),
+        elapsed: time.elapsed(),
+    }
+    .emit();
     Ok(lut)
 }
 
+/// Encode and save a LUT as a png, or any other format inferred from the path extension.
+fn save_lut(lut: &RgbImage, path: &Path) -> Result<(), String> {
+    let mut buf = Cursor::new(Vec::new());
+    lut.write_to(&mut buf, image_format(path, None)?)
+        .map_err(|e| format!("failed to encode LUT: {e}"))?;
+    write_output(path, buf.get_ref())
+}
+
+/// Builtin palettes matching every term: a variant (dark or light), a family, or part of the name.
+fn filter_palettes(terms: &[String]) -> impl Iterator<Item = Palette> + '_ {
+    Palette::VARIANTS.iter().copied().filter(|p| {
+        terms.iter().all(|term| match term.to_lowercase().as_str() {
+            "dark" => p.variant() == Some(Variant::Dark),
+            "light" => p.variant() == Some(Variant::Light),
+            term => {
+                p.family().is_some_and(|f| f.eq_ignore_ascii_case(term))
+                    || p.to_string().contains(term)
+            },
+        })
+    })
+}
+
 fn load_static_or_animated_image<P: AsRef<Path>>(
     path: P,
-) -> Result<Either<RgbaImage, Vec<Frame>>, String> {
-    let path = path.as_ref();
-    let time = Instant::now();
-    let decoder = image::ImageReader::open(path)
-        .map_err(|e| format!("failed to open image: {e}"))?
-        .with_guessed_format()
-        .map_err(|e| format!("failed to guess image format: {e}"))?;
+) -> Result<Either<RgbaImage, Animation>, String> {
+    let decoder = read_image(path.as_ref())?;
 
     let output = match decoder.format() {
-        Some(image::ImageFormat::Gif) => {
+        Some(ImageFormat::Gif) => {
             // Reset reader and decode gif as animation
             let mut reader = decoder.into_inner();
             reader.seek(std::io::SeekFrom::Start(0)).unwrap();
-            Either::Right(
-                GifDecoder::new(reader)
-                    .unwrap()
-                    .into_frames()
-                    .collect_frames()
-                    .map_err(|e| format!("failed to decode frames: {e}"))?,
-            )
+            Either::Right(Animation::decode(
+                ImageFormat::Gif,
+                GifDecoder::new(reader).unwrap(),
+            )?)
         },
-        Some(image::ImageFormat::Png) => {
+        Some(ImageFormat::Png) => {
             // Reset reader
             let mut reader = decoder.into_inner();
             reader.seek(std::io::SeekFrom::Start(0)).unwrap();
             let decoder = PngDecoder::new(reader).map_err(|_| "image is not a png".to_string())?;
             // If the png contains an apng, return as animated, otherwise as an image
             if decoder.is_apng().unwrap() {
-                Either::Right(
-                    decoder
-                        .apng()
-                        .unwrap()
-                        .into_frames()
-                        .collect_frames()
-                        .map_err(|e| format!("failed to decode frames: {e}"))?,
-                )
+                Either::Right(Animation::decode(
+                    ImageFormat::Png,
+                    decoder.apng().unwrap(),
+                )?)
             } else {
                 Either::Left(DynamicImage::from_decoder(decoder).unwrap().into_rgba8())
             }
         },
-        Some(image::ImageFormat::WebP) => {
+        Some(ImageFormat::WebP) => {
             // Reset reader
             let mut reader = decoder.into_inner();
             reader.seek(std::io::SeekFrom::Start(0)).unwrap();
             let decoder = WebPDecoder::new(reader).unwrap();
             // If the webp contains an animation, return as animated, otherwise as an image
             if decoder.has_animation() {
-                Either::Right(
-                    decoder
-                        .into_frames()
-                        .collect_frames()
-                        .map_err(|e| format!("failed to decode frames: {e}"))?,
-                )
+                Either::Right(Animation::decode(ImageFormat::WebP, decoder)?)
             } else {
                 Either::Left(DynamicImage::from_decoder(decoder).unwrap().into_rgba8())
             }
@@ -679,11 +1132,46 @@ fn load_static_or_animated_image<P: AsRef<Path>>(
                 .to_rgba8(),
         ),
     };
-    println!("✔ Loaded {path:?} in {:.2?}", time.elapsed());
     Ok(output)
 }
 
 impl Lutgen {
+    /// Check if the command writes output data to stdout, rather than to files.
+    fn writes_stdout(&self) -> bool {
+        match self {
+            Lutgen::Generate { output, .. } | Lutgen::Extract { output, .. } => {
+                output.as_deref().is_some_and(is_stdio)
+            },
+            Lutgen::Apply {
+                output,
+                output_template,
+                input,
+                preview,
+                ..
+            } => match output {
+                Some(output) => *preview || is_stdio(output),
+                None => {
+                    *preview || output_template.is_none() && input.iter().any(|path| is_stdio(path))
+                },
+            },
+            Lutgen::Preview { .. } => true,
+            Lutgen::Compare { output, .. } => is_stdio(output),
+            Lutgen::Palette {
+                args:
+                    PaletteArgs::Palettes {
+                        format: Some(_),
+                        output,
+                        palettes,
+                    },
+                ..
+            } => match output {
+                Some(output) => is_stdio(output),
+                None => palettes.len() == 1,
+            },
+            _ => false,
+        }
+    }
+
     fn execute(self) -> Result<String, String> {
         match self {
             Lutgen::Generate {
@@ -693,63 +1181,253 @@ impl Lutgen {
                 extra_colors,
             } => Lutgen::generate(output, palette, lut_algorithm, extra_colors),
             Lutgen::Apply {
+                dir,
+                output,
+                output_template,
+                format,
+                frames,
+                raw_video,
+                palette,
                 cache,
-                ref hald_clut_or_algorithm,
-                ..
+                update,
+                preview,
+                jobs,
+                max_memory,
+                include,
+                exclude,
+                hald_clut_or_algorithm,
+                input,
+                extra_colors,
             } => {
-                let hash =
-                    if cache && !matches!(hald_clut_or_algorithm, LutAlgorithm::HaldClut { .. }) {
-                        let mut hasher = DefaultHasher::new();
-                        self.hash(&mut hasher);
-                        Some(hasher.finish())
-                    } else {
-                        None
-                    };
+                let writes_images = match &output {
+                    Some(output) => is_stdio(output),
+                    None => output_template.is_none() && input.iter().any(|path| is_stdio(path)),
+                };
+                if preview && writes_images {
+                    return Err("--preview can't be used when writing images to stdout".into());
+                }
+                if preview && event::is_json() {
+                    // Rendered previews would be mixed in with the json events on stdout
+                    return Err("--preview can't be used with --json".into());
+                }
 
-                let Lutgen::Apply {
-                    dir,
-                    output,
-                    palette,
-                    hald_clut_or_algorithm,
-                    input,
-                    extra_colors,
-                    ..
-                } = self
-                else {
-                    unreachable!()
+                // Previews are only printed to terminals, like palette swatches
+                let preview = match preview {
+                    true if stdout().is_terminal() => Some(Protocol::detect()),
+                    true => {
+                        Event::Warning("skipping previews, stdout is not a terminal".into()).emit();
+                        None
+                    },
+                    false => None,
+                };
+                let include = if include.is_empty() {
+                    vec![Glob::from_str(IMAGE_GLOB)?]
+                } else {
+                    include
                 };
+                if input.iter().filter(|path| is_stdio(path)).count() > 1 {
+                    return Err("stdin can only be used as an input once".into());
+                }
+                let input = collect_inputs(input, &include, &exclude)?;
+                Event::Found { count: input.len() }.emit();
+                if raw_video.is_some() && input.len() > 1 {
+                    return Err("--raw-video can only be used with a single input".into());
+                }
+
+                // Hash only the arguments affecting the LUT, for caching and output templates
+                let mut hasher = DefaultHasher::new();
+                palette.hash(&mut hasher);
+                hald_clut_or_algorithm.hash(&mut hasher);
+                extra_colors.hash(&mut hasher);
+                let hash = hasher.finish();
 
                 Lutgen::apply(
                     hash,
+                    cache && !matches!(hald_clut_or_algorithm, LutAlgorithm::HaldClut { .. }),
+                    update,
+                    preview,
+                    jobs,
+                    max_memory,
                     dir,
                     output,
+                    output_template,
+                    format,
+                    frames,
+                    raw_video,
                     palette,
                     hald_clut_or_algorithm,
                     input,
                     extra_colors,
                 )
             },
-            Lutgen::Patch {
-                write,
-                no_patch,
+            Lutgen::Preview {
+                protocol,
+                columns,
                 palette,
                 hald_clut_or_algorithm,
                 input,
                 extra_colors,
-            } => Lutgen::patch(
-                write,
-                no_patch,
+            } => Lutgen::preview(
+                protocol,
+                columns,
                 palette,
                 hald_clut_or_algorithm,
                 input,
                 extra_colors,
             ),
+            Lutgen::Compare {
+                mut palettes,
+                all_palettes,
+                filter,
+                algorithms,
+                lut_algorithm,
+                thumbnail_level,
+                thumbnail_width,
+                columns,
+                output,
+                input,
+            } => {
+                if all_palettes {
+                    for palette in filter_palettes(&filter) {
+                        let palette = DynamicPalette::Builtin(palette);
+                        if !palettes
+                            .iter()
+                            .any(|p| p.to_string() == palette.to_string())
+                        {
+                            palettes.push(palette);
+                        }
+                    }
+                } else if !filter.is_empty() {
+                    return Err("--filter can only be used with --all-palettes".into());
+                }
+                if palettes.is_empty() {
+                    return Err("no palettes to compare, use --palette or --all-palettes".into());
+                }
+
+                let algorithms = if algorithms.is_empty() {
+                    vec![(None, lut_algorithm)]
+                } else {
+                    algorithms
+                        .into_iter()
+                        .map(|flags| Ok((Some(flags.clone()), LutAlgorithm::from_flags(&flags)?)))
+                        .collect::<Result<_, String>>()?
+                };
+                let algorithms = algorithms
+                    .into_iter()
+                    .map(|(flags, algorithm)| (flags, algorithm.with_level(thumbnail_level)))
+                    .collect();
+                Lutgen::compare(
+                    palettes,
+                    algorithms,
+                    thumbnail_width,
+                    columns,
+                    output,
+                    input,
+                )
+            },
+            Lutgen::Patch {
+                args: PatchArgs::Revert { revert },
+            } => Lutgen::revert(revert),
+            Lutgen::Patch {
+                args:
+                    PatchArgs::Files {
+                        write,
+                        no_patch,
+                        manifest,
+                        report,
+                        report_file,
+                        notation,
+                        skip_notation,
+                        format,
+                        by_role,
+                        palette,
+                        include,
+                        exclude,
+                        hald_clut_or_algorithm,
+                        input,
+                        extra_colors,
+                    },
+            } => {
+                let input = collect_inputs(input, &include, &exclude)?;
+                Event::Found { count: input.len() }.emit();
+
+                let mut files = Vec::new();
+                for InputFile { path, relative } in input {
+                    match std::fs::read_to_string(&path) {
+                        Ok(contents) => files.push((path, contents)),
+                        // Skip binary files found while searching directories
+                        Err(e) if relative.is_some() && e.kind() == ErrorKind::InvalidData => {
+                            Event::Warning(format!("skipping {path:?}, not a text file")).emit();
+                        },
+                        Err(e) => return Err(format!("failed to read {path:?}: {e}")),
+                    }
+                }
+
+                Lutgen::patch(
+                    write,
+                    no_patch || report,
+                    manifest,
+                    report,
+                    report_file,
+                    Tokenizer::default()
+                        .with(&notation)?
+                        .without(&skip_notation)?,
+                    format,
+                    by_role,
+                    palette,
+                    hald_clut_or_algorithm,
+                    files,
+                    extra_colors,
+                )
+            },
             Lutgen::Extract {
                 color_count,
+                quantizer,
+                unweighted,
+                ignore_black,
+                ignore_white,
+                min_delta_e,
+                extract_seed,
+                save_palette,
                 output,
                 lut_algorithm,
                 input,
-            } => Lutgen::extract(color_count, output, lut_algorithm, input),
+            } => {
+                let options = ExtractOptions {
+                    quantizer,
+                    count: color_count as usize,
+                    weighted: !unweighted,
+                    ignore_black,
+                    ignore_white,
+                    min_delta_e: min_delta_e.0,
+                    seed: extract_seed,
+                };
+                Lutgen::extract(options, save_palette, output, lut_algorithm, input)
+            },
+            Lutgen::Theme {
+                scheme,
+                variant,
+                min_contrast,
+                color_count,
+                quantizer,
+                format,
+                name,
+                save,
+                output,
+                input,
+            } => {
+                let extract = ExtractOptions {
+                    quantizer,
+                    count: color_count as usize,
+                    ..Default::default()
+                };
+                let options = ThemeOptions {
+                    scheme,
+                    dark: variant.map(|v| v == Variant::Dark),
+                    min_contrast: min_contrast.0,
+                };
+                Lutgen::theme(extract, options, format, name, save, output, input)
+            },
             Lutgen::Palette { ansi, args } => Lutgen::palette(args, ansi),
         }
     }
@@ -764,81 +1442,285 @@ impl Lutgen {
         let (lut, _) = lut_algorithm.generate(&name, colors)?;
         let time = Instant::now();
         let path = output.unwrap_or(format!("{name}.png").into());
-        lut.save(&path).map_err(|e| e.to_string())?;
-        println!("✔ Saved output to {path:?} in {:.2?}", time.elapsed());
+        save_lut(&lut, &path)?;
+        Event::Saved {
+            path,
+            elapsed: time.elapsed(),
+        }
+        .emit();
         Ok("generating ".into())
     }
 
     fn extract(
-        color_count: u8,
+        options: ExtractOptions,
+        save_palette: Option<String>,
         output: Option<PathBuf>,
         lut_algorithm: LutAlgorithm,
         inputs: Vec<PathBuf>,
     ) -> Result<String, String> {
-        let mut palette_set = HashSet::new();
+        let mut palette_set = Vec::new();
         for input in inputs {
-            // reserve space for a full additional set of items. We may add less so we
-            // incrementally reserve after each image.
-            palette_set.reserve(color_count as usize);
-
             let image = load_image(&input)?.to_rgb8();
+            let pixels: Vec<[u8; 3]> = image.pixels().map(|p| p.0).collect();
 
-            // extract palette
+            // extract palette, merging colors with the other images
             let start = Instant::now();
-            let mut pipeline = PalettePipeline::try_from(&image).unwrap();
-            for color in pipeline
-                .colorspace(ColorSpace::Oklab)
-                .quantize_method(QuantizeMethod::kmeans())
-                .palette_size(color_count)
-                .palette_par()
-            {
-                // push palette to full set
-                palette_set.insert([color.red, color.green, color.blue]);
+            let colors = extract::extract(&pixels, &options);
+            Event::Extracted {
+                colors: colors.len(),
+                elapsed: start.elapsed(),
             }
-            println!(
-                "✔ Extracted {color_count} colors in {:.2?}",
-                start.elapsed()
-            );
+            .emit();
+            for c in colors {
+                if !palette_set.contains(&c.color) {
+                    palette_set.push(c.color);
+                }
+            }
+        }
+
+        if let Some(name) = save_palette {
+            let start = Instant::now();
+            let path = DynamicPalette::save(&name, &palette_set, &[])?;
+            Event::Saved {
+                path,
+                elapsed: start.elapsed(),
+            }
+            .emit();
         }
 
         // generate lut for full palette set
-        let (lut, _) =
-            lut_algorithm.generate("extracted", palette_set.into_iter().collect::<Vec<_>>())?;
+        let (lut, _) = lut_algorithm.generate("extracted", palette_set)?;
 
         // save lut
         let start = Instant::now();
         let path = output.unwrap_or("extracted.png".into());
-        lut.save(&path).map_err(|e| e.to_string())?;
-        println!("✔ Saved output to {path:?} in {:.2?}", start.elapsed());
+        save_lut(&lut, &path)?;
+        Event::Saved {
+            path,
+            elapsed: start.elapsed(),
+        }
+        .emit();
 
         Ok("extracting ".into())
     }
 
-    fn apply(
-        hash: Option<u64>,
-        dir: bool,
-        output: Option<PathBuf>,
-        palette: Option<DynamicPalette>,
-        hald_clut_or_algorithm: LutAlgorithm,
+    fn compare(
+        palettes: Vec<DynamicPalette>,
+        algorithms: Vec<(Option<String>, LutAlgorithm)>,
+        thumbnail_width: u32,
+        columns: Option<usize>,
+        output: PathBuf,
+        input: PathBuf,
+    ) -> Result<String, String> {
+        let image = load_image(&input)?;
+        let thumbnail = image.thumbnail(thumbnail_width, u32::MAX).into_rgba8();
+
+        // With several algorithms, each palette gets a row and the original is left out
+        let compare_algorithms = algorithms.len() > 1;
+        let pairs: Vec<_> = palettes
+            .iter()
+            .flat_map(|palette| algorithms.iter().map(move |algorithm| (palette, algorithm)))
+            .collect();
+        let mut cells = pairs
+            .par_iter()
+            .map(|(palette, (flags, algorithm))| {
+                let name = palette.to_string();
+                let (lut, level) = algorithm.generate(&name, palette.get().to_vec())?;
+                let mut image = thumbnail.clone();
+                lutgen::identity::correct_image_with_level(&mut image, &lut, level);
+                let label = match flags {
+                    Some(flags) => format!("{name}\n{flags}"),
+                    None => name,
+                };
+                Ok(Cell { label, image })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if !compare_algorithms {
+            cells.insert(
+                0,
+                Cell {
+                    label: "original".into(),
+                    image: thumbnail,
+                },
+            );
+        }
+
+        let columns = columns.unwrap_or(if compare_algorithms {
+            algorithms.len()
+        } else {
+            (cells.len() as f64).sqrt().ceil() as usize
+        });
+        let time = Instant::now();
+        let sheet: RgbImage =
+            image::buffer::ConvertBuffer::convert(&contact_sheet(&cells, columns));
+        let mut buf = Cursor::new(Vec::new());
+        sheet
+            .write_to(&mut buf, image_format(&output, None)?)
+            .map_err(|e| format!("failed to encode contact sheet: {e}"))?;
+        write_output(&output, buf.get_ref())?;
+        if !is_stdio(&output) {
+            Event::Saved {
+                path: output,
+                elapsed: time.elapsed(),
+            }
+            .emit();
+        }
+
+        Ok(format!("comparing {} palette(s) ", palettes.len()))
+    }
+
+    fn preview(
+        protocol: Option<Protocol>,
+        columns: Option<u32>,
+        palette: Option<DynamicPalette>,
+        hald_clut_or_algorithm: LutAlgorithm,
         input: Vec<PathBuf>,
         extra_colors: Vec<Color>,
     ) -> Result<String, String> {
+        let protocol = match protocol {
+            Some(protocol) => protocol,
+            None if stdout().is_terminal() => Protocol::detect(),
+            None => {
+                return Err("stdout is not a terminal, use --protocol to force a preview".into())
+            },
+        };
+        let columns = columns.unwrap_or_else(preview::columns);
         let (name, colors) = concat_colors(palette, extra_colors);
-        let (lut, level) = if let Some(hash) = hash {
+        let (lut, level) = hald_clut_or_algorithm.generate(&name, colors)?;
+
+        let images = if input.is_empty() {
+            vec![("test chart".to_string(), preview::test_chart())]
+        } else {
+            input
+                .iter()
+                .map(|path| Ok((path.display().to_string(), load_image(path)?.to_rgba8())))
+                .collect::<Result<_, String>>()?
+        };
+        for (label, before) in images {
+            let mut after = before.clone();
+            lutgen::identity::correct_image_with_level(&mut after, &lut, level);
+            println!("{label}");
+            print!(
+                "{}",
+                preview::render(&preview::side_by_side(&before, &after), protocol, columns)
+            );
+        }
+
+        Ok("previewing ".into())
+    }
+
+    fn theme(
+        extract: ExtractOptions,
+        options: ThemeOptions,
+        formats: Vec<ExportFormat>,
+        name: Option<String>,
+        save: bool,
+        output: PathBuf,
+        inputs: Vec<PathBuf>,
+    ) -> Result<String, String> {
+        let name = name.unwrap_or_else(|| {
+            inputs[0]
+                .file_stem()
+                .map_or("theme".into(), |stem| stem.to_string_lossy().into_owned())
+        });
+
+        // extract colors from every image, weighting each image the same
+        let mut colors = Vec::new();
+        for input in &inputs {
+            let image = load_image(input)?.to_rgb8();
+            let pixels: Vec<[u8; 3]> = image.pixels().map(|p| p.0).collect();
+            let start = Instant::now();
+            let extracted = extract::extract(&pixels, &extract);
+            Event::Extracted {
+                colors: extracted.len(),
+                elapsed: start.elapsed(),
+            }
+            .emit();
+            colors.extend(extracted.into_iter().map(|mut c| {
+                c.weight /= inputs.len() as f32;
+                c
+            }));
+        }
+
+        let theme = theme::theme(&colors, &options);
+        let palette = theme.colors();
+
+        create_dir(&output)?;
+        let start = Instant::now();
+        let path = output.join(format!("{name}.txt"));
+        write_output(
+            &path,
+            DynamicPalette::to_text(&palette, &theme.roles).as_bytes(),
+        )?;
+        Event::Saved {
+            path,
+            elapsed: start.elapsed(),
+        }
+        .emit();
+
+        if save {
+            let start = Instant::now();
+            let path = DynamicPalette::save(&name, &palette, &theme.roles)?;
+            Event::Saved {
+                path,
+                elapsed: start.elapsed(),
+            }
+            .emit();
+        }
+
+        for format in formats {
+            let start = Instant::now();
+            let bytes = export::export(format, &name, &palette, &theme.roles);
+            let path = output.join(format!("{name}.{}", format.extension()));
+            write_output(&path, &bytes)?;
+            Event::Saved {
+                path,
+                elapsed: start.elapsed(),
+            }
+            .emit();
+        }
+
+        Ok("generating theme ".into())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn apply(
+        hash: u64,
+        cache: bool,
+        update: bool,
+        preview: Option<Protocol>,
+        jobs: usize,
+        max_memory: u64,
+        dir: bool,
+        output: Option<PathBuf>,
+        output_template: Option<OutputTemplate>,
+        format: Option<OutputFormat>,
+        frames: bool,
+        raw_video: Option<FrameSize>,
+        palette: Option<DynamicPalette>,
+        hald_clut_or_algorithm: LutAlgorithm,
+        input: Vec<InputFile>,
+        extra_colors: Vec<Color>,
+    ) -> Result<String, String> {
+        let (name, colors) = concat_colors(palette, extra_colors);
+        let (lut, level) = if cache {
             let mut path = dirs::cache_dir()
-                .expect("failed to determine cache dir")
+                .ok_or("failed to determine cache dir")?
                 .join("lutgen");
-            if !path.exists() {
-                std::fs::create_dir_all(&path).expect("failed to create cache directory");
-            }
+            create_dir(&path)?;
 
             path = path.join(format!("{name}-{hash}.png"));
             if !path.exists() {
                 let (lut, level) = hald_clut_or_algorithm.generate(&name, colors)?;
                 let time = Instant::now();
-                lut.save(path)
+                lut.save(&path)
                     .map_err(|e| format!("failed to write cached LUT: {e}"))?;
-                println!("✔ Cached \"{name}\" LUT in {:.02?}", time.elapsed());
+                Event::Cached {
+                    name: name.clone(),
+                    path,
+                    elapsed: time.elapsed(),
+                }
+                .emit();
                 (lut, level)
             } else {
                 let image = load_image(path)?.into_rgb8();
@@ -849,77 +1731,228 @@ impl Lutgen {
             hald_clut_or_algorithm.generate(&name, colors)?
         };
 
-        for file in &input {
-            let res = load_static_or_animated_image(file)?;
+        let to_stdout = output.as_deref().is_some_and(is_stdio);
+        if to_stdout && input.len() > 1 {
+            return Err("only a single input can be written to stdout".into());
+        }
+
+        let find_path = |file: &InputFile, format: Option<OutputFormat>| match &output_template {
+            Some(template) if !to_stdout => {
+                let rendered = template.render(&TemplateVars {
+                    input: &file.path,
+                    palette: &name,
+                    algorithm: hald_clut_or_algorithm.name(),
+                    level,
+                    hash,
+                    format,
+                });
+                let path = match &output {
+                    Some(base) => base.join(rendered),
+                    None => rendered,
+                };
+                if let Some(parent) = path.parent() {
+                    create_dir(parent)?;
+                }
+                Ok(path)
+            },
+            _ => Self::find_path(input.len(), dir, &name, file, format, output.clone()),
+        };
+
+        let columns = preview::columns();
+        let render = |before: &RgbaImage, after: &RgbaImage, protocol: Protocol| {
+            preview::render(&preview::side_by_side(before, after), protocol, columns)
+        };
+
+        let apply_file = |file: &InputFile,
+                          log: &mut Vec<Event>,
+                          rendered: &mut Option<String>|
+         -> Result<bool, String> {
+            if update && is_up_to_date(&file.path, &find_path(file, format)?) {
+                log.push(Event::UpToDate {
+                    path: file.path.clone(),
+                });
+                return Ok(false);
+            }
+
+            // Video streams are corrected frame by frame as they are read
+            let stream = match raw_video {
+                Some(size) => Some(Stream::Rgb24(size)),
+                None => Stream::detect(&file.path),
+            };
+            if let Some(stream) = stream {
+                if format.is_some() {
+                    return Err("--format can't be used with video streams".into());
+                }
+                let time = Instant::now();
+                let path = find_path(file, None)?;
+                let frames = video::correct_video(&file.path, &path, stream, |rgb| {
+                    correct_pixel(&rgb, &lut, level)
+                })?;
+                log.push(Event::Encoded {
+                    frames,
+                    elapsed: time.elapsed(),
+                });
+                log.push(Event::Saved {
+                    path,
+                    elapsed: time.elapsed(),
+                });
+                return Ok(true);
+            }
+
+            let time = Instant::now();
+            let res = load_static_or_animated_image(&file.path)?;
+            log.push(Event::Loaded {
+                path: file.path.clone(),
+                elapsed: time.elapsed(),
+            });
+
             match res {
                 Either::Left(mut image) => {
+                    let before = preview.map(|_| image.clone());
                     let time = Instant::now();
                     lutgen::identity::correct_image_with_level(&mut image, &lut, level);
-                    println!("✔ Applied LUT to {file:?} in {:.2?}", time.elapsed());
+                    log.push(Event::Applied {
+                        path: file.path.clone(),
+                        elapsed: time.elapsed(),
+                    });
+                    if let (Some(protocol), Some(before)) = (preview, before) {
+                        *rendered = Some(render(&before, &image, protocol));
+                    }
 
                     let time = Instant::now();
-                    let path = Self::find_path(input.len(), dir, &name, file, output.clone());
-                    let res = image.save(&path);
-
-                    match res {
+                    let path = find_path(file, format)?;
+                    let image_format = image_format(&path, format)?;
+                    let mut buf = Cursor::new(Vec::new());
+                    match image.write_to(&mut buf, image_format) {
                         Ok(_) => {},
                         Err(image::ImageError::Unsupported(e)) => {
                             // fallback to saving the image as rgb, without transparency
-                            eprintln!("warning: {} does not support transparency", e.format_hint());
-                            image::buffer::ConvertBuffer::<RgbImage>::convert(&image)
-                                .save(&path)
-                                .map_err(|e| format!("failed to save image: {e}"))?;
+                            log.push(Event::Warning(format!(
+                                "{} does not support transparency",
+                                e.format_hint()
+                            )));
+                            let image: RgbImage = image::buffer::ConvertBuffer::convert(&image);
+                            buf = Cursor::new(Vec::new());
+                            image
+                                .write_to(&mut buf, image_format)
+                                .map_err(|e| format!("failed to encode image: {e}"))?;
                         },
-                        Err(e) => return Err(format!("failed to write image: {e}")),
+                        Err(e) => return Err(format!("failed to encode image: {e}")),
                     }
+                    write_output(&path, buf.get_ref())?;
 
-                    println!("✔ Saved output to {path:?} in {:.2?}", time.elapsed());
+                    log.push(Event::Saved {
+                        path,
+                        elapsed: time.elapsed(),
+                    });
                 },
-                Either::Right(mut frames) => {
+                Either::Right(mut animation) => {
+                    // Animations are previewed by their first frame
+                    let first = animation.frames.first();
+                    let before = preview.and(first).map(|f| f.buffer().clone());
                     let time = Instant::now();
-                    let len = frames.len();
-                    frames.iter_mut().enumerate().for_each(|(i, frame)| {
-                        print!("\r… Encoding frame {i}/{len}");
-                        std::io::stdout().lock().flush().unwrap();
-                        let img = frame.buffer_mut();
-                        lutgen::identity::correct_image(img, &lut);
+                    animation.frames.par_iter_mut().for_each(|frame| {
+                        lutgen::identity::correct_image_with_level(frame.buffer_mut(), &lut, level);
+                    });
+                    if let (Some(protocol), Some(before)) = (preview, before) {
+                        *rendered = Some(render(&before, animation.frames[0].buffer(), protocol));
+                    }
+                    log.push(Event::Encoded {
+                        frames: animation.frames.len(),
+                        elapsed: time.elapsed(),
                     });
-                    println!("\r✔ Encoded {len} frames in {:.2?}", time.elapsed());
 
                     let time = Instant::now();
-                    let path = Self::find_path(
-                        input.len(),
-                        dir,
-                        &name,
-                        &file.with_extension("gif"),
-                        output.clone(),
-                    );
-                    let mut buf = Vec::new();
-                    match path
-                        .extension()
-                        .expect("expected image extension")
-                        .to_string_lossy()
-                        .to_ascii_lowercase()
-                        .as_str()
-                    {
-                        "gif" => {
-                            // encode frames into a gif
-                            let mut encoder = GifEncoder::new(&mut buf);
-                            encoder
-                                .set_repeat(image::codecs::gif::Repeat::Infinite)
-                                .unwrap();
-                            encoder
-                                .encode_frames(frames)
-                                .map_err(|e| format!("failed to encode frame: {e}"))?;
+                    let path = find_path(file, format)?;
+                    if frames {
+                        if is_stdio(&path) {
+                            return Err("frames can't be written to stdout".into());
+                        }
+                        // Frames go in a directory named after the output, without the extension
+                        let dir = path.with_extension("");
+                        create_dir(&dir)?;
+                        let format = format.map_or(ImageFormat::Png, |f| f.image_format());
+                        animation.write_frames(&dir, format)?;
+                        log.push(Event::Saved {
+                            path: dir.join(FRAME_LIST),
+                            elapsed: time.elapsed(),
+                        });
+                    } else {
+                        // Keep the source container when writing to stdout without a format
+                        let image_format = match format {
+                            None if is_stdio(&path) => animation.format,
+                            _ => image_format(&path, format)?,
+                        };
+                        write_output(&path, &animation.encode(image_format)?)?;
+                        log.push(Event::Saved {
+                            path,
+                            elapsed: time.elapsed(),
+                        });
+                    }
+                },
+            }
+
+            Ok(true)
+        };
+
+        // Process files concurrently, sharing the LUT and a memory budget between jobs
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build()
+            .map_err(|e| format!("failed to create thread pool: {e}"))?;
+        let budget = MemoryBudget::new(max_memory.saturating_mul(1024 * 1024));
+        let log = OrderedLog::default();
+        let previews = Mutex::new(BTreeMap::new());
+        let skipped = AtomicUsize::new(0);
+        let failed = AtomicUsize::new(0);
+        // Reserve memory on this thread before spawning each job, so pool threads never block on
+        // the budget while nested frame and video work is waiting for them
+        pool.in_place_scope(|scope| {
+            for (i, file) in input.iter().enumerate() {
+                let reservation = budget.reserve(MemoryBudget::estimate(&file.path));
+                let (log, previews, skipped, failed) = (&log, &previews, &skipped, &failed);
+                let apply_file = &apply_file;
+                scope.spawn(move |_| {
+                    let _reservation = reservation;
+                    let mut events = Vec::new();
+                    let mut rendered = None;
+                    match apply_file(file, &mut events, &mut rendered) {
+                        Ok(true) => {},
+                        Ok(false) => {
+                            skipped.fetch_add(1, Ordering::Relaxed);
                         },
-                        _ => {
-                            return Err("animated output must be a gif".to_string());
+                        Err(e) => {
+                            events.push(Event::Failed {
+                                path: file.path.clone(),
+                                error: e,
+                            });
+                            failed.fetch_add(1, Ordering::Relaxed);
                         },
                     }
-                    std::fs::write(&path, buf).map_err(|e| format!("failed to write gif: {e}"))?;
-                    println!("✔ Saved output to {path:?} in {:.2?}", time.elapsed());
-                },
+                    log.finish(i, events);
+                    if let Some(rendered) = rendered {
+                        previews.lock().unwrap().insert(i, rendered);
+                    }
+                });
             }
+        });
+
+        // Print previews in input order once every image is done
+        for (i, rendered) in previews.into_inner().unwrap() {
+            println!("{}", input[i].path.display());
+            print!("{rendered}");
+        }
+
+        let skipped = skipped.into_inner();
+        let failed = failed.into_inner();
+        if skipped > 0 {
+            Event::Skipped { count: skipped }.emit();
+        }
+        if failed > 0 {
+            return Err(format!(
+                "failed to apply LUT to {failed} of {} file(s)",
+                input.len()
+            ));
         }
 
         Ok(format!(
@@ -933,129 +1966,164 @@ impl Lutgen {
         input_len: usize,
         dir: bool,
         palette: &str,
-        input: &Path,
+        input: &InputFile,
+        format: Option<OutputFormat>,
         output: Option<PathBuf>,
-    ) -> PathBuf {
+    ) -> Result<PathBuf, String> {
+        let InputFile {
+            path: input,
+            relative,
+        } = input;
+
+        // Write to stdout when requested, or when reading from stdin without an output path
+        if output.as_deref().is_some_and(is_stdio) || (is_stdio(input) && output.is_none()) {
+            return Ok(PathBuf::from("-"));
+        }
+
+        // Files found in input directories always mirror the directory tree in the output dir
+        if let Some(relative) = relative {
+            let mut path = output.unwrap_or(PathBuf::from(palette)).join(relative);
+            if let Some(format) = format {
+                path.set_extension(format.extension());
+            }
+            if let Some(parent) = path.parent() {
+                create_dir(parent)?;
+            }
+            return Ok(path);
+        }
+
+        let file_name = || {
+            let mut file_name = PathBuf::from(input.file_name().ok_or("input has no file name")?);
+            if let Some(format) = format {
+                file_name.set_extension(format.extension());
+            }
+            Ok::<_, String>(file_name)
+        };
+
         if input_len > 1 {
             // For multiple images, the output path is always treated as a directory
-            let path = output.clone().unwrap_or(PathBuf::from(palette));
-            if !path.exists() {
-                std::fs::create_dir_all(&path).expect("failed to create output directory");
-            }
-            path.join(input.file_name().unwrap())
+            let path = output.unwrap_or(PathBuf::from(palette));
+            create_dir(&path)?;
+            Ok(path.join(file_name()?))
         } else {
             // For single images
-            match &output {
+            match output {
                 // If user provided a path
                 Some(path) => {
                     if dir {
                         // The path is always a dir
-                        if !path.exists() {
-                            std::fs::create_dir_all(path)
-                                .expect("failed to create output directory");
-                        }
-                        path.join(input.file_name().unwrap())
+                        create_dir(&path)?;
+                        Ok(path.join(file_name()?))
                     } else {
                         // Otherwise, ensure the parent dir exists
                         if let Some(parent) = path.parent() {
-                            if !parent.exists() {
-                                std::fs::create_dir_all(parent)
-                                    .expect("failed to create output directory");
-                            }
+                            create_dir(parent)?;
                         }
 
                         if path.is_dir() {
                             // Enable dir mode if user supplied an existing directory
-                            path.join(input.file_name().unwrap())
+                            Ok(path.join(file_name()?))
                         } else {
-                            path.clone()
+                            Ok(path)
                         }
                     }
                 },
                 None => {
                     if dir {
-                        let dir = PathBuf::from(palette);
                         // always create a palette dir
-                        if !input.exists() {
-                            std::fs::create_dir_all(&dir)
-                                .expect("failed to create output directory");
-                        }
-                        dir.join(input.file_name().unwrap())
+                        let dir = PathBuf::from(palette);
+                        create_dir(&dir)?;
+                        Ok(dir.join(file_name()?))
                     } else {
                         // create an image adjacent to the original, with a palette name prefix
-                        let mut file_name =
-                            input.file_stem().unwrap().to_string_lossy().to_string();
+                        let mut file_name = input
+                            .file_stem()
+                            .ok_or("input has no file name")?
+                            .to_string_lossy()
+                            .to_string();
                         file_name.push('_');
                         file_name.push_str(palette);
-                        let extension = input
-                            .extension()
-                            .map(|s| s.to_string_lossy())
-                            .unwrap_or("png".into());
 
                         let mut path = input.to_path_buf();
                         path.set_file_name(file_name);
-                        path.set_extension(extension.as_ref());
-                        path
+                        path.set_extension(output_extension(input, format));
+                        Ok(path)
                     }
                 },
             }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn patch(
         write: bool,
         no_patch: bool,
+        manifest: Option<PathBuf>,
+        report: bool,
+        report_file: Option<PathBuf>,
+        tokenizer: Tokenizer,
+        format: Option<FileFormat>,
+        by_role: Option<DynamicPalette>,
         palette: Option<DynamicPalette>,
         hald_clut_or_algorithm: LutAlgorithm,
         input: Vec<(PathBuf, String)>,
         extra_colors: Vec<Color>,
     ) -> Result<String, String> {
-        const REGEX: &str = r"(#)([0-9a-fA-F]{6}|[0-9a-fA-F]{3})|(rgb)\(((?:[0-9\s]+,?){3})\)|(rgba)\(((?:[0-9\s]+,?){3}),([\s0-9.]*)\)";
+        if manifest.is_some() && !write {
+            return Err(
+                "--manifest requires --write, since no files are changed without it".into(),
+            );
+        }
+
+        // Map source colors to the target colors with the same role
+        let mut roles = HashMap::new();
+        if let Some(source) = by_role {
+            let target = palette
+                .as_ref()
+                .ok_or("--by-role requires a target --palette")?;
+            for (role, color) in source.roles() {
+                if let Some(mapped) = target.role(role) {
+                    roles.insert(color, mapped);
+                }
+            }
+            if roles.is_empty() {
+                return Err(format!(
+                    "no color roles in common between {source} and {target}"
+                ));
+            }
+        }
 
         let (name, colors) = concat_colors(palette, extra_colors);
         let (lut, level) = hald_clut_or_algorithm.generate(&name, colors)?;
 
+        let mut summary = Report::new(name.clone());
+        let mut mapping = Manifest::new(name);
+        let mut writes = Vec::new();
         let len = input.len();
-        let re = Regex::new(REGEX).expect("failed to build regex");
         for (file, contents) in input {
             let time = Instant::now();
 
-            let counter = &mut 0u32;
-            let replaced = re.replace_all(&contents, |caps: &Captures| {
-                *counter += 1;
-                if caps.get(1).is_some() {
-                    let rgb = Color::from_str(&caps[2]).expect("valid hex");
-                    let [r, g, b] = correct_pixel(rgb.as_ref(), &lut, level);
-                    format!("#{r:02x}{g:02x}{b:02x}")
-                } else if caps.get(3).is_some() {
-                    let inner: Vec<u8> = caps[4]
-                        .split(',')
-                        .map(|s| s.trim().parse().expect("invalid rgb code"))
-                        .collect();
-                    let [r, g, b] = correct_pixel(&[inner[0], inner[1], inner[2]], &lut, level);
-                    format!("rgb({r}, {g}, {b})")
-                } else if caps.get(5).is_some() {
-                    let inner: Vec<u8> = caps[6]
-                        .split(',')
-                        .map(|s| s.trim().parse().expect("invalid rgb point"))
-                        .collect();
-                    let [r, g, b] = correct_pixel(&[inner[0], inner[1], inner[2]], &lut, level);
-                    format!("rgba({r}, {g}, {b}, {})", &caps[7].trim())
-                } else {
-                    unreachable!()
-                }
+            let literals = format
+                .unwrap_or_else(|| FileFormat::detect(&file))
+                .tokenize(&tokenizer, &contents);
+            let (replaced, replacements) = literal::rewrite(&contents, &literals, |literal| {
+                roles
+                    .get(&literal.color)
+                    .copied()
+                    .unwrap_or_else(|| correct_pixel(&literal.color, &lut, level))
             });
 
-            eprintln!(
-                "✔ Replaced {counter} colors in {file:?} in {:?}",
-                time.elapsed()
-            );
+            Event::Patched {
+                path: file.clone(),
+                replacements: literals.len() as u32,
+                elapsed: time.elapsed(),
+            }
+            .emit();
 
-            if *counter > 0 {
-                let replaced = replaced.to_string();
-                if write {
-                    std::fs::write(&file, &replaced).expect("failed to write file");
-                    eprintln!("✔ Wrote changes to {file:?}");
+            if !literals.is_empty() {
+                summary.push(&file, &replacements);
+                if manifest.is_some() {
+                    mapping.push(&file, replacements);
                 }
 
                 if !no_patch {
@@ -1066,6 +2134,7 @@ impl Lutgen {
                     let mut diff =
                         imara_diff::Diff::compute(imara_diff::Algorithm::Histogram, &input);
 
+                    let mut patch = String::new();
                     if diff.count_removals() + diff.count_additions() > 0 {
                         diff.postprocess_lines(&input);
                         let printer = imara_diff::BasicLineDiffPrinter(&input.interner);
@@ -1074,14 +2143,26 @@ impl Lutgen {
                             imara_diff::UnifiedDiffConfig::default(),
                             &input,
                         );
-
-                        println!(
+                        patch = format!(
                             "--- a/{file}\n+++ b/{file}\n{unified}",
                             file = file.to_string_lossy(),
                         );
                     }
 
-                    eprintln!("✔ Computed diff in {:.2?}", time.elapsed());
+                    // Diffs are part of the json event, otherwise they're printed as output
+                    if !event::is_json() && !patch.is_empty() {
+                        println!("{patch}");
+                    }
+                    Event::Diff {
+                        path: file.clone(),
+                        diff: patch,
+                        elapsed: time.elapsed(),
+                    }
+                    .emit();
+                }
+
+                if write {
+                    writes.push((file, replaced));
                 }
             }
 
@@ -1089,20 +2170,155 @@ impl Lutgen {
             drop(contents);
         }
 
+        // Save the manifest before changing any files, so an interrupted patch can be reverted
+        if let Some(path) = &manifest {
+            let time = Instant::now();
+            mapping.save(path)?;
+            Event::Saved {
+                path: path.clone(),
+                elapsed: time.elapsed(),
+            }
+            .emit();
+        }
+
+        for (i, (file, replaced)) in writes.into_iter().enumerate() {
+            if let Err(e) = std::fs::write(&file, replaced) {
+                // Only keep the files that were written, so the manifest still reverts cleanly
+                if let Some(path) = &manifest {
+                    mapping.files.truncate(i);
+                    mapping.save(path)?;
+                }
+                return Err(format!("failed to write {file:?}: {e}"));
+            }
+            Event::Wrote { path: file }.emit();
+        }
+
+        if report {
+            if event::is_json() {
+                for (from, to, count) in summary.rows() {
+                    Event::Mapping {
+                        from: Color(from).to_string(),
+                        to: Color(to).to_string(),
+                        count,
+                    }
+                    .emit();
+                }
+            } else {
+                print!("{}", summary.text(stdout().is_terminal()));
+            }
+        }
+
+        if let Some(path) = report_file {
+            let time = Instant::now();
+            summary.save(&path)?;
+            Event::Saved {
+                path,
+                elapsed: time.elapsed(),
+            }
+            .emit();
+        }
+
         Ok(format!(
             "patching {len} file{} ",
             if len > 1 { "s" } else { "" },
         ))
     }
 
+    fn revert(manifest: PathBuf) -> Result<String, String> {
+        let manifest = Manifest::load(&manifest)?;
+
+        // Revert every file before writing any, so a modified file doesn't leave a partial revert
+        let mut files = Vec::new();
+        for file in &manifest.files {
+            let patched = std::fs::read_to_string(&file.path)
+                .map_err(|e| format!("failed to read {:?}: {e}", file.path))?;
+            files.push((file, file.revert(&patched)?));
+        }
+
+        for (file, original) in files {
+            std::fs::write(&file.path, original)
+                .map_err(|e| format!("failed to write {:?}: {e}", file.path))?;
+            Event::Reverted {
+                path: file.path.clone(),
+                replacements: file.replacements.len(),
+            }
+            .emit();
+        }
+
+        let len = manifest.files.len();
+        Ok(format!(
+            "reverting {len} file{} ",
+            if len > 1 { "s" } else { "" },
+        ))
+    }
+
     fn palette(args: PaletteArgs, ansi: bool) -> Result<String, String> {
-        if matches!(args, PaletteArgs::Names) {
-            Palette::VARIANTS.iter().for_each(|p| println!("{p}"));
+        let json = event::is_json();
+        if let PaletteArgs::Names { variant, family } = args {
+            let matches = |p: &&Palette| {
+                variant.is_none_or(|v| p.variant() == Some(v))
+                    && family
+                        .as_ref()
+                        .is_none_or(|f| p.family().is_some_and(|pf| pf.eq_ignore_ascii_case(f)))
+            };
+            Palette::VARIANTS.iter().filter(matches).for_each(|p| {
+                if json {
+                    Event::Palette {
+                        name: p.to_string(),
+                        colors: None,
+                    }
+                    .emit()
+                } else {
+                    println!("{p}")
+                }
+            });
             return Ok(Default::default());
         }
 
+        if let PaletteArgs::Match { top, inputs } = args {
+            return Self::match_palettes(top, inputs, ansi);
+        }
+        if let PaletteArgs::Analyze {
+            lut_algorithm,
+            palettes,
+        } = args
+        {
+            let ansi = ansi || stdout().is_terminal();
+            for palette in palettes {
+                let name = palette.to_string();
+                let (lut, _) = lut_algorithm.generate(&name, palette.get().to_vec())?;
+                let report =
+                    lutgen::analysis::analyze(palette.get(), palette.role("bg"), Some(&lut));
+                if json {
+                    Event::Analysis {
+                        name,
+                        report: analysis::json(&report),
+                    }
+                    .emit();
+                } else {
+                    println!("{}", analysis::text(&name, &report, ansi));
+                }
+            }
+            return Ok("analyzing ".into());
+        }
+
         let is_terminal = ansi || stdout().is_terminal();
         let print = move |palette: DynamicPalette| {
+            if json {
+                Event::Palette {
+                    name: palette.to_string(),
+                    colors: Some(
+                        palette
+                            .get()
+                            .iter()
+                            .map(|c| Color(*c).to_string())
+                            .collect(),
+                    ),
+                }
+                .emit();
+                return;
+            }
+
             // Print palette name with underline
             if is_terminal {
                 eprintln!("\n\x1b[4m{palette}\x1b[0m\n");
@@ -1110,15 +2326,7 @@ impl Lutgen {
             for color in palette.get() {
                 let color = Color(*color);
                 if is_terminal {
-                    // Set background to the color, and choose foreground based on luminocity
-                    let [r, g, b] = color.0;
-                    let Oklab { l, .. } = srgb_to_oklab(color.0.into());
-                    let fg = if l < 0.5 {
-                        "\x1b[38;2;255;255;255m"
-                    } else {
-                        "\x1b[38;2;0;0;0m"
-                    };
-                    println!("\x1b[48;2;{r};{g};{b}m{fg}{color}\x1b[0m");
+                    println!("{}", color.swatch());
                 } else {
                     println!("{color}");
                 }
@@ -1130,20 +2338,186 @@ impl Lutgen {
                 .iter()
                 .map(|&p| DynamicPalette::Builtin(p))
                 .for_each(print),
-            PaletteArgs::Palettes(palettes) => {
+            PaletteArgs::Palettes {
+                format: Some(format),
+                output,
+                palettes,
+            } => return Self::export_palettes(format, output, palettes),
+            PaletteArgs::Palettes { palettes, .. } => {
                 palettes.into_iter().for_each(print);
             },
+            PaletteArgs::Derive {
+                merge,
+                ramp,
+                tints,
+                shades,
+                dedupe,
+                max_colors,
+                save,
+                palettes,
+            } => {
+                let sources: Vec<_> = palettes.iter().chain(&merge).map(|p| p.get()).collect();
+                let mut colors = ops::merge(&sources);
+                if let Some(steps) = ramp {
+                    colors = ops::ramp(&colors, steps);
+                }
+                if let Some(count) = tints {
+                    colors = ops::tints(&colors, count);
+                }
+                if let Some(count) = shades {
+                    colors = ops::shades(&colors, count);
+                }
+                if let Some(delta) = dedupe {
+                    colors = ops::dedupe(&colors, delta.0);
+                }
+                if let Some(max) = max_colors {
+                    colors = ops::max_colors(&colors, max);
+                }
+
+                let name = save.clone().unwrap_or_else(|| {
+                    palettes
+                        .iter()
+                        .chain(&merge)
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join("+")
+                });
+                if let Some(name) = save {
+                    let time = Instant::now();
+                    let path = DynamicPalette::save(&name, &colors, &[])?;
+                    Event::Saved {
+                        path,
+                        elapsed: time.elapsed(),
+                    }
+                    .emit();
+                }
+                print(DynamicPalette::Custom(name, colors, vec![]));
+            },
             _ => unreachable!(),
         }
 
         Ok("".into())
     }
+
+    fn match_palettes(top: usize, inputs: Vec<String>, ansi: bool) -> Result<String, String> {
+        // Sample colors from a thumbnail of each image, weighting colors by their area
+        let mut colors = Vec::new();
+        for input in inputs {
+            if Path::new(&input).is_file() || is_stdio(Path::new(&input)) {
+                let image = load_image(&input)?.thumbnail(64, 64).to_rgb8();
+                colors.extend(image.pixels().map(|p| p.0));
+            } else {
+                let color = Color::from_str(&input)
+                    .map_err(|_| format!("{input:?} is not an image or hex color"))?;
+                colors.push(color.0);
+            }
+        }
+
+        let candidates = palette::registry()
+            .entries()
+            .into_iter()
+            .filter_map(|entry| match DynamicPalette::from_entry(entry) {
+                Ok(palette) => Some(palette),
+                Err(e) => {
+                    Event::Warning(e).emit();
+                    None
+                },
+            })
+            .collect();
+        // Match against the dominant colors, rather than every sampled pixel
+        let colors = extract::extract(&colors, &ExtractOptions::default());
+        let mut ranked = rank_palettes(&colors, candidates);
+        ranked.truncate(top);
+
+        let json = event::is_json();
+        let is_terminal = ansi || stdout().is_terminal();
+        for (i, (score, palette)) in ranked.into_iter().enumerate() {
+            if json {
+                Event::Palette {
+                    name: palette.to_string(),
+                    colors: Some(
+                        palette
+                            .get()
+                            .iter()
+                            .map(|c| Color(*c).to_string())
+                            .collect(),
+                    ),
+                }
+                .emit();
+            } else if is_terminal {
+                let swatches: String = palette
+                    .get()
+                    .iter()
+                    .map(|[r, g, b]| format!("\x1b[48;2;{r};{g};{b}m  "))
+                    .collect();
+                println!(
+                    "{:>2}. {palette} \x1b[2m(ΔE {score:.2})\x1b[0m\n    {swatches}\x1b[0m\n",
+                    i + 1
+                );
+            } else {
+                println!("{palette}\t{score:.2}");
+            }
+        }
+
+        Ok("matching ".into())
+    }
+
+    fn export_palettes(
+        format: ExportFormat,
+        output: Option<PathBuf>,
+        palettes: Vec<DynamicPalette>,
+    ) -> Result<String, String> {
+        let single = palettes.len() == 1;
+        let dir = output.clone().unwrap_or(".".into());
+        if !single {
+            create_dir(&dir)?;
+        }
+
+        for palette in palettes {
+            let time = Instant::now();
+            let name = palette.to_string();
+            let bytes = export::export(format, &name, palette.get(), &palette.roles());
+            let path = match &output {
+                Some(path) if single => path.clone(),
+                None if single => "-".into(),
+                _ => dir.join(format!("{name}.{}", format.extension())),
+            };
+            write_output(&path, &bytes)?;
+            if !is_stdio(&path) {
+                Event::Saved {
+                    path,
+                    elapsed: time.elapsed(),
+                }
+                .emit();
+            }
+        }
+
+        Ok("".into())
+    }
 }
 
 fn main() {
     let time = Instant::now();
-    match lutgen().fallback_to_usage().run().execute() {
-        Ok(s) => eprintln!("\nFinished {s}in {:.2?}", time.elapsed()),
+    let Options {
+        json,
+        verbosity,
+        lutgen,
+    } = options().fallback_to_usage().run();
+    event::init(json, verbosity);
+    if lutgen.writes_stdout() {
+        event::reserve_stdout();
+    }
+
+    match lutgen.execute() {
+        Ok(action) => Event::Finished {
+            action,
+            elapsed: time.elapsed(),
+        }
+        .emit(),
+        Err(e) if json => {
+            Event::Error(e).emit();
+            std::process::exit(1)
+        },
         Err(e) => {
             bpaf::ParseFailure::Stderr(e.as_str().into()).print_message(80);
             std::process::exit(1)
@@ -1184,7 +2558,20 @@ mod tests {
             .join("\n"),
         )];
 
-        let result = Lutgen::patch(false, true, None, algorithm, input, palette_colors);
+        let result = Lutgen::patch(
+            false,
+            true,
+            None,
+            false,
+            None,
+            Tokenizer::default(),
+            None,
+            None,
+            None,
+            algorithm,
+            input,
+            palette_colors,
+        );
         let msg = result.expect("patch should succeed");
         assert!(msg.contains("patching 1 file"));
     }
@@ -1212,7 +2599,21 @@ mod tests {
         std::fs::write(&tmp, "color = #ff8000\n").unwrap();
         let input = vec![(tmp.clone(), std::fs::read_to_string(&tmp).unwrap())];
 
-        Lutgen::patch(true, true, None, algorithm, input, palette_colors).unwrap();
+        Lutgen::patch(
+            true,
+            true,
+            None,
+            false,
+            None,
+            Tokenizer::default(),
+            None,
+            None,
+            None,
+            algorithm,
+            input,
+            palette_colors,
+        )
+        .unwrap();
 
         let patched = std::fs::read_to_string(&tmp).unwrap();
         std::fs::remove_file(&tmp).ok();
@@ -1225,9 +2626,662 @@ mod tests {
         );
     }
 
+    #[test]
+    fn patch_revert_manifest() {
+        // Both colors collapse into red, but are restored exactly
+        let original = "a = #ff8000\nb = #FF0000\nc = rgb(200, 10, 10)\n";
+        let tmp = std::env::temp_dir().join("lutgen-test-patch-revert.txt");
+        let manifest = std::env::temp_dir().join("lutgen-test-patch-revert.json");
+        std::fs::write(&tmp, original).unwrap();
+
+        let algorithm = LutAlgorithm::NearestNeighbor {
+            _nearest_neighbor: (),
+            common: Common {
+                level: 2,
+                preserve: false,
+                lum_factor: Hashed(1.0),
+            },
+        };
+        Lutgen::patch(
+            true,
+            true,
+            Some(manifest.clone()),
+            false,
+            None,
+            Tokenizer::default(),
+            None,
+            None,
+            None,
+            algorithm.clone(),
+            vec![(tmp.clone(), original.to_string())],
+            vec![Color([0xff, 0x00, 0x00]), Color([0x00, 0x00, 0xff])],
+        )
+        .unwrap();
+        let patched = std::fs::read_to_string(&tmp).unwrap();
+        assert_eq!(patched, "a = #ff0000\nb = #FF0000\nc = rgb(255, 0, 0)\n");
+
+        Lutgen::revert(manifest.clone()).unwrap();
+        let reverted = std::fs::read_to_string(&tmp).unwrap();
+        assert_eq!(reverted, original);
+
+        let patch = |write, files| {
+            Lutgen::patch(
+                write,
+                true,
+                Some(manifest.clone()),
+                false,
+                None,
+                Tokenizer::default(),
+                None,
+                None,
+                None,
+                algorithm.clone(),
+                files,
+                vec![Color([0xff, 0x00, 0x00]), Color([0x00, 0x00, 0xff])],
+            )
+        };
+        let files = vec![(tmp.clone(), original.to_string())];
+        assert!(patch(false, files.clone()).is_err());
+
+        // A failed write errors, and the manifest only keeps the files that were written
+        let dir = std::env::temp_dir();
+        let err = patch(true, vec![files[0].clone(), (dir, original.to_string())]).unwrap_err();
+        assert!(err.starts_with("failed to write"), "{err}");
+        assert_eq!(Manifest::load(&manifest).unwrap().files.len(), 1);
+        Lutgen::revert(manifest.clone()).unwrap();
+        let reverted = std::fs::read_to_string(&tmp).unwrap();
+        std::fs::remove_file(&tmp).ok();
+        std::fs::remove_file(&manifest).ok();
+        assert_eq!(reverted, original);
+    }
+
+    #[test]
+    fn patch_report() {
+        let report = std::env::temp_dir().join("lutgen-test-patch-report.html");
+        let algorithm = LutAlgorithm::NearestNeighbor {
+            _nearest_neighbor: (),
+            common: Common {
+                level: 2,
+                preserve: false,
+                lum_factor: Hashed(1.0),
+            },
+        };
+        Lutgen::patch(
+            false,
+            true,
+            None,
+            false,
+            Some(report.clone()),
+            Tokenizer::default(),
+            None,
+            None,
+            None,
+            algorithm,
+            vec![
+                (PathBuf::from("a.css"), "#ff8000 #ff8000 #0000ff".into()),
+                (PathBuf::from("b.css"), "#ff8000".into()),
+            ],
+            vec![Color([0xff, 0x00, 0x00]), Color([0x00, 0x00, 0xff])],
+        )
+        .unwrap();
+        let html = std::fs::read_to_string(&report).unwrap();
+        std::fs::remove_file(&report).ok();
+
+        // Orange is mapped 3 times in total, twice in the first file and once in the second
+        assert!(html.contains("<th>a.css</th><th>b.css</th>"));
+        assert!(html.contains(
+            "#ff8000</td><td><span class=\"swatch\" style=\"background: #ff0000\"></span>\
+             #ff0000</td><td>3</td><td>2</td><td>1</td>"
+        ));
+        assert!(html.contains("#0000ff</td><td>1</td><td>1</td><td>0</td>"));
+    }
+
+    #[test]
+    fn patch_by_role() {
+        let tmp = std::env::temp_dir().join("lutgen-test-patch-role.txt");
+        let source = DynamicPalette::Custom(
+            "source".into(),
+            vec![[0xe0, 0x6c, 0x00]],
+            vec![("base08".into(), [0xe0, 0x6c, 0x00])],
+        );
+        let target = DynamicPalette::Custom(
+            "target".into(),
+            vec![[0xff, 0x00, 0x00], [0xe0, 0x70, 0x00], [0x00, 0x00, 0xff]],
+            vec![
+                ("red".into(), [0xff, 0x00, 0x00]),
+                ("orange".into(), [0xe0, 0x70, 0x00]),
+            ],
+        );
+        let algorithm = LutAlgorithm::NearestNeighbor {
+            _nearest_neighbor: (),
+            common: Common {
+                level: 8,
+                preserve: false,
+                lum_factor: Hashed(1.0),
+            },
+        };
+        Lutgen::patch(
+            true,
+            true,
+            None,
+            false,
+            None,
+            Tokenizer::default(),
+            None,
+            Some(source),
+            Some(target),
+            algorithm,
+            vec![(tmp.clone(), "error = #e06c00\nlink = #0000ee\n".into())],
+            vec![],
+        )
+        .unwrap();
+        let patched = std::fs::read_to_string(&tmp).unwrap();
+        std::fs::remove_file(&tmp).ok();
+
+        // The base08 role maps to red, even though orange is perceptually closer.
+        // Colors without a role fall back to the LUT.
+        assert_eq!(patched, "error = #ff0000\nlink = #0000ff\n");
+    }
+
+    #[test]
+    fn palette_match() {
+        // Colors taken from a palette rank it first, with no error
+        let colors: Vec<_> = Palette::Nord
+            .get()
+            .iter()
+            .map(|&color| extract::WeightedColor { color, weight: 1. })
+            .collect();
+        let candidates = [
+            Palette::GruvboxDark,
+            Palette::Nord,
+            Palette::CatppuccinLatte,
+        ]
+        .map(DynamicPalette::Builtin)
+        .to_vec();
+        let ranked = rank_palettes(&colors, candidates);
+        assert_eq!(ranked[0].1.to_string(), "nord");
+        assert_eq!(ranked[0].0, 0.);
+        assert!(ranked[1].0 <= ranked[2].0);
+    }
+
+    #[test]
+    fn theme_roles() {
+        use lutgen::analysis::wcag_contrast;
+        use lutgen::theme::DIM_CONTRAST;
+        use lutgen_palettes::{import, roles};
+
+        // Mostly light background, with red, green, and blue accents
+        let image = RgbImage::from_fn(64, 64, |x, _| {
+            image::Rgb(match x / 8 {
+                0 => [200, 40, 40],
+                1 => [40, 150, 60],
+                2 => [50, 80, 200],
+                _ => [235, 230, 220],
+            })
+        });
+        let dir = std::env::temp_dir().join(format!("lutgen-theme-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("wallpaper.png");
+        image.save(&input).unwrap();
+
+        let extract = ExtractOptions {
+            count: 8,
+            ..Default::default()
+        };
+        let formats = vec![ExportFormat::Kitty, ExportFormat::Base16Yaml];
+        Lutgen::theme(
+            extract,
+            ThemeOptions::default(),
+            formats,
+            None,
+            false,
+            dir.clone(),
+            vec![input],
+        )
+        .unwrap();
+
+        // Roles are read back from the palette file
+        let palette = import::read(&dir.join("wallpaper.txt")).unwrap();
+        let role = |name: &str| palette.roles.iter().find(|(r, _)| r == name).unwrap().1;
+        let bg = role("bg");
+        assert!(wcag_contrast(bg, [255; 3]) < 1.5, "light theme");
+        assert!(wcag_contrast(role("fg"), bg) >= 4.5);
+        for i in 1..7 {
+            assert!(
+                wcag_contrast(role(&format!("color{i}")), bg) >= 4.5,
+                "color{i}"
+            );
+        }
+        assert!(wcag_contrast(role("color8"), bg) >= DIM_CONTRAST);
+        let [r, g, b] = role("color1");
+        assert!(r > g && r > b);
+        let [r, g, b] = role("color4");
+        assert!(b > r && b > g);
+
+        // Theme files use the same roles
+        let kitty = import::read(&dir.join("wallpaper.conf")).unwrap();
+        let kitty: Vec<_> = kitty.roles.iter().map(|(r, c)| (r.as_str(), *c)).collect();
+        for name in ["bg", "fg", "color1", "color15"] {
+            assert_eq!(roles::find(&kitty, name), Some(role(name)), "{name}");
+        }
+        assert!(dir.join("wallpaper.yaml").is_file());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn preview_protocols() {
+        use crate::preview::{render, side_by_side, test_chart};
+
+        let chart = test_chart();
+        let image = side_by_side(&chart, &chart);
+        assert!(image.width() > chart.width() * 2);
+
+        // Half blocks are one cell per pixel wide, and two pixels tall
+        let blocks = render(&chart, Protocol::Blocks, 90);
+        assert_eq!(blocks.lines().count(), 23);
+        assert!(blocks.lines().all(|l| l.matches('▀').count() == 90));
+
+        let sixel = render(&chart, Protocol::Sixel, 90);
+        assert!(sixel.starts_with("\x1bP0;1;0q\"1;1;360;180"));
+        assert!(sixel.ends_with("\x1b\\\n"));
+        assert_eq!(sixel.matches('-').count(), 30);
+
+        // Kitty payloads are a base64 png split into chunks
+        let kitty = render(&image, Protocol::Kitty, 40);
+        assert!(kitty.starts_with("\x1b_Ga=T,f=100,c=40,m=1;"));
+        let payload: String = kitty
+            .split("\x1b\\")
+            .filter_map(|chunk| chunk.split_once(';').map(|(_, data)| data))
+            .collect();
+        assert!(payload.starts_with("iVBORw0KGgo"));
+        assert_eq!(payload.len() % 4, 0);
+    }
+
+    #[test]
+    fn compare_sheet() {
+        let dir = std::env::temp_dir().join(format!("lutgen-compare-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("gradient.png");
+        RgbImage::from_fn(64, 32, |x, y| image::Rgb([x as u8 * 4, y as u8 * 8, 128]))
+            .save(&input)
+            .unwrap();
+        let output = dir.join("sheet.png");
+
+        let algorithm = LutAlgorithm::NearestNeighbor {
+            _nearest_neighbor: (),
+            common: Common {
+                level: 10,
+                preserve: false,
+                lum_factor: Hashed(1.0),
+            },
+        }
+        .with_level(4);
+        let palettes = vec![
+            DynamicPalette::Builtin(Palette::GruvboxDark),
+            DynamicPalette::Builtin(Palette::Nord),
+        ];
+        Lutgen::compare(
+            palettes,
+            vec![(None, algorithm)],
+            32,
+            None,
+            output.clone(),
+            input,
+        )
+        .unwrap();
+
+        // The original and two palettes in a 2x2 grid of 32x16 thumbnails with one line labels
+        let sheet = image::open(&output).unwrap().to_rgb8();
+        assert_eq!(
+            sheet.dimensions(),
+            (2 * (32 + 8) + 8, 2 * (16 + 4 + 18 + 8) + 8)
+        );
+        let gruvbox = Palette::GruvboxDark.get();
+        for (x, y) in (48..80).flat_map(|x| (8..24).map(move |y| (x, y))) {
+            assert!(gruvbox.contains(&sheet.get_pixel(x, y).0));
+        }
+        let label = (8..40).flat_map(|x| (28..42).map(move |y| (x, y)));
+        assert!(label
+            .clone()
+            .any(|(x, y)| sheet.get_pixel(x, y).0 == [0xe8; 3]));
+
+        std::fs::remove_dir_all(dir).unwrap();
+
+        // Variant filters cover palettes without metadata, and terms all have to match
+        let light = filter_palettes(&["Light".into()]).collect::<Vec<_>>();
+        assert!(light.contains(&Palette::RosePineDawn));
+        assert!(light.contains(&Palette::GruvboxLight));
+        assert!(!light.contains(&Palette::Carburetor));
+        let dark = filter_palettes(&["dark".into(), "gruvbox".into()]).collect::<Vec<_>>();
+        assert!(dark.contains(&Palette::GruvboxDark));
+        assert!(dark.contains(&Palette::GruvboxMaterialDarkHard));
+        assert!(!dark.contains(&Palette::GruvboxLight));
+    }
+
+    #[test]
+    fn animated_outputs() {
+        use image::metadata::LoopCount;
+        use image::{Delay, Frame};
+
+        let dir = std::env::temp_dir().join(format!("lutgen-animation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let colors = [[0xff, 0, 0, 0xff], [0, 0xff, 0, 0xff], [0, 0, 0xff, 0xff]];
+        let frames = colors.iter().zip([40, 80, 120]).map(|(&color, ms)| {
+            let buffer = RgbaImage::from_pixel(16, 8, image::Rgba(color));
+            Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(ms, 1))
+        });
+        let animation = Animation {
+            format: ImageFormat::Gif,
+            frames: frames.collect(),
+            loop_count: LoopCount::Finite(3.try_into().unwrap()),
+        };
+
+        // Containers keep every frame, the delays, and the loop count
+        for format in [ImageFormat::Gif, ImageFormat::Png, ImageFormat::WebP] {
+            let path = dir
+                .join("animation")
+                .with_extension(format.extensions_str()[0]);
+            std::fs::write(&path, animation.encode(format).unwrap()).unwrap();
+            let Either::Right(decoded) = load_static_or_animated_image(&path).unwrap() else {
+                panic!("{format:?} output is not animated");
+            };
+            assert_eq!(decoded.format, format);
+            assert!(matches!(decoded.loop_count, LoopCount::Finite(n) if n.get() == 3));
+            assert_eq!(decoded.frames.len(), 3);
+            // Every decoded frame is counted towards the memory budget
+            assert_eq!(MemoryBudget::estimate(&path), 16 * 8 * 4 * 4);
+            for ((frame, color), ms) in decoded.frames.iter().zip(colors).zip([40, 80, 120]) {
+                let (numer, denom) = frame.delay().numer_denom_ms();
+                assert_eq!(numer / denom, ms);
+                assert_eq!(frame.buffer().get_pixel(4, 4).0, color);
+            }
+        }
+        assert!(animation.encode(ImageFormat::Jpeg).is_err());
+
+        // Frame sequences are numbered, with durations in the ffconcat list
+        let frames = dir.join("frames");
+        std::fs::create_dir_all(&frames).unwrap();
+        assert_eq!(
+            animation.write_frames(&frames, ImageFormat::Png).unwrap(),
+            3
+        );
+        let frame = image::open(frames.join("0002.png")).unwrap().to_rgba8();
+        assert_eq!(frame.get_pixel(0, 0).0, colors[1]);
+        assert_eq!(
+            MemoryBudget::estimate(&frames.join("0002.png")),
+            16 * 8 * 4 * 2
+        );
+        let list = std::fs::read_to_string(frames.join(FRAME_LIST)).unwrap();
+        assert!(list.starts_with("ffconcat version 1.0\nfile 0001.png\nduration 0.04\n"));
+        assert!(list.contains("file 0003.png\nduration 0.12\n"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn video_streams() {
+        use crate::video::correct_stream;
+
+        // 4x2 limited range 4:2:0 frames, with frame parameters that should be passed through
+        let header = b"YUV4MPEG2 W4 H2 F25:1 Ip A1:1 C420jpeg\n";
+        let mut y4m = header.to_vec();
+        for frame in [&b"FRAME\n"[..], b"FRAME Ixyz\n"] {
+            y4m.extend_from_slice(frame);
+            y4m.extend_from_slice(&[126; 8]);
+            y4m.extend_from_slice(&[128; 4]);
+        }
+
+        // Gray survives the round trip through rgb unchanged
+        let mut output = Vec::new();
+        assert_eq!(
+            correct_stream(&y4m[..], &mut output, Stream::Y4m, |rgb| rgb),
+            Ok(2)
+        );
+        assert_eq!(output, y4m);
+
+        // Pure red in BT.601 limited range
+        let mut output = Vec::new();
+        correct_stream(&y4m[..], &mut output, Stream::Y4m, |_| [255, 0, 0]).unwrap();
+        let frame = &output[header.len() + 6..][..12];
+        assert_eq!(frame, [[81; 8].as_slice(), &[90; 2], &[240; 2]].concat());
+        assert!(output[header.len() + 18..].starts_with(b"FRAME Ixyz\n"));
+
+        let truncated = &y4m[..y4m.len() - 1];
+        let err = correct_stream(truncated, Vec::new(), Stream::Y4m, |rgb| rgb).unwrap_err();
+        assert_eq!(err, "stream ended in the middle of frame 2");
+
+        // Huge frame sizes are rejected before allocating a frame
+        for header in [
+            &b"YUV4MPEG2 W4294967296 H4294967296\nFRAME\n"[..],
+            b"YUV4MPEG2 W100000 H100000 C444\nFRAME\n",
+        ] {
+            let err = correct_stream(header, Vec::new(), Stream::Y4m, |rgb| rgb).unwrap_err();
+            assert!(err.ends_with("is too large"), "{err}");
+        }
+
+        // Raw rgb24 frames have no headers
+        let size = FrameSize::from_str("2x1").unwrap();
+        assert!(FrameSize::from_str("0x1").is_err());
+        assert!(FrameSize::from_str("4294967295x4294967295").is_err());
+        let raw = [0, 0, 0, 10, 20, 30, 255, 255, 255, 1, 2, 3];
+        let mut output = Vec::new();
+        let invert = |rgb: [u8; 3]| rgb.map(|c| 255 - c);
+        let frames = correct_stream(&raw[..], &mut output, Stream::Rgb24(size), invert);
+        assert_eq!(frames, Ok(2));
+        assert_eq!(output, raw.map(|c| 255 - c));
+
+        assert_eq!(Stream::detect(Path::new("clip.Y4M")), Some(Stream::Y4m));
+        assert_eq!(Stream::detect(Path::new("clip.mp4")), None);
+    }
+
+    #[test]
+    fn palette_analysis() {
+        let colors = [
+            [0, 0, 0],
+            [255, 255, 255],
+            [0xcc, 0x24, 0x1d],
+            [0xcd, 0x25, 0x1e],
+        ];
+        let report = lutgen::analysis::analyze(&colors, None, None);
+        let text = analysis::text("test", &report, false);
+        assert!(text.contains("#cc241d #cd251e"));
+        assert!(text.contains("WCAG 21.00 AAA"));
+    }
+
+    #[test]
+    fn output_template_tokens() {
+        let template = OutputTemplate::from_str("{dir}/{palette}/{stem}-{level}.{ext}").unwrap();
+        let vars = TemplateVars {
+            input: Path::new("wallpapers/forest.jpg"),
+            palette: "nord",
+            algorithm: "gaussian-blur",
+            level: 10,
+            hash: 42,
+            format: Some(OutputFormat::Webp),
+        };
+        assert_eq!(
+            template.render(&vars),
+            PathBuf::from("wallpapers/nord/forest-10.webp")
+        );
+
+        let template = OutputTemplate::from_str("{{{algorithm}}}_{hash}").unwrap();
+        assert_eq!(template.render(&vars), PathBuf::from("{gaussian-blur}_42"));
+
+        assert!(OutputTemplate::from_str("{name}.png").is_err());
+        assert!(OutputTemplate::from_str("{stem").is_err());
+    }
+
+    #[test]
+    fn input_globs() {
+        let images = Glob::from_str(IMAGE_GLOB).unwrap();
+        assert!(images.is_match(Path::new("wallpapers/forest.JPG")));
+        assert!(!images.is_match(Path::new("wallpapers/notes.txt")));
+
+        let nested = Glob::from_str("**/kitty/*.{conf,ini}").unwrap();
+        assert!(nested.is_match(Path::new("kitty/kitty.conf")));
+        assert!(nested.is_match(Path::new(".config/kitty/theme.ini")));
+        assert!(!nested.is_match(Path::new(".config/foot/foot.ini")));
+    }
+
+    #[test]
+    fn color_literals() {
+        let tokenizer = Tokenizer::default().with(&["named".into()]).unwrap();
+        let text = [
+            "a = #FF0000CC",
+            "b = #f00a",
+            "c = 0xff0000",
+            "d = rgb(100% 0% 0% / 50%)",
+            "e = rgba(255, 0, 0, 0.5)",
+            "f = hsl(0deg 100% 50%)",
+            "g = hsla(0, 100%, 50%, 0.5)",
+            "h = oklab(0.628 0.225 0.126)",
+            "i = oklch(62.8% 0.258 29.23)",
+            "j: red;",
+            "red = rgb(255.0, 0.0, 0.0)",
+        ]
+        .join("\n");
+
+        let literals = tokenizer.tokenize(&text);
+        assert_eq!(literals.len(), 11);
+        for literal in &literals {
+            assert_eq!(
+                literal.color,
+                [255, 0, 0],
+                "{}",
+                &text[literal.range.clone()]
+            );
+        }
+
+        // Rewrite everything as green, keeping notation, precision, and alpha
+        let (replaced, _) = literal::rewrite(&text, &literals, |_| [0, 255, 0]);
+        assert_eq!(
+            replaced,
+            [
+                "a = #00FF00CC",
+                "b = #0f0a",
+                "c = 0x00ff00",
+                "d = rgb(0% 100% 0% / 50%)",
+                "e = rgba(0, 255, 0, 0.5)",
+                "f = hsl(120deg 100% 50%)",
+                "g = hsla(120, 100%, 50%, 0.5)",
+                "h = oklab(0.866 -0.234 0.179)",
+                "i = oklch(86.6% 0.295 142.50)",
+                "j: lime;",
+                "red = rgb(0.0, 255.0, 0.0)",
+            ]
+            .join("\n")
+        );
+
+        // Color names are common words, so they're opt in
+        let prose = "The red fox, a Gold medal, and Red Hat in white snow";
+        assert!(Tokenizer::default().tokenize(prose).is_empty());
+        assert_eq!(tokenizer.tokenize(prose).len(), 5);
+
+        let tokenizer = tokenizer.without(&["named".into(), "0x".into()]).unwrap();
+        assert_eq!(tokenizer.tokenize("0xff0000 red #fff").len(), 1);
+        assert!(Tokenizer::default().without(&["cmyk".into()]).is_err());
+        assert!(Tokenizer::default().with(&["bare-hex".into()]).is_err());
+    }
+
+    #[test]
+    fn structured_formats() {
+        let tokenizer = Tokenizer::default();
+        let patch = |format: FileFormat, text: &str| {
+            let literals = format.tokenize(&tokenizer, text);
+            literal::rewrite(text, &literals, |_| [0, 0, 0x80]).0
+        };
+
+        assert_eq!(
+            patch(
+                FileFormat::Ini,
+                "# foreground=ebdbb2\n[colors]\nforeground=ebdbb2\nalpha=0.9\n"
+            ),
+            "# foreground=ebdbb2\n[colors]\nforeground=000080\nalpha=0.9\n"
+        );
+        assert_eq!(
+            patch(
+                FileFormat::Kitty,
+                "# color0 #ff0000\ncolor0 #ff0000\ncolor1 FF0000\n"
+            ),
+            "# color0 #ff0000\ncolor0 #000080\ncolor1 000080\n"
+        );
+        assert_eq!(
+            patch(
+                FileFormat::Xresources,
+                "! *.color0: #ff0000\n#define bg #ff0000\n*.background: bg\n*.color1: #f00\n"
+            ),
+            "! *.color0: #ff0000\n#define bg #000080\n*.background: bg\n*.color1: #000080\n"
+        );
+        assert_eq!(
+            patch(
+                FileFormat::Toml,
+                "# red = '#ff0000'\n[colors.primary]\nred = '0xff0000' # #ff0000\nlist = [\n  \"#ff0000\",\n]\n"
+            ),
+            "# red = '#ff0000'\n[colors.primary]\nred = '0x000080' # #ff0000\nlist = [\n  \"#000080\",\n]\n"
+        );
+        assert_eq!(
+            patch(
+                FileFormat::Json,
+                "{\n  // \"#ff0000\"\n  \"#ff0000\": \"#ff0000\",\n  \"red\": \"ff0000\"\n}"
+            ),
+            "{\n  // \"#ff0000\"\n  \"#ff0000\": \"#000080\",\n  \"red\": \"000080\"\n}"
+        );
+        assert_eq!(
+            patch(
+                FileFormat::Yaml,
+                "# base00: \"ff0000\"\nbase00: \"ff0000\" # red\nred:\n  - '#ff0000'\n"
+            ),
+            "# base00: \"ff0000\"\nbase00: \"000080\" # red\nred:\n  - '#000080'\n"
+        );
+        // Color names are only patched as entire values
+        assert_eq!(
+            patch(
+                FileFormat::Toml,
+                "cursor = \"white\"\nfont = \"Red Hat Mono\"\n"
+            ),
+            "cursor = \"navy\"\nfont = \"Red Hat Mono\"\n"
+        );
+
+        assert_eq!(
+            FileFormat::detect(Path::new("foot/foot.ini")),
+            FileFormat::Ini
+        );
+        assert_eq!(
+            FileFormat::detect(Path::new(".config/kitty/themes/nord.conf")),
+            FileFormat::Kitty
+        );
+        assert_eq!(
+            FileFormat::detect(Path::new(".Xresources")),
+            FileFormat::Xresources
+        );
+        assert_eq!(
+            FileFormat::detect(Path::new("style.css")),
+            FileFormat::Plain
+        );
+    }
+
+    #[test]
+    fn json_events() {
+        let event = Event::Patched {
+            path: "theme.css".into(),
+            replacements: 3,
+            elapsed: std::time::Duration::from_millis(5),
+        };
+        let json = event.json();
+        assert_eq!(json["event"], "patched");
+        assert_eq!(json["path"], "theme.css");
+        assert_eq!(json["replacements"], 3);
+        assert_eq!(json["elapsed_ms"], 5.0);
+
+        let json = Event::Error("no such file".into()).json();
+        assert_eq!(json["event"], "error");
+        assert_eq!(json["message"], "no such file");
+    }
+
     #[test]
     fn generate_docs() {
-        let options = lutgen();
+        let options = options();
 
         let roff = options.render_manpage("lutgen", bpaf::doc::Section::General, None, None, None);
         std::fs::write("../../docs/man/lutgen.1", roff).expect("failed to write manpage");
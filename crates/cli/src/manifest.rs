@@ -0,0 +1,106 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+use crate::literal;
+
+/// Record of every color rewritten by a patch, used to revert it.
+///
+/// Each occurrence is stored with its exact original text, so files can be restored even when
+/// several colors were mapped to the same one.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Name of the palette used for the patch
+    pub palette: String,
+    pub files: Vec<FileMapping>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileMapping {
+    pub path: PathBuf,
+    pub replacements: Vec<Replacement>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Replacement {
+    /// Original color
+    pub from: String,
+    /// New color
+    pub to: String,
+    /// Byte offset of the literal in the original file
+    pub offset: usize,
+    /// Byte offset of the rewritten literal in the patched file
+    pub patched_offset: usize,
+    /// Original text of the literal
+    pub original: String,
+    /// Rewritten text of the literal
+    pub patched: String,
+}
+
+impl Manifest {
+    pub fn new(palette: String) -> Self {
+        Self {
+            palette,
+            files: Vec::new(),
+        }
+    }
+
+    /// Add the replacements made in a file
+    pub fn push(&mut self, path: &Path, replacements: Vec<literal::Replacement>) {
+        self.files.push(FileMapping {
+            // Store absolute paths, so the manifest can be reverted from anywhere
+            path: path.canonicalize().unwrap_or(path.to_path_buf()),
+            replacements: replacements
+                .into_iter()
+                .map(|r| Replacement {
+                    from: Color(r.from).to_string(),
+                    to: Color(r.to).to_string(),
+                    offset: r.range.start,
+                    patched_offset: r.offset,
+                    original: r.original,
+                    patched: r.replaced,
+                })
+                .collect(),
+        });
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read manifest {path:?}: {e}"))?;
+        serde_json::from_str(&contents).map_err(|e| format!("invalid manifest {path:?}: {e}"))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("failed to serialize manifest: {e}"))?;
+        std::fs::write(path, contents)
+            .map_err(|e| format!("failed to write manifest {path:?}: {e}"))
+    }
+}
+
+impl FileMapping {
+    /// Restore the original contents of a patched file.
+    ///
+    /// Fails without changing anything if a rewritten literal is no longer where it was written.
+    pub fn revert(&self, patched: &str) -> Result<String, String> {
+        let mut buf = String::with_capacity(patched.len());
+        let mut last = 0;
+        for r in &self.replacements {
+            let end = r.patched_offset + r.patched.len();
+            if r.patched_offset < last
+                || patched.get(r.patched_offset..end) != Some(r.patched.as_str())
+            {
+                return Err(format!(
+                    "{:?} was modified after patching, expected `{}` at byte {}",
+                    self.path, r.patched, r.patched_offset
+                ));
+            }
+            buf.push_str(&patched[last..r.patched_offset]);
+            buf.push_str(&r.original);
+            last = end;
+        }
+        buf.push_str(&patched[last..]);
+        Ok(buf)
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt::Display;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use image::ImageFormat;
+
+use crate::input::is_stdio;
+
+/// Image formats that can be forced for output, regardless of the input or output path extension.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    Jpg,
+    Webp,
+    Avif,
+    Gif,
+}
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(Self::Png),
+            "jpg" | "jpeg" => Ok(Self::Jpg),
+            "webp" => Ok(Self::Webp),
+            "avif" => Ok(Self::Avif),
+            "gif" => Ok(Self::Gif),
+            _ => Err(format!(
+                "unsupported output format `{s}`, expected one of: png, jpg, webp, avif, gif"
+            )),
+        }
+    }
+}
+impl OutputFormat {
+    /// File extension used for the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpg => "jpg",
+            Self::Webp => "webp",
+            Self::Avif => "avif",
+            Self::Gif => "gif",
+        }
+    }
+
+    /// Underlying image format to encode with
+    pub fn image_format(&self) -> ImageFormat {
+        match self {
+            Self::Png => ImageFormat::Png,
+            Self::Jpg => ImageFormat::Jpeg,
+            Self::Webp => ImageFormat::WebP,
+            Self::Avif => ImageFormat::Avif,
+            Self::Gif => ImageFormat::Gif,
+        }
+    }
+}
+
+/// A single piece of a parsed output template
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Dir,
+    Stem,
+    Ext,
+    Palette,
+    Algorithm,
+    Level,
+    Hash,
+}
+impl Segment {
+    fn from_token(token: &str) -> Result<Self, String> {
+        Ok(match token {
+            "dir" => Self::Dir,
+            "stem" => Self::Stem,
+            "ext" => Self::Ext,
+            "palette" => Self::Palette,
+            "algorithm" => Self::Algorithm,
+            "level" => Self::Level,
+            "hash" => Self::Hash,
+            _ => {
+                return Err(format!(
+                    "unknown template token `{{{token}}}`, expected one of: {{dir}}, {{stem}}, \
+                     {{ext}}, {{palette}}, {{algorithm}}, {{level}}, {{hash}}"
+                ))
+            },
+        })
+    }
+}
+
+/// Output path template, ie `{dir}/{palette}/{stem}.{ext}`.
+///
+/// Templates are validated when parsed, so rendering can never fail on an unknown token.
+/// Literal braces can be written as `{{` and `}}`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct OutputTemplate(Vec<Segment>);
+impl FromStr for OutputTemplate {
+    type Err = String;
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    let mut token = String::new();
+                    loop {
+                        match chars.next() {
+                            // Escaped brace
+                            Some('{') if token.is_empty() => {
+                                text.push('{');
+                                break;
+                            },
+                            Some('}') => {
+                                if !text.is_empty() {
+                                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                                }
+                                segments.push(Segment::from_token(&token)?);
+                                break;
+                            },
+                            Some(c) => token.push(c),
+                            None => return Err(format!("unclosed `{{` in template `{template}`")),
+                        }
+                    }
+                },
+                '}' => {
+                    if chars.next() != Some('}') {
+                        return Err(format!(
+                            "unmatched `}}` in template `{template}`, use `}}}}` for a literal"
+                        ));
+                    }
+                    text.push('}');
+                },
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        if segments.is_empty() {
+            return Err("output template cannot be empty".into());
+        }
+        Ok(Self(segments))
+    }
+}
+
+/// Values available to an [`OutputTemplate`] while rendering
+pub struct TemplateVars<'a> {
+    pub input: &'a Path,
+    pub palette: &'a str,
+    pub algorithm: &'a str,
+    pub level: u8,
+    pub hash: u64,
+    pub format: Option<OutputFormat>,
+}
+
+impl OutputTemplate {
+    /// Render the template for an input file
+    pub fn render(&self, vars: &TemplateVars) -> PathBuf {
+        let mut buf = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Text(text) => buf.push_str(text),
+                Segment::Dir => match vars.input.parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => {
+                        buf.push_str(&dir.to_string_lossy())
+                    },
+                    _ => buf.push('.'),
+                },
+                Segment::Stem => buf.push_str(
+                    &vars
+                        .input
+                        .file_stem()
+                        .map(|s| s.to_string_lossy())
+                        .unwrap_or_default(),
+                ),
+                Segment::Ext => buf.push_str(&output_extension(vars.input, vars.format)),
+                Segment::Palette => buf.push_str(vars.palette),
+                Segment::Algorithm => buf.push_str(vars.algorithm),
+                Segment::Level => buf.push_str(&vars.level.to_string()),
+                Segment::Hash => buf.push_str(&vars.hash.to_string()),
+            }
+        }
+        PathBuf::from(buf)
+    }
+}
+
+/// Extension to use for an output, either from the forced format or the input file.
+pub fn output_extension(input: &Path, format: Option<OutputFormat>) -> String {
+    match format {
+        Some(format) => format.extension().to_string(),
+        None => input
+            .extension()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or("png".into()),
+    }
+}
+
+/// Create a directory and any parents if it does not already exist.
+pub fn create_dir(path: &Path) -> Result<(), String> {
+    if !path.as_os_str().is_empty() && !path.exists() {
+        std::fs::create_dir_all(path)
+            .map_err(|e| format!("failed to create output directory {path:?}: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Format to encode an output with, either forced or inferred from the path extension.
+/// Output written to stdout defaults to png.
+pub fn image_format(path: &Path, format: Option<OutputFormat>) -> Result<ImageFormat, String> {
+    match format {
+        Some(format) => Ok(format.image_format()),
+        None if is_stdio(path) => Ok(ImageFormat::Png),
+        None => ImageFormat::from_path(path)
+            .map_err(|e| format!("failed to determine output format for {path:?}: {e}")),
+    }
+}
+
+/// Write an encoded output to a file, or stdout when the path is `-`.
+pub fn write_output(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    if is_stdio(path) {
+        let mut stdout = std::io::stdout().lock();
+        stdout
+            .write_all(bytes)
+            .and_then(|_| stdout.flush())
+            .map_err(|e| format!("failed to write to stdout: {e}"))
+    } else {
+        std::fs::write(path, bytes).map_err(|e| format!("failed to write {path:?}: {e}"))
+    }
+}
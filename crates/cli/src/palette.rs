@@ -1,60 +1,77 @@
 use std::collections::BTreeSet;
 use std::fmt::Display;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::OnceLock;
 
-use bpaf::{long, positional, Doc, Parser};
-use lutgen_palettes::Palette;
+use bpaf::{construct, long, positional, Doc, Parser};
+use lutgen_palettes::registry::{Entry, PaletteRegistry, RegistryError, Source};
+use lutgen_palettes::{import, roles, Palette, Variant};
 
 use crate::color::Color;
+use crate::event::Event;
+use crate::output::create_dir;
 
 #[derive(Clone, Debug, Hash)]
 pub enum DynamicPalette {
     Builtin(Palette),
-    Custom(String, Vec<[u8; 3]>),
+    /// Custom palette name, colors, and role names for some of the colors
+    Custom(String, Vec<[u8; 3]>, Vec<(String, [u8; 3])>),
 }
 impl Display for DynamicPalette {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             DynamicPalette::Builtin(palette) => std::fmt::Display::fmt(palette, f),
-            DynamicPalette::Custom(name, _) => f.write_str(name),
+            DynamicPalette::Custom(name, ..) => f.write_str(name),
         }
     }
 }
 impl FromStr for DynamicPalette {
     type Err = String;
     fn from_str(input: &str) -> Result<Self, Self::Err> {
-        if let Some((name, path)) = Self::get_custom_palettes()
-            .iter()
-            .find(|(name, _)| input == name.to_lowercase())
-        {
-            let contents = std::fs::read_to_string(path)
-                .map_err(|e| format!("failed to read custom palette file: {e}"))?;
-
-            let mut palette = Vec::new();
-            for color in contents.split_whitespace() {
-                palette.push(Color::from_str(color)?.0)
-            }
+        match registry().resolve(input) {
+            Ok(entry) => Self::from_entry(entry),
+            // Palette files can also be used directly
+            Err(_) if Path::new(input).is_file() => {
+                let path = Path::new(input);
+                let palette =
+                    import::read(path).map_err(|e| format!("failed to read palette file {e}"))?;
+                let name = path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                Ok(DynamicPalette::Custom(name, palette.colors, palette.roles))
+            },
+            Err(RegistryError::Unknown { suggestion, .. }) => {
+                let mut doc: Doc = "unknown palette\n  \n  ".into();
 
-            Ok(DynamicPalette::Custom(name.clone(), palette))
-        } else if let Ok(palette) = Palette::from_str(input) {
-            Ok(DynamicPalette::Builtin(palette))
-        } else {
-            let mut doc: Doc = "unknown palette\n  \n  ".into();
-
-            if let Some((_, name)) = Self::suggestions(input).pop_first() {
-                doc.text("Did you mean ");
-                doc.emphasis(&name);
-                doc.text("\n  \n  ");
-            }
+                if let Some(name) = suggestion {
+                    doc.text("Did you mean ");
+                    doc.emphasis(&name);
+                    doc.text("\n  \n  ");
+                }
 
-            doc.emphasis("Hint: ");
-            doc.text("view all palettes with ");
-            doc.literal("`lutgen palette names`");
-            Err(doc.monochrome(true))
+                doc.emphasis("Hint: ");
+                doc.text("view all palettes with ");
+                doc.literal("`lutgen palette names`");
+                Err(doc.monochrome(true))
+            },
+            Err(e) => Err(e.to_string()),
         }
     }
 }
+
+/// Registry of builtin and custom palettes, scanned once on first use
+pub fn registry() -> &'static PaletteRegistry {
+    static REGISTRY: OnceLock<PaletteRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        PaletteRegistry::with_default_dir().unwrap_or_else(|e| {
+            Event::Warning(e.to_string()).emit();
+            PaletteRegistry::new()
+        })
+    })
+}
+
 impl DynamicPalette {
     const HELP: &'static str = "\
 Builtin or custom palette to use.
@@ -65,7 +82,14 @@ Custom palettes can be added to `$LUTGEN_DIR` or `<CONFIG DIR>/lutgen`.
    - Windows: `C:\\Users\\Alice\\AppData\\Roaming\\lutgen`
 
 Names are case-insensitive and parsed from the file stem, minus any file extensions.
-For example, `~/.config/lutgen/My-palette.txt` would be avalable to use as `my-palette`.";
+For example, `~/.config/lutgen/My-palette.txt` would be avalable to use as `my-palette`.
+
+Palette files can also be used directly as a path. Supported formats are whitespace separated hex
+colors, Lospec `.hex`, GIMP `.gpl`, Adobe `.ase` and `.aco`, Paint.NET `.txt`, base16/base24
+`.yaml`, pywal `colors.json`, kitty, alacritty, foot, and wezterm themes, and Xresources.
+
+Colors can be given a role for `patch --by-role`, ie `bg=#282828 red=#cc241d base0D=#458588`.
+Roles are also read from terminal themes and base16 schemes.";
 
     /// Argument parser and completion for palettes
     pub fn flag_parser() -> impl Parser<Self> {
@@ -99,46 +123,99 @@ For example, `~/.config/lutgen/My-palette.txt` would be avalable to use as `my-p
     pub fn get(&self) -> &[[u8; 3]] {
         match self {
             DynamicPalette::Builtin(p) => p.get(),
-            DynamicPalette::Custom(_, p) => p.as_ref(),
+            DynamicPalette::Custom(_, p, _) => p.as_ref(),
+        }
+    }
+
+    /// Get the colors with a role, ie `bg`, `red`, or `base08`
+    pub fn roles(&self) -> Vec<(&str, [u8; 3])> {
+        match self {
+            DynamicPalette::Builtin(p) => p.roles().to_vec(),
+            DynamicPalette::Custom(_, _, roles) => {
+                roles.iter().map(|(r, c)| (r.as_str(), *c)).collect()
+            },
+        }
+    }
+
+    /// Find the color for a role, or any equivalent role (ie `base08` and `red`)
+    pub fn role(&self, role: &str) -> Option<[u8; 3]> {
+        roles::find(&self.roles(), role)
+    }
+
+    /// Get a palette from a registry entry, reading custom palettes from disk
+    pub fn from_entry(entry: &Entry) -> Result<Self, String> {
+        match &entry.source {
+            Source::Builtin(palette) => Ok(DynamicPalette::Builtin(*palette)),
+            _ => {
+                let palette = entry.load().map_err(|e| e.to_string())?;
+                Ok(DynamicPalette::Custom(
+                    entry.name.to_lowercase(),
+                    palette.colors,
+                    palette.roles,
+                ))
+            },
         }
     }
 
     /// Compute a set of palette suggestions based on some input. Best matches are first in the set.
     pub fn suggestions(input: &str) -> BTreeSet<(u16, String)> {
-        let input = input.to_lowercase();
-        Palette::VARIANTS
-            .iter()
-            .map(ToString::to_string)
-            .chain(Self::get_custom_palettes().iter().map(|v| v.0.clone()))
-            .filter_map(|variant| {
-                let score = strsim::jaro_winkler(&input, &variant);
-                (score > 0.7).then_some((((1. - score) * 10000.) as u16, variant))
-            })
-            .collect::<BTreeSet<_>>()
+        registry()
+            .search(input)
+            .into_iter()
+            .map(|(score, entry)| (((1. - score) * 10000.) as u16, entry.name.to_lowercase()))
+            .collect()
+    }
+
+    /// Save colors as a custom palette, `<NAME>.txt` in `$LUTGEN_DIR` or `<CONFIG DIR>/lutgen`
+    pub fn save(
+        name: &str,
+        colors: &[[u8; 3]],
+        roles: &[(&str, [u8; 3])],
+    ) -> Result<PathBuf, String> {
+        let dir = registry()
+            .dir()
+            .ok_or("failed to find the config directory, set `$LUTGEN_DIR`")?;
+        create_dir(dir)?;
+        let path = dir.join(format!("{name}.txt"));
+        std::fs::write(&path, Self::to_text(colors, roles))
+            .map_err(|e| format!("failed to write {path:?}: {e}"))?;
+        Ok(path)
     }
 
-    /// Parse files in the palette directory and return all items and locations
-    pub fn get_custom_palettes() -> Vec<(String, PathBuf)> {
-        let path = std::env::var("LUTGEN_DIR")
-            .map(Into::into)
-            .unwrap_or(dirs::config_dir().unwrap().join("lutgen"));
-
-        if path.is_dir() {
-            std::fs::read_dir(path)
-                .expect("failed to read lutgen dir")
-                .map(|v| {
-                    let path = v.expect("failed to get file info").path();
-                    (
-                        path.file_stem()
-                            .expect("missing file stem")
-                            .to_string_lossy()
-                            .to_lowercase(),
-                        path,
-                    )
-                })
-                .collect()
-        } else {
-            vec![]
+    /// Plain text palette file, with a `role=#hex` line for each role followed by the remaining
+    /// colors.
+    pub fn to_text(colors: &[[u8; 3]], roles: &[(&str, [u8; 3])]) -> String {
+        let mut text: String = roles
+            .iter()
+            .map(|(role, c)| format!("{role}={}\n", Color(*c)))
+            .collect();
+        for &c in colors {
+            if !roles.iter().any(|(_, color)| *color == c) {
+                text += &format!("{}\n", Color(c));
+            }
         }
+        text
     }
 }
+
+/// Parser for filtering palettes by variant
+pub fn variant_filter() -> impl Parser<Option<Variant>> {
+    let dark = long("dark")
+        .help("Only include dark palettes.")
+        .req_flag(Variant::Dark);
+    let light = long("light")
+        .help("Only include light palettes.")
+        .req_flag(Variant::Light);
+    construct!([dark, light]).optional()
+}
+
+/// Parser for forcing a dark or light theme
+pub fn theme_variant() -> impl Parser<Option<Variant>> {
+    let dark = long("dark")
+        .help("Force a dark theme, instead of picking from the image's lightness.")
+        .req_flag(Variant::Dark);
+    let light = long("light")
+        .help("Force a light theme, instead of picking from the image's lightness.")
+        .req_flag(Variant::Light);
+    construct!([dark, light]).optional()
+}
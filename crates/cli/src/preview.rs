@@ -0,0 +1,265 @@
+use std::fmt::{Display, Write};
+use std::io::Cursor;
+use std::str::FromStr;
+
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, Rgba, RgbaImage};
+
+/// Preview width when `$COLUMNS` is not set.
+const DEFAULT_COLUMNS: u32 = 80;
+/// Assumed width of a terminal cell in pixels, for sizing kitty and sixel images.
+const CELL_WIDTH: u32 = 10;
+/// Maximum length of a kitty graphics payload chunk.
+const KITTY_CHUNK: usize = 4096;
+/// Gap between the images of a before and after preview, as a fraction of the image width.
+const GAP: f32 = 0.04;
+
+/// Protocol used to draw images in the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    /// Kitty graphics protocol, also supported by ghostty and wezterm
+    Kitty,
+    Sixel,
+    /// Truecolor half blocks, two pixels per cell. Works in any modern terminal.
+    Blocks,
+}
+
+impl Protocol {
+    pub const ALL: &'static [Protocol] = &[Protocol::Kitty, Protocol::Sixel, Protocol::Blocks];
+
+    /// Detect the best supported protocol from the terminal's environment variables, falling
+    /// back to half blocks. Multiplexers like tmux need passthrough for images, so they also
+    /// use half blocks.
+    pub fn detect() -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default().to_lowercase();
+        let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+        if std::env::var_os("TMUX").is_some() || term.starts_with("screen") {
+            Protocol::Blocks
+        } else if std::env::var_os("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || term.contains("ghostty")
+            || matches!(program.as_str(), "ghostty" | "wezterm")
+        {
+            Protocol::Kitty
+        } else if ["foot", "mlterm", "contour", "sixel"]
+            .iter()
+            .any(|t| term.contains(t))
+            || matches!(program.as_str(), "iterm.app" | "mintty")
+        {
+            Protocol::Sixel
+        } else {
+            Protocol::Blocks
+        }
+    }
+}
+
+impl Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Protocol::Kitty => "kitty",
+            Protocol::Sixel => "sixel",
+            Protocol::Blocks => "blocks",
+        })
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Protocol::ALL
+            .iter()
+            .find(|p| p.to_string().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("unknown protocol `{s}`, expected kitty, sixel, or blocks"))
+    }
+}
+
+/// Terminal width in columns, from `$COLUMNS`.
+pub fn columns() -> u32 {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .filter(|&c| c > 0)
+        .unwrap_or(DEFAULT_COLUMNS)
+}
+
+/// Render an image for the terminal, at most `columns` cells wide.
+pub fn render(image: &RgbaImage, protocol: Protocol, columns: u32) -> String {
+    match protocol {
+        Protocol::Kitty => kitty(&shrink(image, columns * CELL_WIDTH), columns),
+        Protocol::Sixel => sixel(&shrink(image, columns * CELL_WIDTH)),
+        Protocol::Blocks => blocks(&shrink(image, columns)),
+    }
+}
+
+/// Place two images next to each other, with a transparent gap between them.
+pub fn side_by_side(before: &RgbaImage, after: &RgbaImage) -> RgbaImage {
+    let gap = (before.width() as f32 * GAP).ceil() as u32;
+    let mut image = RgbaImage::new(
+        before.width() + gap + after.width(),
+        before.height().max(after.height()),
+    );
+    imageops::replace(&mut image, before, 0, 0);
+    imageops::replace(&mut image, after, (before.width() + gap) as i64, 0);
+    image
+}
+
+/// Test chart for previewing a LUT: hues from left to right, going from white through the full
+/// color to black, above a gray ramp.
+pub fn test_chart() -> RgbaImage {
+    let (width, height, ramp) = (360, 180, 150);
+    RgbaImage::from_fn(width, height, |x, y| {
+        if y >= ramp {
+            let v = (x * 255 / (width - 1)) as u8;
+            return Rgba([v, v, v, 255]);
+        }
+
+        // Fully saturated hue, then mixed with white or black
+        let hue = x as f32 / width as f32 * 6.;
+        let channel = |offset: f32| {
+            let k = (hue + offset) % 6.;
+            1. - (k.min(4. - k).clamp(0., 1.))
+        };
+        let [r, g, b] = [channel(5.), channel(3.), channel(1.)];
+        let t = y as f32 / (ramp - 1) as f32 * 2.;
+        let mix = |c: f32| {
+            let v = if t < 1. {
+                1. + (c - 1.) * t
+            } else {
+                c * (2. - t)
+            };
+            (v * 255.).round() as u8
+        };
+        Rgba([mix(r), mix(g), mix(b), 255])
+    })
+}
+
+/// Downscale an image to fit a width, keeping the aspect ratio.
+fn shrink(image: &RgbaImage, width: u32) -> RgbaImage {
+    if image.width() <= width {
+        return image.clone();
+    }
+    let height = (image.height() as u64 * width as u64 / image.width() as u64).max(1) as u32;
+    imageops::resize(image, width, height, FilterType::Triangle)
+}
+
+/// Kitty graphics protocol, transmitting a png in chunks and scaling it to the columns.
+fn kitty(image: &RgbaImage, columns: u32) -> String {
+    let mut png = Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, ImageFormat::Png)
+        .expect("encoding a png to memory should not fail");
+    let data = base64(png.get_ref());
+    let columns = columns.min(image.width().div_ceil(CELL_WIDTH));
+
+    let mut out = String::new();
+    let chunks: Vec<_> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        let chunk = std::str::from_utf8(chunk).unwrap();
+        if i == 0 {
+            write!(out, "\x1b_Ga=T,f=100,c={columns},m={more};{chunk}\x1b\\").unwrap();
+        } else {
+            write!(out, "\x1b_Gm={more};{chunk}\x1b\\").unwrap();
+        }
+    }
+    out.push('\n');
+    out
+}
+
+/// Sixel graphics, with colors quantized to a 6x6x6 cube. Transparent pixels are not drawn.
+fn sixel(image: &RgbaImage) -> String {
+    let level = |v: u8| (v as u32 * 5 + 127) / 255;
+    let index = |p: &Rgba<u8>| {
+        (p[3] >= 128).then(|| (level(p[0]) * 36 + level(p[1]) * 6 + level(p[2])) as usize)
+    };
+    let (width, height) = image.dimensions();
+
+    let mut out = format!("\x1bP0;1;0q\"1;1;{width};{height}");
+    let mut defined = [false; 216];
+    for y in (0..height).step_by(6) {
+        let rows = y..(y + 6).min(height);
+
+        // Sixel bits of each column for every color in the band
+        let mut bands: Vec<(usize, Vec<u8>)> = Vec::new();
+        for x in 0..width {
+            for (bit, row) in rows.clone().enumerate() {
+                let Some(color) = index(image.get_pixel(x, row)) else {
+                    continue;
+                };
+                let band = match bands.iter().position(|(c, _)| *c == color) {
+                    Some(i) => &mut bands[i].1,
+                    None => {
+                        bands.push((color, vec![0; width as usize]));
+                        &mut bands.last_mut().unwrap().1
+                    },
+                };
+                band[x as usize] |= 1 << bit;
+            }
+        }
+
+        for (color, bits) in bands {
+            if !defined[color] {
+                defined[color] = true;
+                let [r, g, b] = [color / 36, color / 6 % 6, color % 6].map(|l| l * 20);
+                write!(out, "#{color};2;{r};{g};{b}").unwrap();
+            }
+            write!(out, "#{color}").unwrap();
+            for run in bits.chunk_by(|a, b| a == b) {
+                let c = (63 + run[0]) as char;
+                match run.len() {
+                    n @ 4.. => write!(out, "!{n}{c}").unwrap(),
+                    n => (0..n).for_each(|_| out.push(c)),
+                }
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\\n");
+    out
+}
+
+/// Truecolor half blocks, with the upper pixel as the foreground and the lower as the background.
+fn blocks(image: &RgbaImage) -> String {
+    // Cells are about twice as tall as they are wide, so each half block is square
+    let opaque = |p: &Rgba<u8>| (p[3] >= 128).then_some([p[0], p[1], p[2]]);
+    let mut out = String::new();
+    for y in (0..image.height()).step_by(2) {
+        for x in 0..image.width() {
+            let top = opaque(image.get_pixel(x, y));
+            let bottom = (y + 1 < image.height())
+                .then(|| opaque(image.get_pixel(x, y + 1)))
+                .flatten();
+            match (top, bottom) {
+                (Some([r, g, b]), Some([br, bg, bb])) => {
+                    write!(out, "\x1b[38;2;{r};{g};{b};48;2;{br};{bg};{bb}m▀").unwrap()
+                },
+                (Some([r, g, b]), None) => write!(out, "\x1b[0;38;2;{r};{g};{b}m▀").unwrap(),
+                (None, Some([r, g, b])) => write!(out, "\x1b[0;38;2;{r};{g};{b}m▄").unwrap(),
+                (None, None) => out.push_str("\x1b[0m "),
+            }
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - i * 8));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - i * 6)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
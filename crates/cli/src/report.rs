@@ -0,0 +1,192 @@
+//! Reports of the colors mapped by a patch, to review a theme port without reading hex codes.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::color::Color;
+use crate::literal::Replacement;
+
+/// Distinct source colors and the colors they were mapped to, counted per file.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub palette: String,
+    pub files: Vec<PathBuf>,
+    /// Number of replacements for each mapping, indexed by file
+    pub mappings: BTreeMap<([u8; 3], [u8; 3]), Vec<usize>>,
+}
+
+impl Report {
+    pub fn new(palette: String) -> Self {
+        Self {
+            palette,
+            ..Default::default()
+        }
+    }
+
+    /// Count the replacements made in a file
+    pub fn push(&mut self, path: &Path, replacements: &[Replacement]) {
+        let index = self.files.len();
+        self.files.push(path.to_path_buf());
+        for r in replacements {
+            let counts = self.mappings.entry((r.from, r.to)).or_default();
+            counts.resize(index + 1, 0);
+            counts[index] += 1;
+        }
+    }
+
+    /// Mappings and their total counts, most used first
+    pub fn rows(&self) -> Vec<([u8; 3], [u8; 3], usize)> {
+        let mut rows: Vec<_> = self
+            .mappings
+            .iter()
+            .map(|(&(from, to), counts)| (from, to, counts.iter().sum::<usize>()))
+            .collect();
+        rows.sort_by_key(|&(_, _, count)| std::cmp::Reverse(count));
+        rows
+    }
+
+    /// Count of a mapping in a file
+    fn count(&self, from: [u8; 3], to: [u8; 3], file: usize) -> usize {
+        self.mappings[&(from, to)].get(file).copied().unwrap_or(0)
+    }
+
+    /// Side by side source and mapped colors, with 24-bit ansi swatches if enabled.
+    pub fn text(&self, ansi: bool) -> String {
+        let mut out = String::new();
+        for (from, to, count) in self.rows() {
+            let (from, to) = (Color(from), Color(to));
+            if ansi {
+                writeln!(out, "{} → {}  {count}×", from.swatch(), to.swatch()).unwrap();
+            } else {
+                writeln!(out, "{from} → {to}  {count}×").unwrap();
+            }
+        }
+        out
+    }
+
+    /// Html page with a table of mappings and their counts per file.
+    pub fn html(&self) -> String {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>lutgen patch report: {palette}</title>\n<style>\n\
+             body {{ font-family: monospace; }}\n\
+             td, th {{ padding: 4px 8px; text-align: right; }}\n\
+             .swatch {{ display: inline-block; width: 2em; height: 1em; vertical-align: middle; \
+             margin-right: 0.5em; border: 1px solid #888; }}\n\
+             </style>\n</head>\n<body>\n<h1>{palette}</h1>\n<table>\n<tr><th>From</th><th>To</th>\
+             <th>Total</th>",
+            palette = escape(&self.palette)
+        );
+        for file in &self.files {
+            write!(out, "<th>{}</th>", escape(&file.to_string_lossy())).unwrap();
+        }
+        out.push_str("</tr>\n");
+
+        for (from, to, count) in self.rows() {
+            out.push_str("<tr>");
+            for color in [Color(from), Color(to)] {
+                write!(
+                    out,
+                    "<td><span class=\"swatch\" style=\"background: {color}\"></span>{color}</td>"
+                )
+                .unwrap();
+            }
+            write!(out, "<td>{count}</td>").unwrap();
+            for file in 0..self.files.len() {
+                write!(out, "<td>{}</td>", self.count(from, to, file)).unwrap();
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n</body>\n</html>\n");
+        out
+    }
+
+    /// Svg image with a row of swatches for each mapping, and their counts per file.
+    pub fn svg(&self) -> String {
+        const ROW: usize = 24;
+        const SWATCH: usize = 40;
+        const COLUMN: usize = 96;
+        let width = 2 * (SWATCH + COLUMN) + COLUMN * (self.files.len() + 1);
+        let rows = self.rows();
+        let height = ROW * (rows.len() + 2);
+
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+             font-family=\"monospace\" font-size=\"12\">\n\
+             <text x=\"4\" y=\"16\" font-weight=\"bold\">{}</text>\n",
+            escape(&self.palette)
+        );
+
+        // Header with the total and file names
+        let counts_x = 2 * (SWATCH + COLUMN);
+        let headers =
+            std::iter::once("total".into()).chain(self.files.iter().map(|f| f.to_string_lossy()));
+        for (i, header) in headers.enumerate() {
+            writeln!(
+                out,
+                "<text x=\"{}\" y=\"{}\">{}</text>",
+                counts_x + i * COLUMN,
+                ROW + 16,
+                escape(&header)
+            )
+            .unwrap();
+        }
+
+        for (row, (from, to, count)) in rows.into_iter().enumerate() {
+            let y = ROW * (row + 2);
+            for (i, color) in [Color(from), Color(to)].into_iter().enumerate() {
+                let x = i * (SWATCH + COLUMN);
+                writeln!(
+                    out,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{SWATCH}\" height=\"{}\" fill=\"{color}\" \
+                     stroke=\"#888\"/>\n<text x=\"{}\" y=\"{}\">{color}</text>",
+                    x + 4,
+                    y + 2,
+                    ROW - 4,
+                    x + SWATCH + 8,
+                    y + 16
+                )
+                .unwrap();
+            }
+            let counts = std::iter::once(count)
+                .chain((0..self.files.len()).map(|file| self.count(from, to, file)));
+            for (i, count) in counts.enumerate() {
+                writeln!(
+                    out,
+                    "<text x=\"{}\" y=\"{}\">{count}</text>",
+                    counts_x + i * COLUMN,
+                    y + 16
+                )
+                .unwrap();
+            }
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+
+    /// Save the report as html or svg, depending on the file extension.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        let contents = match extension.as_deref() {
+            Some("html" | "htm") => self.html(),
+            Some("svg") => self.svg(),
+            _ => {
+                return Err(format!(
+                    "unsupported report format for {path:?}, expected .html or .svg"
+                ))
+            },
+        };
+        std::fs::write(path, contents).map_err(|e| format!("failed to write report {path:?}: {e}"))
+    }
+}
+
+/// Escape text for html and svg
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,358 @@
+//! Structured config formats, so patching only rewrites colors in values, and never in keys,
+//! section headers, or comments.
+//!
+//! Formats are scanned by hand rather than parsed, so files are rewritten without touching any
+//! formatting.
+
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::literal::{Literal, Tokenizer};
+
+/// Format of a file being patched.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FileFormat {
+    /// Patch colors anywhere in the file
+    #[default]
+    Plain,
+    Toml,
+    Json,
+    Yaml,
+    /// Also used for foot configs
+    Ini,
+    Xresources,
+    Kitty,
+}
+
+impl Display for FileFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Plain => "plain",
+            Self::Toml => "toml",
+            Self::Json => "json",
+            Self::Yaml => "yaml",
+            Self::Ini => "ini",
+            Self::Xresources => "xresources",
+            Self::Kitty => "kitty",
+        })
+    }
+}
+
+impl FromStr for FileFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "plain" | "text" => Ok(Self::Plain),
+            "toml" | "alacritty" => Ok(Self::Toml),
+            "json" | "jsonc" => Ok(Self::Json),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            "ini" | "foot" => Ok(Self::Ini),
+            "xresources" | "xdefaults" => Ok(Self::Xresources),
+            "kitty" => Ok(Self::Kitty),
+            _ => Err(format!(
+                "unknown file format `{s}`, expected one of: plain, toml, json, yaml, ini, \
+                 xresources, kitty, alacritty, foot"
+            )),
+        }
+    }
+}
+
+impl FileFormat {
+    /// Detect the format of a file from its name, defaulting to plain text.
+    pub fn detect(path: &Path) -> Self {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("toml") => Self::Toml,
+            Some("json" | "jsonc") => Self::Json,
+            Some("yaml" | "yml") => Self::Yaml,
+            Some("ini") => Self::Ini,
+            Some("xresources" | "xdefaults") => Self::Xresources,
+            _ if matches!(name.as_str(), ".xresources" | ".xdefaults" | "xresources") => {
+                Self::Xresources
+            },
+            // Kitty configs and themes, ie `kitty.conf` or `~/.config/kitty/themes/*.conf`
+            Some("conf")
+                if name == "kitty.conf" || path.components().any(|c| c.as_os_str() == "kitty") =>
+            {
+                Self::Kitty
+            },
+            _ => Self::Plain,
+        }
+    }
+
+    /// Byte ranges of the values in some text.
+    pub fn values(&self, text: &str) -> Vec<Range<usize>> {
+        match self {
+            Self::Plain => std::iter::once(0..text.len()).collect(),
+            Self::Toml => toml_values(text),
+            Self::Json => json_values(text),
+            Self::Yaml => yaml_values(text),
+            Self::Ini | Self::Xresources | Self::Kitty => line_values(*self, text),
+        }
+    }
+
+    /// Find color literals in the values of some text.
+    ///
+    /// Structured formats also support values written as bare hex or a color name, ie
+    /// `foreground=ebdbb2` or `cursor = "white"`.
+    pub fn tokenize(&self, tokenizer: &Tokenizer, text: &str) -> Vec<Literal> {
+        if *self == Self::Plain {
+            return tokenizer.tokenize(text);
+        }
+
+        let mut literals = Vec::new();
+        for range in self.values(text) {
+            let value = &text[range.clone()];
+            let mut offset = range.start;
+            let mut found = tokenizer.tokenize(value);
+            if found.is_empty() {
+                let unquoted = value.trim_start_matches(['"', '\'']);
+                offset += value.len() - unquoted.len();
+                found = tokenizer
+                    .tokenize_value(unquoted.trim_end_matches(['"', '\'']))
+                    .into_iter()
+                    .collect();
+            }
+
+            literals.extend(found.into_iter().map(|mut literal| {
+                literal.range = literal.range.start + offset..literal.range.end + offset;
+                literal
+            }));
+        }
+        literals
+    }
+}
+
+/// Iterate over lines and their byte offsets, without line endings
+fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split_inclusive('\n').scan(0, |pos, line| {
+        let start = *pos;
+        *pos += line.len();
+        Some((start, line.trim_end_matches(['\n', '\r'])))
+    })
+}
+
+/// Range of some text at an offset, without surrounding whitespace
+fn trimmed(offset: usize, text: &str) -> Option<Range<usize>> {
+    let start = offset + text.len() - text.trim_start().len();
+    let len = text.trim().len();
+    (len > 0).then_some(start..start + len)
+}
+
+/// Index after the end of a quoted string starting at `start`, supporting escapes and toml's
+/// multiline strings. Unterminated strings end at the end of the line.
+fn string_end(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let triple = [quote; 3];
+    if bytes[start..].starts_with(&triple) {
+        let mut i = start + 3;
+        while i < bytes.len() {
+            if quote == b'"' && bytes[i] == b'\\' {
+                i += 2;
+                continue;
+            }
+            if bytes[i..].starts_with(&triple) {
+                return i + 3;
+            }
+            i += 1;
+        }
+        return bytes.len();
+    }
+
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if quote == b'"' => i += 1,
+            b'\n' => return i,
+            b if b == quote => return i + 1,
+            _ => {},
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// Values in line based `key = value` formats
+fn line_values(format: FileFormat, text: &str) -> Vec<Range<usize>> {
+    lines(text)
+        .filter_map(|(offset, line)| {
+            let content = line.trim_start();
+            let indent = line.len() - content.len();
+            let start = match format {
+                FileFormat::Ini => {
+                    if content.starts_with(['#', ';', '[']) {
+                        return None;
+                    }
+                    content.find('=')? + 1
+                },
+                FileFormat::Xresources => {
+                    if let Some(define) = content.strip_prefix("#define") {
+                        // `#define NAME VALUE`
+                        let name = define.trim_start();
+                        content.len() - name.len() + name.find(char::is_whitespace)?
+                    } else if content.starts_with(['!', '#']) {
+                        return None;
+                    } else {
+                        content.find(':')? + 1
+                    }
+                },
+                FileFormat::Kitty => {
+                    if content.starts_with('#') {
+                        return None;
+                    }
+                    content.find(char::is_whitespace)?
+                },
+                _ => unreachable!(),
+            };
+            trimmed(offset + indent + start, &content[start..])
+        })
+        .collect()
+}
+
+/// Values in toml, including multiline arrays and inline tables
+fn toml_values(text: &str) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut values = Vec::new();
+    let mut push = |range: Range<usize>| values.extend(trimmed(range.start, &text[range]));
+
+    let (mut in_value, mut depth, mut start) = (false, 0usize, 0);
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            // Skip strings, so quoted keys and values can contain any character
+            b'"' | b'\'' => {
+                i = string_end(bytes, i);
+                continue;
+            },
+            b'#' => {
+                // Skip comments
+                if in_value {
+                    push(start..i);
+                }
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                start = i;
+                continue;
+            },
+            b'=' if !in_value => {
+                in_value = true;
+                start = i + 1;
+            },
+            b'[' | b'{' if in_value => depth += 1,
+            b']' | b'}' if in_value => depth = depth.saturating_sub(1),
+            b'\n' if in_value => {
+                push(start..i);
+                start = i + 1;
+                in_value = depth > 0;
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+    if in_value {
+        push(start..bytes.len());
+    }
+    values
+}
+
+/// String values in json, skipping keys and (jsonc) comments
+fn json_values(text: &str) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut values = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'"', _) => {
+                let end = string_end(bytes, i);
+                // Keys are followed by a colon
+                if !text[end..].trim_start().starts_with(':') {
+                    values.push(i + 1..(end - 1).max(i + 1));
+                }
+                i = end;
+                continue;
+            },
+            (b'/', Some(b'/')) => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            },
+            (b'/', Some(b'*')) => {
+                i = text[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 2);
+                continue;
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+    values
+}
+
+/// Values in yaml, including list items and flow sequences
+fn yaml_values(text: &str) -> Vec<Range<usize>> {
+    lines(text)
+        .filter_map(|(offset, line)| {
+            // Strip comments, which start with a `#` at the start of the line or after whitespace
+            let mut quote = None;
+            let mut end = line.len();
+            let mut prev = ' ';
+            for (i, c) in line.char_indices() {
+                match (quote, c) {
+                    (None, '"' | '\'') => quote = Some(c),
+                    (Some(q), c) if q == c => quote = None,
+                    (None, '#') if prev.is_whitespace() => {
+                        end = i;
+                        break;
+                    },
+                    _ => {},
+                }
+                prev = c;
+            }
+            let line = &line[..end];
+
+            let content = line.trim_start();
+            if content.starts_with("---") || content.starts_with("...") {
+                return None;
+            }
+
+            // Skip list item markers and keys
+            let mut start = line.len() - content.len();
+            while line[start..].starts_with("- ") {
+                start += 2;
+                start += line[start..].len() - line[start..].trim_start().len();
+            }
+            if let Some(colon) = key_end(&line[start..]) {
+                start += colon;
+            }
+            trimmed(offset + start, &line[start..])
+        })
+        .collect()
+}
+
+/// Index after the colon ending a yaml key, ie `key: value`
+fn key_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, ':') if chars.peek().is_none_or(|(_, c)| c.is_whitespace()) => {
+                return Some(i + 1)
+            },
+            _ => {},
+        }
+    }
+    None
+}
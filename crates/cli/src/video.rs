@@ -0,0 +1,427 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use rayon::prelude::*;
+
+use crate::input::is_stdio;
+
+/// Signature at the start of a YUV4MPEG2 stream.
+const Y4M_MAGIC: &[u8] = b"YUV4MPEG2";
+
+/// Largest frame accepted, in bytes. 8K rgb24 frames are about 100MB, so larger sizes are most
+/// likely a corrupt header, and are rejected before allocating anything.
+const MAX_FRAME_LEN: usize = 1 << 30;
+
+/// Size of the frames in a raw video stream, written as `WIDTHxHEIGHT`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct FrameSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Display for FrameSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl FromStr for FrameSize {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid frame size `{s}`, expected WIDTHxHEIGHT");
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(err)?;
+        let (width, height) = (
+            width.parse().map_err(|_| err())?,
+            height.parse().map_err(|_| err())?,
+        );
+        if width == 0 || height == 0 {
+            return Err(err());
+        }
+        let size = Self { width, height };
+        size.frame_len()
+            .ok_or_else(|| format!("frame size `{s}` is too large"))?;
+        Ok(size)
+    }
+}
+
+impl FrameSize {
+    /// Length of an rgb24 frame in bytes, or `None` if it is larger than [`MAX_FRAME_LEN`].
+    fn frame_len(&self) -> Option<usize> {
+        (self.width as usize)
+            .checked_mul(self.height as usize)?
+            .checked_mul(3)
+            .filter(|&len| len <= MAX_FRAME_LEN)
+    }
+}
+
+/// Video stream formats that are corrected frame by frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    /// YUV4MPEG2, as written by ffmpeg's `yuv4mpegpipe` muxer
+    Y4m,
+    /// Packed 8 bit rgb frames without any header, like ffmpeg's `-f rawvideo -pix_fmt rgb24`
+    Rgb24(FrameSize),
+}
+
+impl Stream {
+    /// Detect a y4m stream from the file extension, or by peeking at the start of stdin.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let y4m = if is_stdio(path) {
+            // Peeking leaves the bytes in the stdin buffer for the image or stream reader
+            std::io::stdin()
+                .lock()
+                .fill_buf()
+                .is_ok_and(|buf| buf.starts_with(Y4M_MAGIC))
+        } else {
+            path.extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("y4m"))
+        };
+        y4m.then_some(Stream::Y4m)
+    }
+}
+
+/// Correct a video stream read from a file or stdin, and write it to a file or stdout.
+pub fn correct_video(
+    input: &Path,
+    output: &Path,
+    stream: Stream,
+    correct: impl Fn([u8; 3]) -> [u8; 3] + Sync,
+) -> Result<usize, String> {
+    let reader: Box<dyn BufRead> = if is_stdio(input) {
+        Box::new(std::io::stdin().lock())
+    } else {
+        let file = File::open(input).map_err(|e| format!("failed to open video: {e}"))?;
+        Box::new(BufReader::new(file))
+    };
+    let writer: Box<dyn Write> = if is_stdio(output) {
+        Box::new(BufWriter::new(std::io::stdout().lock()))
+    } else {
+        let file = File::create(output).map_err(|e| format!("failed to write {output:?}: {e}"))?;
+        Box::new(BufWriter::new(file))
+    };
+    correct_stream(reader, writer, stream, correct)
+}
+
+/// Correct every frame of a video stream with a function mapping rgb pixels, writing the stream
+/// back out in the same format. Batches of frames are corrected in parallel. Returns the number
+/// of frames written.
+pub fn correct_stream(
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+    stream: Stream,
+    correct: impl Fn([u8; 3]) -> [u8; 3] + Sync,
+) -> Result<usize, String> {
+    let layout = match stream {
+        Stream::Rgb24(size) => Layout::Rgb24(size),
+        Stream::Y4m => {
+            let mut header = Vec::new();
+            reader
+                .read_until(b'\n', &mut header)
+                .map_err(|e| format!("failed to read y4m header: {e}"))?;
+            let y4m = Y4m::parse(&header)?;
+            writer
+                .write_all(&header)
+                .map_err(|e| format!("failed to write y4m header: {e}"))?;
+            Layout::Y4m(y4m)
+        },
+    };
+
+    let batch = rayon::current_num_threads().max(1);
+    let mut count = 0;
+    loop {
+        let mut frames = Vec::with_capacity(batch);
+        while frames.len() < batch {
+            match read_frame(&mut reader, &layout, count + frames.len())? {
+                Some(frame) => frames.push(frame),
+                None => break,
+            }
+        }
+        if frames.is_empty() {
+            break;
+        }
+
+        frames
+            .par_iter_mut()
+            .for_each(|Frame { data, .. }| match &layout {
+                Layout::Y4m(y4m) => y4m.correct(data, &correct),
+                Layout::Rgb24(_) => data.chunks_exact_mut(3).for_each(|px| {
+                    px.copy_from_slice(&correct([px[0], px[1], px[2]]));
+                }),
+            });
+
+        for Frame { header, data } in &frames {
+            writer
+                .write_all(header)
+                .and_then(|_| writer.write_all(data))
+                .map_err(|e| format!("failed to write frame: {e}"))?;
+        }
+        count += frames.len();
+    }
+
+    writer
+        .flush()
+        .map_err(|e| format!("failed to write frame: {e}"))?;
+    Ok(count)
+}
+
+/// Frame data, with the frame header of y4m streams.
+struct Frame {
+    header: Vec<u8>,
+    data: Vec<u8>,
+}
+
+enum Layout {
+    Rgb24(FrameSize),
+    Y4m(Y4m),
+}
+
+/// Read the header and data of the next frame, or `None` at the end of the stream.
+fn read_frame(
+    reader: &mut impl BufRead,
+    layout: &Layout,
+    index: usize,
+) -> Result<Option<Frame>, String> {
+    let at_end = reader
+        .fill_buf()
+        .map_err(|e| format!("failed to read frame: {e}"))?
+        .is_empty();
+    if at_end {
+        return Ok(None);
+    }
+
+    let (header, len) = match layout {
+        Layout::Rgb24(size) => {
+            let len = size
+                .frame_len()
+                .ok_or_else(|| format!("frame size {size} is too large"))?;
+            (Vec::new(), len)
+        },
+        Layout::Y4m(y4m) => {
+            let mut header = Vec::new();
+            reader
+                .read_until(b'\n', &mut header)
+                .map_err(|e| format!("failed to read frame header: {e}"))?;
+            if !header.starts_with(b"FRAME") || !header.ends_with(b"\n") {
+                return Err(format!("invalid y4m frame header for frame {}", index + 1));
+            }
+            (header, y4m.frame_len)
+        },
+    };
+
+    let mut data = vec![0; len];
+    reader.read_exact(&mut data).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => format!("stream ended in the middle of frame {}", index + 1),
+        _ => format!("failed to read frame: {e}"),
+    })?;
+    Ok(Some(Frame { header, data }))
+}
+
+/// Chroma subsampling of a y4m stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Chroma {
+    /// One chroma sample for every 2x2 pixels
+    C420,
+    /// One chroma sample for every 2x1 pixels
+    C422,
+    C444,
+    /// Full resolution chroma and an alpha plane, which is passed through
+    C444Alpha,
+    /// Luma only
+    Mono,
+}
+
+impl Chroma {
+    /// Horizontal and vertical subsampling factors.
+    fn factors(&self) -> (usize, usize) {
+        match self {
+            Chroma::C420 => (2, 2),
+            Chroma::C422 => (2, 1),
+            Chroma::C444 | Chroma::C444Alpha | Chroma::Mono => (1, 1),
+        }
+    }
+}
+
+/// Properties of a y4m stream needed to convert frames to and from rgb.
+#[derive(Clone, Copy, Debug)]
+struct Y4m {
+    width: usize,
+    height: usize,
+    chroma: Chroma,
+    /// Length of the frame data in bytes, checked against [`MAX_FRAME_LEN`]
+    frame_len: usize,
+    /// Full range samples, instead of the usual limited range
+    full_range: bool,
+    /// Luma coefficients of red and blue. Y4M does not record the color matrix, so BT.709 is
+    /// assumed for HD frames and BT.601 for smaller ones.
+    kr: f32,
+    kb: f32,
+}
+
+impl Y4m {
+    fn parse(header: &[u8]) -> Result<Self, String> {
+        let header = std::str::from_utf8(header).map_err(|_| "invalid y4m header")?;
+        let mut params = header.trim_end().split(' ');
+        if params.next() != Some("YUV4MPEG2") {
+            return Err("input is not a y4m stream".into());
+        }
+
+        let (mut width, mut height) = (0, 0);
+        let (mut chroma, mut full_range) = (Chroma::C420, false);
+        for param in params.filter(|p| !p.is_empty()) {
+            let mut chars = param.chars();
+            let (tag, value) = (chars.next(), chars.as_str());
+            match tag {
+                Some('W') => {
+                    width = value
+                        .parse()
+                        .map_err(|_| format!("invalid y4m width {value}"))?
+                },
+                Some('H') => {
+                    height = value
+                        .parse()
+                        .map_err(|_| format!("invalid y4m height {value}"))?
+                },
+                Some('C') => {
+                    chroma = match value {
+                        "420" | "420jpeg" | "420mpeg2" | "420paldv" => Chroma::C420,
+                        "422" => Chroma::C422,
+                        "444" => Chroma::C444,
+                        "444alpha" => Chroma::C444Alpha,
+                        "mono" => Chroma::Mono,
+                        _ => return Err(format!("unsupported y4m colorspace `{value}`")),
+                    }
+                },
+                Some('X') => {
+                    if let Some(range) = value.strip_prefix("COLORRANGE=") {
+                        full_range = range.eq_ignore_ascii_case("full");
+                    }
+                },
+                _ => {},
+            }
+        }
+        if width == 0 || height == 0 {
+            return Err("y4m header is missing the frame size".into());
+        }
+
+        let (kr, kb) = if height >= 720 {
+            (0.2126, 0.0722)
+        } else {
+            (0.299, 0.114)
+        };
+        let mut y4m = Self {
+            width,
+            height,
+            chroma,
+            frame_len: 0,
+            full_range,
+            kr,
+            kb,
+        };
+        y4m.frame_len = y4m
+            .checked_frame_len()
+            .filter(|&len| len <= MAX_FRAME_LEN)
+            .ok_or_else(|| format!("y4m frame size {width}x{height} is too large"))?;
+        Ok(y4m)
+    }
+
+    /// Size of the chroma planes.
+    fn chroma_size(&self) -> (usize, usize) {
+        let (sx, sy) = self.chroma.factors();
+        (self.width.div_ceil(sx), self.height.div_ceil(sy))
+    }
+
+    /// Length of the frame data in bytes, or `None` if it overflows.
+    fn checked_frame_len(&self) -> Option<usize> {
+        let luma = self.width.checked_mul(self.height)?;
+        let (cw, ch) = self.chroma_size();
+        let chroma = cw.checked_mul(ch)?.checked_mul(2)?;
+        match self.chroma {
+            Chroma::Mono => Some(luma),
+            Chroma::C444Alpha => luma.checked_mul(2)?.checked_add(chroma),
+            _ => luma.checked_add(chroma),
+        }
+    }
+
+    /// Correct a frame in place. Each chroma sample is converted with the pixels it covers, then
+    /// set to the average chroma of the corrected pixels.
+    fn correct(&self, frame: &mut [u8], correct: impl Fn([u8; 3]) -> [u8; 3]) {
+        let (luma, rest) = frame.split_at_mut(self.width * self.height);
+        if self.chroma == Chroma::Mono {
+            for y in luma {
+                *y = self.rgb_to_yuv(correct(self.yuv_to_rgb(*y, 128, 128)))[0];
+            }
+            return;
+        }
+
+        let (sx, sy) = self.chroma.factors();
+        let (cw, ch) = self.chroma_size();
+        let (u_plane, rest) = rest.split_at_mut(cw * ch);
+        let v_plane = &mut rest[..cw * ch];
+        for (cy, cx) in (0..ch).flat_map(|cy| (0..cw).map(move |cx| (cy, cx))) {
+            let c = cy * cw + cx;
+            let (mut u_sum, mut v_sum, mut n) = (0., 0., 0.);
+            for y in cy * sy..((cy + 1) * sy).min(self.height) {
+                for x in cx * sx..((cx + 1) * sx).min(self.width) {
+                    let i = y * self.width + x;
+                    let rgb = correct(self.yuv_to_rgb(luma[i], u_plane[c], v_plane[c]));
+                    let [l, u, v] = self.rgb_to_ycbcr(rgb);
+                    luma[i] = self.quantize_luma(l);
+                    (u_sum, v_sum, n) = (u_sum + u, v_sum + v, n + 1.);
+                }
+            }
+            u_plane[c] = self.quantize_chroma(u_sum / n);
+            v_plane[c] = self.quantize_chroma(v_sum / n);
+        }
+    }
+
+    fn yuv_to_rgb(&self, y: u8, u: u8, v: u8) -> [u8; 3] {
+        let (y, cb, cr) = if self.full_range {
+            (y as f32, u as f32 - 128., v as f32 - 128.)
+        } else {
+            (
+                (y as f32 - 16.) * 255. / 219.,
+                (u as f32 - 128.) * 255. / 224.,
+                (v as f32 - 128.) * 255. / 224.,
+            )
+        };
+        let (kr, kb) = (self.kr, self.kb);
+        let r = y + 2. * (1. - kr) * cr;
+        let b = y + 2. * (1. - kb) * cb;
+        let g = (y - kr * r - kb * b) / (1. - kr - kb);
+        [r, g, b].map(|c| c.round().clamp(0., 255.) as u8)
+    }
+
+    /// Luma and centered chroma of a pixel, in the 0 to 255 scale.
+    fn rgb_to_ycbcr(&self, [r, g, b]: [u8; 3]) -> [f32; 3] {
+        let [r, g, b] = [r, g, b].map(|c| c as f32);
+        let (kr, kb) = (self.kr, self.kb);
+        let y = kr * r + (1. - kr - kb) * g + kb * b;
+        [y, (b - y) / (2. * (1. - kb)), (r - y) / (2. * (1. - kr))]
+    }
+
+    fn rgb_to_yuv(&self, rgb: [u8; 3]) -> [u8; 3] {
+        let [y, cb, cr] = self.rgb_to_ycbcr(rgb);
+        [
+            self.quantize_luma(y),
+            self.quantize_chroma(cb),
+            self.quantize_chroma(cr),
+        ]
+    }
+
+    fn quantize_luma(&self, y: f32) -> u8 {
+        let y = if self.full_range {
+            y
+        } else {
+            16. + y * 219. / 255.
+        };
+        y.round().clamp(0., 255.) as u8
+    }
+
+    fn quantize_chroma(&self, c: f32) -> u8 {
+        let c = if self.full_range { c } else { c * 224. / 255. };
+        (128. + c).round().clamp(0., 255.) as u8
+    }
+}
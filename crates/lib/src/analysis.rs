@@ -0,0 +1,358 @@
+//! Palette quality analysis: contrast, distinctness, coverage, and how well sRGB maps onto a
+//! palette.
+//!
+//! ```rust
+//! use lutgen::analysis::analyze;
+//!
+//! let palette = [
+//!     [0x28, 0x28, 0x28],
+//!     [0xeb, 0xdb, 0xb2],
+//!     [0xcc, 0x24, 0x1d],
+//!     [0xfb, 0x49, 0x34],
+//! ];
+//! let analysis = analyze(&palette, None, None);
+//!
+//! // Darkest and lightest colors are used as backgrounds
+//! assert_eq!(analysis.contrast[0].bg, [0x28, 0x28, 0x28]);
+//! assert!(analysis.contrast[0].wcag > 1.);
+//! assert!(analysis.closest.unwrap().delta_e > 0.);
+//! ```
+
+use image::RgbImage;
+use oklab::Oklab;
+
+use crate::identity::{correct_pixel, detect_level};
+use crate::ops::{distance, to_oklab};
+
+/// Pairs of colors closer than this perceptual difference are flagged as near duplicates.
+pub const NEAR_DUPLICATE: f32 = 5.0;
+/// Minimum chroma for a color to count towards hue coverage.
+pub const MIN_CHROMA: f32 = 0.04;
+/// Hue gaps smaller than this many degrees are not reported.
+pub const MIN_HUE_GAP: f32 = 45.0;
+/// Number of samples per channel when checking how sRGB maps onto the palette.
+const GRID_STEPS: u8 = 9;
+/// Number of poorly mapped regions to report.
+const MAX_REGIONS: usize = 8;
+
+/// Contrast between a foreground and background color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contrast {
+    pub fg: [u8; 3],
+    pub bg: [u8; 3],
+    /// WCAG 2 contrast ratio, from 1 to 21. 4.5 is needed for AA body text, 7 for AAA.
+    pub wcag: f32,
+    /// APCA lightness contrast (Lc), from about -108 to 106. Negative for light text on dark
+    /// backgrounds. An absolute value of 75 is recommended for body text.
+    pub apca: f32,
+}
+
+/// Perceptual difference between two palette colors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pair {
+    pub a: [u8; 3],
+    pub b: [u8; 3],
+    pub delta_e: f32,
+}
+
+/// Summary of a set of values, with a fixed range histogram.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Distribution {
+    pub min: f32,
+    pub mean: f32,
+    pub max: f32,
+    pub histogram: [usize; 8],
+}
+
+/// Range of hues, in Oklab degrees, without any palette colors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HueGap {
+    pub start: f32,
+    pub size: f32,
+}
+
+/// An sRGB color that maps poorly onto the palette.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub color: [u8; 3],
+    pub mapped: [u8; 3],
+    pub delta_e: f32,
+}
+
+/// Palette analysis, see [`analyze`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Analysis {
+    /// Contrast of every other color on each background candidate.
+    pub contrast: Vec<Contrast>,
+    /// Most similar pair of colors.
+    pub closest: Option<Pair>,
+    /// Pairs of colors within [`NEAR_DUPLICATE`] of each other.
+    pub near_duplicates: Vec<Pair>,
+    /// Oklab lightness, from 0 to 1.
+    pub lightness: Distribution,
+    /// Oklab chroma, with the histogram covering 0 to 0.32.
+    pub chroma: Distribution,
+    /// Hue ranges not covered by any colorful palette color, largest first.
+    pub hue_gaps: Vec<HueGap>,
+    /// Mean perceptual difference when remapping an evenly spaced sRGB grid.
+    pub remap_error: f32,
+    /// Distinct sRGB colors with the largest remap errors, worst first.
+    pub poor_regions: Vec<Region>,
+}
+
+/// Analyze a palette.
+///
+/// Contrast is computed against the given background, or both the darkest and lightest palette
+/// colors. When a hald clut is given, remap errors are measured through it, which accounts for
+/// the algorithm it was generated with. Otherwise, colors are mapped to the nearest palette color.
+pub fn analyze(
+    colors: &[[u8; 3]],
+    background: Option<[u8; 3]>,
+    lut: Option<&RgbImage>,
+) -> Analysis {
+    if colors.is_empty() {
+        return Analysis::default();
+    }
+    let lab: Vec<Oklab> = colors.iter().map(|&c| to_oklab(c)).collect();
+
+    // Contrast
+    let backgrounds = match background {
+        Some(bg) => vec![bg],
+        None => {
+            let by_lightness =
+                |a: &&([u8; 3], Oklab), b: &&([u8; 3], Oklab)| a.1.l.total_cmp(&b.1.l);
+            let pairs: Vec<_> = colors.iter().copied().zip(lab.iter().copied()).collect();
+            let darkest = pairs.iter().min_by(by_lightness).unwrap().0;
+            let lightest = pairs.iter().max_by(by_lightness).unwrap().0;
+            if darkest == lightest {
+                vec![darkest]
+            } else {
+                vec![darkest, lightest]
+            }
+        },
+    };
+    let contrast = backgrounds
+        .iter()
+        .flat_map(|&bg| {
+            colors
+                .iter()
+                .filter(move |&&fg| fg != bg)
+                .map(move |&fg| Contrast {
+                    fg,
+                    bg,
+                    wcag: wcag_contrast(fg, bg),
+                    apca: apca_contrast(fg, bg),
+                })
+        })
+        .collect();
+
+    // Distinctness
+    let mut pairs = Vec::new();
+    for i in 0..colors.len() {
+        for j in i + 1..colors.len() {
+            pairs.push(Pair {
+                a: colors[i],
+                b: colors[j],
+                delta_e: distance(&lab[i], &lab[j]) * 100.,
+            });
+        }
+    }
+    pairs.sort_by(|a, b| a.delta_e.total_cmp(&b.delta_e));
+    let closest = pairs.first().copied();
+    let near_duplicates = pairs
+        .into_iter()
+        .take_while(|p| p.delta_e < NEAR_DUPLICATE)
+        .collect();
+
+    // Distributions
+    let lightness = distribution(lab.iter().map(|c| c.l), 1.);
+    let chroma = distribution(lab.iter().map(chroma_of), 0.32);
+
+    // Hue coverage
+    let mut hues: Vec<f32> = lab
+        .iter()
+        .filter(|c| chroma_of(c) >= MIN_CHROMA)
+        .map(|c| c.b.atan2(c.a).to_degrees().rem_euclid(360.))
+        .collect();
+    hues.sort_by(f32::total_cmp);
+    let mut hue_gaps: Vec<HueGap> = match hues.as_slice() {
+        [] => vec![HueGap {
+            start: 0.,
+            size: 360.,
+        }],
+        [.., last] => std::iter::once((*last, hues[0] + 360.))
+            .chain(hues.windows(2).map(|w| (w[0], w[1])))
+            .map(|(start, end)| HueGap {
+                start,
+                size: end - start,
+            })
+            .filter(|gap| gap.size >= MIN_HUE_GAP)
+            .collect(),
+    };
+    hue_gaps.sort_by(|a, b| b.size.total_cmp(&a.size));
+
+    // Remap coverage over an sRGB grid
+    let level = lut.map(detect_level);
+    let step = |i: u8| (i as u32 * 255 / (GRID_STEPS as u32 - 1)) as u8;
+    let mut samples = Vec::with_capacity((GRID_STEPS as usize).pow(3));
+    for r in 0..GRID_STEPS {
+        for g in 0..GRID_STEPS {
+            for b in 0..GRID_STEPS {
+                let color = [step(r), step(g), step(b)];
+                let c = to_oklab(color);
+                let mapped = match (lut, level) {
+                    (Some(lut), Some(level)) => correct_pixel(&color, lut, level),
+                    _ => {
+                        let nearest = lab
+                            .iter()
+                            .enumerate()
+                            .min_by(|a, b| distance(&c, a.1).total_cmp(&distance(&c, b.1)))
+                            .unwrap()
+                            .0;
+                        colors[nearest]
+                    },
+                };
+                let delta_e = distance(&c, &to_oklab(mapped)) * 100.;
+                samples.push(Region {
+                    color,
+                    mapped,
+                    delta_e,
+                });
+            }
+        }
+    }
+    let remap_error = samples.iter().map(|s| s.delta_e).sum::<f32>() / samples.len() as f32;
+
+    // Report the worst samples that are distinct from each other
+    samples.sort_by(|a, b| b.delta_e.total_cmp(&a.delta_e));
+    let mut poor_regions: Vec<Region> = Vec::new();
+    for sample in samples {
+        if poor_regions.len() == MAX_REGIONS || sample.delta_e < remap_error {
+            break;
+        }
+        let c = to_oklab(sample.color);
+        if poor_regions
+            .iter()
+            .all(|r| distance(&c, &to_oklab(r.color)) * 100. >= 20.)
+        {
+            poor_regions.push(sample);
+        }
+    }
+
+    Analysis {
+        contrast,
+        closest,
+        near_duplicates,
+        lightness,
+        chroma,
+        hue_gaps,
+        remap_error,
+        poor_regions,
+    }
+}
+
+fn chroma_of(c: &Oklab) -> f32 {
+    (c.a * c.a + c.b * c.b).sqrt()
+}
+
+fn distribution(values: impl Iterator<Item = f32>, range: f32) -> Distribution {
+    let mut dist = Distribution {
+        min: f32::INFINITY,
+        max: f32::NEG_INFINITY,
+        ..Default::default()
+    };
+    let mut count = 0;
+    for v in values {
+        dist.min = dist.min.min(v);
+        dist.max = dist.max.max(v);
+        dist.mean += v;
+        let bucket = (v / range * dist.histogram.len() as f32) as usize;
+        dist.histogram[bucket.min(dist.histogram.len() - 1)] += 1;
+        count += 1;
+    }
+    dist.mean /= count.max(1) as f32;
+    dist
+}
+
+/// WCAG 2 contrast ratio between two colors.
+pub fn wcag_contrast(a: [u8; 3], b: [u8; 3]) -> f32 {
+    let luminance = |c: [u8; 3]| {
+        let [r, g, b] = c.map(|v| {
+            let v = v as f32 / 255.;
+            if v <= 0.04045 {
+                v / 12.92
+            } else {
+                ((v + 0.055) / 1.055).powf(2.4)
+            }
+        });
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    };
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// APCA (0.0.98G) lightness contrast of text on a background.
+pub fn apca_contrast(text: [u8; 3], background: [u8; 3]) -> f32 {
+    let luminance = |c: [u8; 3]| {
+        let [r, g, b] = c.map(|v| (v as f32 / 255.).powf(2.4));
+        let y = 0.2126729 * r + 0.7151522 * g + 0.072175 * b;
+        // Soft clamp near black
+        if y < 0.022 {
+            y + (0.022 - y).powf(1.414)
+        } else {
+            y
+        }
+    };
+    let (text, bg) = (luminance(text), luminance(background));
+    if (bg - text).abs() < 0.0005 {
+        return 0.;
+    }
+
+    let contrast = if bg > text {
+        // Dark text on a light background
+        let sapc = (bg.powf(0.56) - text.powf(0.57)) * 1.14;
+        if sapc < 0.1 {
+            0.
+        } else {
+            sapc - 0.027
+        }
+    } else {
+        // Light text on a dark background
+        let sapc = (bg.powf(0.65) - text.powf(0.62)) * 1.14;
+        if sapc > -0.1 {
+            0.
+        } else {
+            sapc + 0.027
+        }
+    };
+    contrast * 100.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_analysis() {
+        let (black, white) = ([0, 0, 0], [255, 255, 255]);
+        assert!((wcag_contrast(white, black) - 21.).abs() < 0.01);
+        assert!((apca_contrast(black, white) - 106.04).abs() < 0.1);
+        assert!((apca_contrast(white, black) + 107.88).abs() < 0.1);
+
+        let colors = [black, white, [0xcc, 0x24, 0x1d], [0xcd, 0x25, 0x1e]];
+        let report = analyze(&colors, None, None);
+        // Every other color on the darkest and lightest colors
+        assert_eq!(report.contrast.len(), 6);
+        assert_eq!(report.near_duplicates.len(), 1);
+        assert_eq!(report.closest, report.near_duplicates.first().copied());
+        // Only reds are colorful, leaving a large hue gap
+        assert!(report.hue_gaps[0].size > 300.);
+        assert!(report.poor_regions.len() <= 8);
+        assert!(report.poor_regions[0].delta_e >= report.remap_error);
+
+        // A given background replaces the darkest and lightest candidates
+        let report = analyze(&colors, Some(white), None);
+        assert_eq!(report.contrast.len(), 3);
+        assert!(report.contrast.iter().all(|c| c.bg == white));
+    }
+}
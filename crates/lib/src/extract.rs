@@ -0,0 +1,648 @@
+//! Palette extraction from images, with a choice of quantizers.
+//!
+//! ```rust
+//! use lutgen::extract::{extract, ExtractOptions, Quantizer};
+//!
+//! let mut pixels = vec![[255, 0, 0]; 30];
+//! pixels.extend([[0, 0, 255]; 10]);
+//!
+//! let options = ExtractOptions {
+//!     quantizer: Quantizer::Wu,
+//!     count: 2,
+//!     ..Default::default()
+//! };
+//! let colors = extract(&pixels, &options);
+//!
+//! // Colors are sorted by how much of the image they cover
+//! assert_eq!(colors[0].color, [255, 0, 0]);
+//! assert_eq!(colors[0].weight, 0.75);
+//! assert_eq!(colors[1].color, [0, 0, 255]);
+//! ```
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use oklab::Oklab;
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+
+use crate::ops::{delta_e, squared_distance, to_oklab, to_srgb};
+
+/// Colors with an Oklab lightness under this are skipped with [`ExtractOptions::ignore_black`].
+pub const NEAR_BLACK: f32 = 0.15;
+/// Colors with an Oklab lightness over this are skipped with [`ExtractOptions::ignore_white`].
+pub const NEAR_WHITE: f32 = 0.95;
+/// Pixels are binned with this many bits per channel before quantizing.
+const BITS: usize = 5;
+/// Maximum number of k-means iterations.
+const MAX_ITERATIONS: usize = 32;
+
+/// Algorithm used to reduce an image to a palette.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Quantizer {
+    /// K-means clustering in Oklab, seeded with k-means++. Slowest, but usually the most
+    /// accurate.
+    #[default]
+    KMeans,
+    /// Repeatedly split the box with the widest range of colors at its median.
+    MedianCut,
+    /// Wu's quantizer, which splits boxes to minimize the variance of each color. Fast and
+    /// accurate.
+    Wu,
+    /// Merge the least common leaves of a color octree, which favors common colors.
+    Octree,
+}
+
+impl Quantizer {
+    pub const ALL: &'static [Quantizer] = &[
+        Quantizer::KMeans,
+        Quantizer::MedianCut,
+        Quantizer::Wu,
+        Quantizer::Octree,
+    ];
+}
+
+impl Display for Quantizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Quantizer::KMeans => "kmeans",
+            Quantizer::MedianCut => "median-cut",
+            Quantizer::Wu => "wu",
+            Quantizer::Octree => "octree",
+        })
+    }
+}
+
+impl FromStr for Quantizer {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Quantizer::ALL
+            .iter()
+            .find(|q| q.to_string().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| {
+                let all: Vec<_> = Quantizer::ALL.iter().map(ToString::to_string).collect();
+                format!(
+                    "unknown quantizer `{s}`, expected one of {}",
+                    all.join(", ")
+                )
+            })
+    }
+}
+
+/// Options for [`extract`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExtractOptions {
+    pub quantizer: Quantizer,
+    /// Maximum number of colors to extract.
+    pub count: usize,
+    /// Weight colors by how often they appear. Otherwise every distinct color counts the same,
+    /// which favors small details over large areas.
+    pub weighted: bool,
+    /// Skip colors darker than [`NEAR_BLACK`].
+    pub ignore_black: bool,
+    /// Skip colors lighter than [`NEAR_WHITE`].
+    pub ignore_white: bool,
+    /// Merge extracted colors closer than this perceptual difference, see [`delta_e`].
+    pub min_delta_e: f32,
+    /// Seed for the k-means initialization. Other quantizers are deterministic.
+    pub seed: u64,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            quantizer: Quantizer::default(),
+            count: 16,
+            weighted: true,
+            ignore_black: false,
+            ignore_white: false,
+            min_delta_e: 0.,
+            seed: 0,
+        }
+    }
+}
+
+/// Extracted color, and how much of the image it represents.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WeightedColor {
+    pub color: [u8; 3],
+    /// Share of the (weighted) pixels closest to this color. Weights of all colors add up to 1.
+    pub weight: f32,
+}
+
+/// Mean color and weight of a set of pixels.
+#[derive(Clone, Copy, Debug, Default)]
+struct Sample {
+    rgb: [f64; 3],
+    weight: f64,
+}
+
+impl Sample {
+    fn add(&mut self, rgb: [f64; 3], weight: f64) {
+        for (sum, v) in self.rgb.iter_mut().zip(rgb) {
+            *sum += v * weight;
+        }
+        self.weight += weight;
+    }
+
+    /// Convert summed colors into the mean color
+    fn mean(mut self) -> Self {
+        if self.weight > 0. {
+            self.rgb = self.rgb.map(|v| v / self.weight);
+        }
+        self
+    }
+
+    fn color(&self) -> [u8; 3] {
+        self.rgb.map(|v| v.round().clamp(0., 255.) as u8)
+    }
+}
+
+/// Extract up to [`ExtractOptions::count`] colors from some pixels, sorted by weight.
+pub fn extract(pixels: &[[u8; 3]], options: &ExtractOptions) -> Vec<WeightedColor> {
+    let samples = histogram(pixels, options);
+    if options.count == 0 || samples.is_empty() {
+        return Vec::new();
+    }
+
+    let clusters = if samples.len() <= options.count {
+        samples
+    } else {
+        match options.quantizer {
+            Quantizer::KMeans => kmeans(&samples, options.count, options.seed),
+            Quantizer::MedianCut => median_cut(samples, options.count),
+            Quantizer::Wu => wu(&samples, options.count),
+            Quantizer::Octree => octree(&samples, options.count),
+        }
+    };
+
+    // Merge similar colors into the most common one
+    let mut clusters: Vec<Sample> = clusters.into_iter().filter(|c| c.weight > 0.).collect();
+    clusters.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+    let mut colors: Vec<WeightedColor> = Vec::with_capacity(clusters.len());
+    for cluster in clusters {
+        let color = cluster.color();
+        let weight = cluster.weight as f32;
+        match colors
+            .iter_mut()
+            .find(|c| c.color == color || delta_e(c.color, color) < options.min_delta_e)
+        {
+            Some(existing) => existing.weight += weight,
+            None => colors.push(WeightedColor { color, weight }),
+        }
+    }
+
+    let total: f32 = colors.iter().map(|c| c.weight).sum();
+    for color in &mut colors {
+        color.weight /= total;
+    }
+    colors.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+    colors
+}
+
+/// Bin pixels by their most significant bits, keeping the mean color of each bin.
+fn histogram(pixels: &[[u8; 3]], options: &ExtractOptions) -> Vec<Sample> {
+    let mut bins = vec![Sample::default(); 1 << (BITS * 3)];
+    for &[r, g, b] in pixels {
+        let i = bin(r, g, b);
+        bins[i].add([r as f64, g as f64, b as f64], 1.);
+    }
+
+    bins.into_iter()
+        .filter(|s| s.weight > 0.)
+        .map(|s| {
+            let mut s = s.mean();
+            if !options.weighted {
+                s.weight = 1.;
+            }
+            s
+        })
+        .filter(|s| {
+            let l = to_oklab(s.color()).l;
+            !(options.ignore_black && l < NEAR_BLACK || options.ignore_white && l > NEAR_WHITE)
+        })
+        .collect()
+}
+
+fn bin(r: u8, g: u8, b: u8) -> usize {
+    let shift = 8 - BITS;
+    ((r as usize >> shift) << (BITS * 2)) | ((g as usize >> shift) << BITS) | (b as usize >> shift)
+}
+
+/// Weighted k-means in Oklab, seeded with k-means++.
+fn kmeans(samples: &[Sample], count: usize, seed: u64) -> Vec<Sample> {
+    let mut rng: StdRng = SeedableRng::seed_from_u64(seed);
+    let lab: Vec<Oklab> = samples.iter().map(|s| to_oklab(s.color())).collect();
+
+    // Pick each center with a probability proportional to the weighted squared distance from
+    // the closest existing center
+    let pick = |rng: &mut StdRng, weights: &[f64]| {
+        let mut target = rng.random::<f64>() * weights.iter().sum::<f64>();
+        for (i, w) in weights.iter().enumerate() {
+            if target < *w {
+                return i;
+            }
+            target -= w;
+        }
+        weights.len() - 1
+    };
+    let weights: Vec<f64> = samples.iter().map(|s| s.weight).collect();
+    let mut centers = vec![lab[pick(&mut rng, &weights)]];
+    let mut closest: Vec<f32> = lab
+        .iter()
+        .map(|c| squared_distance(c, &centers[0]))
+        .collect();
+    while centers.len() < count {
+        let weights: Vec<f64> = samples
+            .iter()
+            .zip(&closest)
+            .map(|(s, d)| s.weight * *d as f64)
+            .collect();
+        if weights.iter().all(|w| *w <= 0.) {
+            break;
+        }
+        let center = lab[pick(&mut rng, &weights)];
+        for (d, c) in closest.iter_mut().zip(&lab) {
+            *d = d.min(squared_distance(c, &center));
+        }
+        centers.push(center);
+    }
+
+    let mut assignments = vec![usize::MAX; samples.len()];
+    for _ in 0..MAX_ITERATIONS {
+        let mut changed = false;
+        for (assignment, c) in assignments.iter_mut().zip(&lab) {
+            let nearest = centers
+                .iter()
+                .enumerate()
+                .min_by(|a, b| squared_distance(c, a.1).total_cmp(&squared_distance(c, b.1)))
+                .unwrap()
+                .0;
+            changed |= *assignment != nearest;
+            *assignment = nearest;
+        }
+        if !changed {
+            break;
+        }
+
+        // Move centers to the weighted mean of their colors
+        let mut sums = vec![(0f64, [0f64; 3]); centers.len()];
+        for ((&i, c), s) in assignments.iter().zip(&lab).zip(samples) {
+            sums[i].0 += s.weight;
+            sums[i].1[0] += c.l as f64 * s.weight;
+            sums[i].1[1] += c.a as f64 * s.weight;
+            sums[i].1[2] += c.b as f64 * s.weight;
+        }
+        for (center, (weight, [l, a, b])) in centers.iter_mut().zip(sums) {
+            if weight > 0. {
+                *center = Oklab {
+                    l: (l / weight) as f32,
+                    a: (a / weight) as f32,
+                    b: (b / weight) as f32,
+                };
+            }
+        }
+    }
+
+    let mut clusters = vec![Sample::default(); centers.len()];
+    for (&i, s) in assignments.iter().zip(samples) {
+        clusters[i].weight += s.weight;
+    }
+    for (cluster, center) in clusters.iter_mut().zip(centers) {
+        cluster.rgb = to_srgb(center).map(f64::from);
+    }
+    clusters
+}
+
+/// Median cut in sRGB.
+fn median_cut(samples: Vec<Sample>, count: usize) -> Vec<Sample> {
+    let range = |samples: &[Sample], channel: usize| {
+        let (min, max) = samples.iter().fold((f64::MAX, f64::MIN), |(min, max), s| {
+            (min.min(s.rgb[channel]), max.max(s.rgb[channel]))
+        });
+        max - min
+    };
+    let widest = |samples: &[Sample]| {
+        (0..3)
+            .map(|channel| (range(samples, channel), channel))
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap()
+    };
+
+    let mut boxes = vec![samples];
+    while boxes.len() < count {
+        // Split the box with the widest range of colors
+        let Some((i, channel)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| (i, widest(b)))
+            .max_by(|a, b| a.1 .0.total_cmp(&b.1 .0))
+            .map(|(i, (_, channel))| (i, channel))
+        else {
+            break;
+        };
+
+        let mut samples = boxes.swap_remove(i);
+        samples.sort_by(|a, b| a.rgb[channel].total_cmp(&b.rgb[channel]));
+        let half = samples.iter().map(|s| s.weight).sum::<f64>() / 2.;
+        let mut sum = 0.;
+        let median = samples
+            .iter()
+            .position(|s| {
+                sum += s.weight;
+                sum >= half
+            })
+            .unwrap_or(0);
+        let upper = samples.split_off((median + 1).min(samples.len() - 1));
+        boxes.push(samples);
+        boxes.push(upper);
+    }
+
+    boxes
+        .into_iter()
+        .map(|samples| {
+            let mut sum = Sample::default();
+            for s in samples {
+                sum.add(s.rgb, s.weight);
+            }
+            sum.mean()
+        })
+        .collect()
+}
+
+/// Cumulative color moments over the binned color cube, for Wu's quantizer.
+struct Moments {
+    weight: Vec<f64>,
+    rgb: [Vec<f64>; 3],
+    squares: Vec<f64>,
+}
+
+/// Box of bins, with exclusive lower and inclusive upper bounds.
+#[derive(Clone, Copy)]
+struct Cube {
+    min: [usize; 3],
+    max: [usize; 3],
+}
+
+const SIDE: usize = (1 << BITS) + 1;
+
+impl Moments {
+    fn new(samples: &[Sample]) -> Self {
+        let len = SIDE * SIDE * SIDE;
+        let mut m = Moments {
+            weight: vec![0.; len],
+            rgb: [vec![0.; len], vec![0.; len], vec![0.; len]],
+            squares: vec![0.; len],
+        };
+        let shift = 8 - BITS;
+        for s in samples {
+            let [r, g, b] = s.color().map(|v| (v as usize >> shift) + 1);
+            let i = Self::index(r, g, b);
+            m.weight[i] += s.weight;
+            for c in 0..3 {
+                m.rgb[c][i] += s.rgb[c] * s.weight;
+            }
+            m.squares[i] += s.rgb.iter().map(|v| v * v).sum::<f64>() * s.weight;
+        }
+
+        // Prefix sums over all three dimensions
+        for table in [&mut m.weight, &mut m.squares]
+            .into_iter()
+            .chain(m.rgb.iter_mut())
+        {
+            for r in 1..SIDE {
+                for g in 1..SIDE {
+                    for b in 1..SIDE {
+                        table[Self::index(r, g, b)] += table[Self::index(r - 1, g, b)]
+                            + table[Self::index(r, g - 1, b)]
+                            + table[Self::index(r, g, b - 1)]
+                            - table[Self::index(r - 1, g - 1, b)]
+                            - table[Self::index(r - 1, g, b - 1)]
+                            - table[Self::index(r, g - 1, b - 1)]
+                            + table[Self::index(r - 1, g - 1, b - 1)];
+                    }
+                }
+            }
+        }
+        m
+    }
+
+    fn index(r: usize, g: usize, b: usize) -> usize {
+        (r * SIDE + g) * SIDE + b
+    }
+
+    fn volume(table: &[f64], cube: &Cube) -> f64 {
+        let [r0, g0, b0] = cube.min;
+        let [r1, g1, b1] = cube.max;
+        table[Self::index(r1, g1, b1)]
+            - table[Self::index(r1, g1, b0)]
+            - table[Self::index(r1, g0, b1)]
+            + table[Self::index(r1, g0, b0)]
+            - table[Self::index(r0, g1, b1)]
+            + table[Self::index(r0, g1, b0)]
+            + table[Self::index(r0, g0, b1)]
+            - table[Self::index(r0, g0, b0)]
+    }
+
+    /// Total weight and weighted color sums of a cube
+    fn sums(&self, cube: &Cube) -> (f64, [f64; 3]) {
+        (
+            Self::volume(&self.weight, cube),
+            [0, 1, 2].map(|c| Self::volume(&self.rgb[c], cube)),
+        )
+    }
+
+    fn variance(&self, cube: &Cube) -> f64 {
+        let (weight, rgb) = self.sums(cube);
+        if weight <= 0. {
+            return 0.;
+        }
+        Self::volume(&self.squares, cube) - rgb.iter().map(|v| v * v).sum::<f64>() / weight
+    }
+
+    /// Find the cut that minimizes the variance of both halves, returning the halves
+    fn cut(&self, cube: &Cube) -> Option<(Cube, Cube)> {
+        let score = |cube: &Cube| {
+            let (weight, rgb) = self.sums(cube);
+            (weight > 0.).then(|| rgb.iter().map(|v| v * v).sum::<f64>() / weight)
+        };
+
+        let mut best: Option<(f64, Cube, Cube)> = None;
+        for channel in 0..3 {
+            for at in cube.min[channel] + 1..cube.max[channel] {
+                let mut lower = *cube;
+                lower.max[channel] = at;
+                let mut upper = *cube;
+                upper.min[channel] = at;
+                if let (Some(a), Some(b)) = (score(&lower), score(&upper)) {
+                    if best.as_ref().is_none_or(|(s, ..)| a + b > *s) {
+                        best = Some((a + b, lower, upper));
+                    }
+                }
+            }
+        }
+        best.map(|(_, lower, upper)| (lower, upper))
+    }
+}
+
+/// Wu's greedy variance minimization.
+fn wu(samples: &[Sample], count: usize) -> Vec<Sample> {
+    let moments = Moments::new(samples);
+    let mut cubes = vec![Cube {
+        min: [0; 3],
+        max: [SIDE - 1; 3],
+    }];
+    let mut variances = vec![moments.variance(&cubes[0])];
+
+    while cubes.len() < count {
+        // Split the cube with the most variance
+        let (i, variance) = variances
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        if *variance <= 0. {
+            break;
+        }
+        match moments.cut(&cubes[i]) {
+            Some((lower, upper)) => {
+                variances[i] = moments.variance(&lower);
+                cubes[i] = lower;
+                variances.push(moments.variance(&upper));
+                cubes.push(upper);
+            },
+            None => variances[i] = 0.,
+        }
+    }
+
+    cubes
+        .iter()
+        .map(|cube| {
+            let (weight, rgb) = moments.sums(cube);
+            Sample { rgb, weight }.mean()
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Node {
+    children: [Option<usize>; 8],
+    /// Weighted sum of every color in the subtree
+    sum: Sample,
+    leaf: bool,
+}
+
+/// Octree quantization, reducing the least common nodes first.
+fn octree(samples: &[Sample], count: usize) -> Vec<Sample> {
+    let mut nodes = vec![Node::default()];
+    // Interior nodes at each depth, which can be reduced into leaves
+    let mut levels: Vec<Vec<usize>> = vec![Vec::new(); BITS];
+    let mut leaves = 0;
+
+    for s in samples {
+        let [r, g, b] = s.color();
+        let mut node = 0;
+        for depth in 0..BITS {
+            nodes[node].sum.add(s.rgb, s.weight);
+            let bit = 7 - depth;
+            let child = (((r >> bit) & 1) << 2 | ((g >> bit) & 1) << 1 | ((b >> bit) & 1)) as usize;
+            node = match nodes[node].children[child] {
+                Some(i) => i,
+                None => {
+                    nodes.push(Node::default());
+                    let i = nodes.len() - 1;
+                    nodes[node].children[child] = Some(i);
+                    if depth + 1 == BITS {
+                        nodes[i].leaf = true;
+                        leaves += 1;
+                    } else {
+                        levels[depth + 1].push(i);
+                    }
+                    i
+                },
+            };
+        }
+        nodes[node].sum.add(s.rgb, s.weight);
+    }
+    levels[0].push(0);
+
+    // Merge the children of the least common deepest nodes until there are few enough leaves
+    while leaves > count {
+        let Some(level) = levels.iter_mut().rev().find(|l| !l.is_empty()) else {
+            break;
+        };
+        let (i, _) = level
+            .iter()
+            .enumerate()
+            .min_by(|a, b| nodes[*a.1].sum.weight.total_cmp(&nodes[*b.1].sum.weight))
+            .unwrap();
+        let node = level.swap_remove(i);
+        let children = nodes[node].children.iter().flatten().count();
+        nodes[node].children = [None; 8];
+        nodes[node].leaf = true;
+        leaves = leaves + 1 - children;
+    }
+
+    // Collect leaves reachable from the root
+    let mut clusters = Vec::with_capacity(leaves);
+    let mut stack = vec![0];
+    while let Some(i) = stack.pop() {
+        if nodes[i].leaf {
+            clusters.push(nodes[i].sum.mean());
+        } else {
+            stack.extend(nodes[i].children.iter().flatten());
+        }
+    }
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_palettes() {
+        // Three areas of noisy colors, covering 1/2, 1/4, and 1/4 of the image
+        let noisy = |[r, g, b]: [u8; 3], i: usize, step: u8| {
+            let n = (i % 5) as u8 * step;
+            [r + n, g + n, b + n]
+        };
+        let pixels: Vec<[u8; 3]> = (0..600)
+            .map(|i| match i % 4 {
+                0 | 1 => noisy([210, 40, 40], i, 6),
+                2 => noisy([40, 40, 200], i, 6),
+                _ => noisy([0, 0, 0], i, 3),
+            })
+            .collect();
+        let (red, black) = ([222, 52, 52], [6, 6, 6]);
+
+        for &quantizer in Quantizer::ALL {
+            let options = ExtractOptions {
+                quantizer,
+                count: 3,
+                ..Default::default()
+            };
+            let colors = extract(&pixels, &options);
+            assert_eq!(colors.len(), 3, "{quantizer}");
+            assert!(delta_e(colors[0].color, red) < 3., "{quantizer}");
+            let blacks = colors.iter().filter(|c| delta_e(c.color, black) < 3.);
+            assert_eq!(blacks.count(), 1, "{quantizer}");
+            assert!((colors[0].weight - 0.5).abs() < 0.01, "{quantizer}");
+            assert!((colors.iter().map(|c| c.weight).sum::<f32>() - 1.).abs() < 0.001);
+            assert_eq!(colors, extract(&pixels, &options), "{quantizer}");
+        }
+
+        // Near black colors can be skipped, and similar colors merged
+        let options = ExtractOptions {
+            count: 16,
+            ignore_black: true,
+            min_delta_e: 15.,
+            ..Default::default()
+        };
+        let colors = extract(&pixels, &options);
+        assert_eq!(colors.len(), 2);
+        assert!((colors[0].weight - 2. / 3.).abs() < 0.01);
+    }
+}
@@ -103,8 +103,12 @@ use std::sync::Arc;
 use image::buffer::ConvertBuffer;
 use interpolation::InterpolatedRemapper;
 
+pub mod analysis;
+pub mod extract;
 pub mod identity;
 pub mod interpolation;
+pub mod ops;
+pub mod theme;
 
 /// Core image type (Rgba8)
 pub use image::{RgbImage, RgbaImage};
@@ -0,0 +1,287 @@
+//! Palette operations, computed in Oklab.
+//!
+//! ```rust
+//! use lutgen::ops;
+//!
+//! let palette = ops::merge(&[&[[0, 0, 0], [255, 0, 0]], &[[255, 0, 0], [0, 0, 255]]]);
+//! assert_eq!(palette, vec![[0, 0, 0], [255, 0, 0], [0, 0, 255]]);
+//!
+//! // Add 3 colors between each pair of colors
+//! let ramp = ops::ramp(&palette, 3);
+//! assert_eq!(ramp.len(), 9);
+//!
+//! // Keep the 2 most distinct colors
+//! assert_eq!(ops::max_colors(&ramp, 2).len(), 2);
+//! ```
+
+use oklab::{oklab_to_srgb, srgb_to_oklab, Oklab};
+
+use crate::extract::WeightedColor;
+
+pub(crate) fn to_oklab(color: [u8; 3]) -> Oklab {
+    srgb_to_oklab(color.into())
+}
+
+pub(crate) fn to_srgb(color: Oklab) -> [u8; 3] {
+    let rgb = oklab_to_srgb(color);
+    [rgb.r, rgb.g, rgb.b]
+}
+
+pub(crate) fn lerp(from: Oklab, to: Oklab, t: f32) -> Oklab {
+    Oklab {
+        l: from.l + (to.l - from.l) * t,
+        a: from.a + (to.a - from.a) * t,
+        b: from.b + (to.b - from.b) * t,
+    }
+}
+
+pub(crate) fn squared_distance(a: &Oklab, b: &Oklab) -> f32 {
+    (a.l - b.l).powi(2) + (a.a - b.a).powi(2) + (a.b - b.b).powi(2)
+}
+
+/// Euclidean distance in Oklab, ie [`delta_e`] without the scaling
+pub(crate) fn distance(a: &Oklab, b: &Oklab) -> f32 {
+    squared_distance(a, b).sqrt()
+}
+
+/// Perceptual difference between two colors, as the euclidean distance in Oklab scaled by 100.
+/// A difference of around 2 is barely noticeable.
+pub fn delta_e(a: [u8; 3], b: [u8; 3]) -> f32 {
+    distance(&to_oklab(a), &to_oklab(b)) * 100.
+}
+
+/// Combine palettes in order, skipping exact duplicates.
+pub fn merge(palettes: &[&[[u8; 3]]]) -> Vec<[u8; 3]> {
+    let mut merged = Vec::new();
+    for &color in palettes.iter().copied().flatten() {
+        if !merged.contains(&color) {
+            merged.push(color);
+        }
+    }
+    merged
+}
+
+/// Drop colors within `min_delta_e` of an earlier color.
+pub fn dedupe(colors: &[[u8; 3]], min_delta_e: f32) -> Vec<[u8; 3]> {
+    let mut kept: Vec<[u8; 3]> = Vec::new();
+    for &color in colors {
+        if kept.iter().all(|&c| delta_e(c, color) >= min_delta_e) {
+            kept.push(color);
+        }
+    }
+    kept
+}
+
+/// Insert `steps` evenly spaced colors between each pair of consecutive colors.
+pub fn ramp(colors: &[[u8; 3]], steps: usize) -> Vec<[u8; 3]> {
+    let mut out = Vec::with_capacity(colors.len() + colors.len().saturating_sub(1) * steps);
+    for pair in colors.windows(2) {
+        let (from, to) = (to_oklab(pair[0]), to_oklab(pair[1]));
+        out.push(pair[0]);
+        for i in 1..=steps {
+            out.push(to_srgb(lerp(from, to, i as f32 / (steps + 1) as f32)));
+        }
+    }
+    out.extend(colors.last());
+    out
+}
+
+/// Mix each color towards a target lightness, adding `count` evenly spaced steps after it.
+fn mix(colors: &[[u8; 3]], count: usize, lightness: f32) -> Vec<[u8; 3]> {
+    let target = Oklab {
+        l: lightness,
+        a: 0.,
+        b: 0.,
+    };
+    let mut out = Vec::with_capacity(colors.len() * (count + 1));
+    for &color in colors {
+        out.push(color);
+        let from = to_oklab(color);
+        for i in 1..=count {
+            out.push(to_srgb(lerp(from, target, i as f32 / (count + 1) as f32)));
+        }
+    }
+    out
+}
+
+/// Add `count` tints of each color, mixed towards white.
+pub fn tints(colors: &[[u8; 3]], count: usize) -> Vec<[u8; 3]> {
+    mix(colors, count, 1.)
+}
+
+/// Add `count` shades of each color, mixed towards black.
+pub fn shades(colors: &[[u8; 3]], count: usize) -> Vec<[u8; 3]> {
+    mix(colors, count, 0.)
+}
+
+/// Keep the `max` most distinct colors, in their original order.
+///
+/// Colors are picked greedily, starting with the color furthest from the palette's average and
+/// then repeatedly adding the color furthest from any picked color.
+pub fn max_colors(colors: &[[u8; 3]], max: usize) -> Vec<[u8; 3]> {
+    if colors.len() <= max {
+        return colors.to_vec();
+    }
+    if max == 0 {
+        return Vec::new();
+    }
+
+    let lab: Vec<_> = colors.iter().map(|&c| to_oklab(c)).collect();
+    let n = lab.len() as f32;
+    let mean = Oklab {
+        l: lab.iter().map(|c| c.l).sum::<f32>() / n,
+        a: lab.iter().map(|c| c.a).sum::<f32>() / n,
+        b: lab.iter().map(|c| c.b).sum::<f32>() / n,
+    };
+
+    let furthest = |distances: &[f32], picked: &[bool]| {
+        distances
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !picked[*i])
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .expect("more colors than picks")
+    };
+
+    let mut picked = vec![false; colors.len()];
+    let from_mean: Vec<f32> = lab.iter().map(|c| squared_distance(c, &mean)).collect();
+    let mut next = furthest(&from_mean, &picked);
+
+    // Distance from each color to the closest picked color
+    let mut closest = vec![f32::INFINITY; colors.len()];
+    for _ in 1..max {
+        picked[next] = true;
+        for (i, c) in lab.iter().enumerate() {
+            closest[i] = closest[i].min(squared_distance(c, &lab[next]));
+        }
+        next = furthest(&closest, &picked);
+    }
+    picked[next] = true;
+
+    colors
+        .iter()
+        .zip(picked)
+        .filter_map(|(&c, picked)| picked.then_some(c))
+        .collect()
+}
+
+/// Mean perceptual difference between each color and the closest palette color, ie the average
+/// error of remapping the colors with a nearest neighbor LUT. Lower scores are closer matches.
+pub fn remap_error(colors: &[[u8; 3]], palette: &[[u8; 3]]) -> f32 {
+    if palette.is_empty() {
+        return f32::INFINITY;
+    }
+    if colors.is_empty() {
+        return 0.;
+    }
+
+    let palette: Vec<_> = palette.iter().map(|&c| to_oklab(c)).collect();
+    let total: f32 = colors
+        .iter()
+        .map(|&color| {
+            let c = to_oklab(color);
+            palette
+                .iter()
+                .map(|p| squared_distance(&c, p))
+                .fold(f32::INFINITY, f32::min)
+                .sqrt()
+        })
+        .sum();
+    total / colors.len() as f32 * 100.
+}
+
+/// Symmetric perceptual difference between weighted colors, ie the dominant colors of an image,
+/// and a palette. The weighted remap error of the colors is averaged with the mean distance from
+/// each palette color to the closest color, so palettes with many unused colors score worse than
+/// a palette covering the colors alone. Lower scores are closer matches.
+pub fn match_error(colors: &[WeightedColor], palette: &[[u8; 3]]) -> f32 {
+    if colors.is_empty() || palette.is_empty() {
+        return f32::INFINITY;
+    }
+
+    let colors: Vec<_> = colors
+        .iter()
+        .map(|c| (to_oklab(c.color), c.weight))
+        .collect();
+    let palette: Vec<_> = palette.iter().map(|&c| to_oklab(c)).collect();
+
+    let total_weight: f32 = colors.iter().map(|(_, w)| w).sum();
+    let coverage = colors
+        .iter()
+        .map(|(c, w)| nearest_distance(c, &palette) * w)
+        .sum::<f32>()
+        / total_weight.max(f32::EPSILON);
+    let lab: Vec<_> = colors.iter().map(|(c, _)| *c).collect();
+    let unused = palette
+        .iter()
+        .map(|p| nearest_distance(p, &lab))
+        .sum::<f32>()
+        / palette.len() as f32;
+    (coverage + unused) / 2. * 100.
+}
+
+fn nearest_distance(color: &Oklab, others: &[Oklab]) -> f32 {
+    others
+        .iter()
+        .map(|o| squared_distance(color, o))
+        .fold(f32::INFINITY, f32::min)
+        .sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_ops() {
+        let black = [0, 0, 0];
+        let white = [255, 255, 255];
+        let red = [255, 0, 0];
+
+        let merged = merge(&[&[black, red], &[red, white]]);
+        assert_eq!(merged, vec![black, red, white]);
+
+        let ramp = ramp(&[black, white], 3);
+        assert_eq!(ramp.len(), 5);
+        assert_eq!((ramp[0], ramp[4]), (black, white));
+        assert!(ramp.windows(2).all(|w| w[0][0] < w[1][0]));
+
+        let tints = tints(&[red], 2);
+        assert_eq!(tints.len(), 3);
+        assert!(tints[1][1] > 0 && tints[2][1] > tints[1][1]);
+        assert_eq!(shades(&[white], 1).len(), 2);
+
+        // Near duplicates are dropped, keeping the first color
+        let near = [[128, 128, 128], [129, 129, 129], white];
+        assert_eq!(dedupe(&near, 2.), vec![[128, 128, 128], white]);
+
+        // The most distinct colors are kept in order
+        let colors = max_colors(&[black, [10, 10, 10], red, white], 3);
+        assert_eq!(colors, vec![black, red, white]);
+    }
+
+    #[test]
+    fn palette_remap_error() {
+        assert_eq!(remap_error(&[[0, 0, 0]], &[[0, 0, 0], [255, 255, 255]]), 0.);
+        assert!(remap_error(&[[0, 0, 0]], &[]).is_infinite());
+    }
+
+    #[test]
+    fn palette_match_error() {
+        let weighted = |color, weight| WeightedColor { color, weight };
+        let colors = [weighted([0, 0, 0], 0.75), weighted([255, 0, 0], 0.25)];
+        assert_eq!(match_error(&colors, &[[0, 0, 0], [255, 0, 0]]), 0.);
+        assert!(match_error(&colors, &[]).is_infinite());
+
+        // Unused palette colors are penalized, unlike the one directional remap error
+        let larger = [[0, 0, 0], [255, 0, 0], [0, 255, 0], [0, 0, 255]];
+        assert_eq!(remap_error(&[[0, 0, 0], [255, 0, 0]], &larger), 0.);
+        assert!(match_error(&colors, &larger) > match_error(&colors, &[[0, 0, 0], [250, 0, 0]]));
+
+        // Colors are weighted by their share of the image
+        let dark = match_error(&colors, &[[0, 0, 0], [128, 0, 0]]);
+        let red = match_error(&colors, &[[64, 64, 64], [255, 0, 0]]);
+        assert!(dark < red);
+    }
+}
@@ -0,0 +1,413 @@
+//! Assign extracted colors to terminal or base16 theme roles by hue and lightness.
+//!
+//! ```rust
+//! use lutgen::analysis::wcag_contrast;
+//! use lutgen::extract::WeightedColor;
+//! use lutgen::theme::{theme, Scheme, ThemeOptions};
+//!
+//! let colors = [
+//!     WeightedColor {
+//!         color: [0x1d, 0x20, 0x21],
+//!         weight: 0.6,
+//!     },
+//!     WeightedColor {
+//!         color: [0xd5, 0xc4, 0xa1],
+//!         weight: 0.2,
+//!     },
+//!     WeightedColor {
+//!         color: [0xb8, 0x3a, 0x2a],
+//!         weight: 0.1,
+//!     },
+//!     WeightedColor {
+//!         color: [0x45, 0x85, 0x88],
+//!         weight: 0.1,
+//!     },
+//! ];
+//! let theme = theme(&colors, &ThemeOptions::default());
+//!
+//! // Dominant dark colors become the background, and every role is named
+//! assert!(theme.dark);
+//! assert_eq!(theme.role("bg"), Some([0x1d, 0x20, 0x21]));
+//! assert_eq!(theme.roles.len(), Scheme::Terminal.roles().len());
+//!
+//! // Text colors are adjusted to be readable on the background
+//! let red = theme.role("color1").unwrap();
+//! assert!(wcag_contrast(red, theme.role("bg").unwrap()) >= 4.5);
+//! ```
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use oklab::{oklab_to_linear_srgb, Oklab};
+
+use crate::analysis::{wcag_contrast, MIN_CHROMA};
+use crate::extract::WeightedColor;
+use crate::ops::{lerp, to_oklab, to_srgb};
+
+/// Images with a mean Oklab lightness under this get a dark theme, unless set in
+/// [`ThemeOptions::dark`].
+pub const DARK_THRESHOLD: f32 = 0.6;
+/// Extracted colors further than this many degrees from a role's hue are not used for it.
+pub const MAX_HUE_SHIFT: f32 = 30.0;
+/// Minimum WCAG contrast for dimmed text, ie comments and `color8`.
+pub const DIM_CONTRAST: f32 = 3.0;
+/// Backgrounds and foregrounds are desaturated to at most this Oklab chroma.
+const MAX_NEUTRAL_CHROMA: f32 = 0.04;
+
+/// Accent roles, with the Oklab hue in degrees they are matched to. Assigned in this order, so
+/// earlier roles get the best matching colors.
+const ACCENTS: [(Accent, f32); 7] = [
+    (Accent::Red, 25.),
+    (Accent::Green, 145.),
+    (Accent::Yellow, 100.),
+    (Accent::Blue, 260.),
+    (Accent::Magenta, 330.),
+    (Accent::Cyan, 200.),
+    (Accent::Orange, 60.),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Accent {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    Orange,
+}
+
+/// Set of roles to assign colors to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Scheme {
+    /// `bg`, `fg`, and the 16 ansi colors `color0` to `color15`
+    #[default]
+    Terminal,
+    /// `base00` to `base0f`, with shades from background to foreground followed by accents
+    Base16,
+}
+
+impl Scheme {
+    pub const ALL: &'static [Scheme] = &[Scheme::Terminal, Scheme::Base16];
+
+    /// Role names assigned for the scheme, in order.
+    pub fn roles(&self) -> &'static [&'static str] {
+        match self {
+            Scheme::Terminal => &[
+                "bg", "fg", "color0", "color1", "color2", "color3", "color4", "color5", "color6",
+                "color7", "color8", "color9", "color10", "color11", "color12", "color13",
+                "color14", "color15",
+            ],
+            Scheme::Base16 => &[
+                "base00", "base01", "base02", "base03", "base04", "base05", "base06", "base07",
+                "base08", "base09", "base0a", "base0b", "base0c", "base0d", "base0e", "base0f",
+            ],
+        }
+    }
+}
+
+impl Display for Scheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Scheme::Terminal => "terminal",
+            Scheme::Base16 => "base16",
+        })
+    }
+}
+
+impl FromStr for Scheme {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scheme::ALL
+            .iter()
+            .find(|scheme| scheme.to_string().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("unknown scheme `{s}`, expected terminal or base16"))
+    }
+}
+
+/// Options for [`theme`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThemeOptions {
+    pub scheme: Scheme,
+    /// Force a dark or light theme. Otherwise picked from the mean lightness of the colors,
+    /// see [`DARK_THRESHOLD`].
+    pub dark: Option<bool>,
+    /// Minimum WCAG contrast ratio of the foreground and accents against the background.
+    pub min_contrast: f32,
+}
+
+impl Default for ThemeOptions {
+    fn default() -> Self {
+        Self {
+            scheme: Scheme::default(),
+            dark: None,
+            min_contrast: 4.5,
+        }
+    }
+}
+
+/// Colors assigned to theme roles.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Theme {
+    pub dark: bool,
+    /// Every role of the [`Scheme`] in order. Roles may share a color.
+    pub roles: Vec<(&'static str, [u8; 3])>,
+}
+
+impl Theme {
+    /// Get the color for a role.
+    pub fn role(&self, name: &str) -> Option<[u8; 3]> {
+        self.roles.iter().find(|(r, _)| *r == name).map(|(_, c)| *c)
+    }
+
+    /// Distinct colors of the theme, in role order.
+    pub fn colors(&self) -> Vec<[u8; 3]> {
+        let mut colors = Vec::with_capacity(self.roles.len());
+        for (_, color) in &self.roles {
+            if !colors.contains(color) {
+                colors.push(*color);
+            }
+        }
+        colors
+    }
+}
+
+/// Assign colors to the roles of a [`Scheme`], usually from [`crate::extract::extract`].
+///
+/// The background and foreground are the most common colors at either end of the lightness
+/// range, desaturated. Accents are the closest colors to each role's hue, synthesized at the
+/// typical chroma and lightness of the image when there are none within [`MAX_HUE_SHIFT`]. Text
+/// colors are then lightened or darkened until they meet the minimum contrast.
+pub fn theme(colors: &[WeightedColor], options: &ThemeOptions) -> Theme {
+    let colors: Vec<(Oklab, f32)> = colors
+        .iter()
+        .map(|c| (to_oklab(c.color), c.weight))
+        .collect();
+    let total = colors.iter().map(|(_, w)| w).sum::<f32>().max(f32::EPSILON);
+    let mean = colors.iter().map(|(c, w)| c.l * w).sum::<f32>() / total;
+    let dark = options
+        .dark
+        .unwrap_or(colors.is_empty() || mean < DARK_THRESHOLD);
+
+    // Lightness moves away from the background for more contrast
+    let direction = if dark { 1. } else { -1. };
+    let bg = neutral(&colors, dark, if dark { 0.25 } else { 0.95 });
+    let fg = neutral(&colors, !dark, if dark { 0.9 } else { 0.3 });
+    let bg_rgb = to_srgb(bg);
+    let readable = |color: Oklab, min: f32| contrast(color, bg_rgb, min, direction);
+    let fg = readable(fg, options.min_contrast);
+
+    let accents = accents(&colors, dark);
+    let accent = |accent: Accent| {
+        let color = accents.iter().find(|(a, _)| *a == accent).unwrap().1;
+        readable(color, options.min_contrast)
+    };
+    // Bright accents have more contrast than the normal accents
+    let bright = |role: Accent| {
+        let color = to_oklab(accent(role));
+        readable(shift(color, 0.08 * direction), options.min_contrast)
+    };
+    let shade = |t: f32| to_srgb(lerp(bg, to_oklab(fg), t));
+
+    let roles: Vec<[u8; 3]> = match options.scheme {
+        Scheme::Terminal => {
+            // Black and white are the darker and lighter of the background and foreground
+            let (black, white) = if dark {
+                (bg, to_oklab(fg))
+            } else {
+                (to_oklab(fg), bg)
+            };
+            let gray = |t: f32| lerp(black, white, t);
+            vec![
+                bg_rgb,
+                fg,
+                to_srgb(shift(black, 0.05)),
+                accent(Accent::Red),
+                accent(Accent::Green),
+                accent(Accent::Yellow),
+                accent(Accent::Blue),
+                accent(Accent::Magenta),
+                accent(Accent::Cyan),
+                to_srgb(gray(0.85)),
+                readable(gray(0.4), DIM_CONTRAST),
+                bright(Accent::Red),
+                bright(Accent::Green),
+                bright(Accent::Yellow),
+                bright(Accent::Blue),
+                bright(Accent::Magenta),
+                bright(Accent::Cyan),
+                to_srgb(white),
+            ]
+        },
+        Scheme::Base16 => {
+            let orange = accents
+                .iter()
+                .find(|(a, _)| *a == Accent::Orange)
+                .unwrap()
+                .1;
+            let brown = Oklab {
+                l: orange.l - 0.12,
+                a: orange.a * 0.6,
+                b: orange.b * 0.6,
+            };
+            vec![
+                bg_rgb,
+                shade(0.08),
+                shade(0.16),
+                readable(lerp(bg, to_oklab(fg), 0.35), DIM_CONTRAST),
+                shade(0.6),
+                fg,
+                to_srgb(shift(to_oklab(fg), 0.04 * direction)),
+                to_srgb(shift(to_oklab(fg), 0.08 * direction)),
+                accent(Accent::Red),
+                accent(Accent::Orange),
+                accent(Accent::Yellow),
+                accent(Accent::Green),
+                accent(Accent::Cyan),
+                accent(Accent::Blue),
+                accent(Accent::Magenta),
+                readable(brown, options.min_contrast),
+            ]
+        },
+    };
+
+    Theme {
+        dark,
+        roles: options.scheme.roles().iter().copied().zip(roles).collect(),
+    }
+}
+
+/// Most common color on one end of the lightness range, or the most extreme color moved to the
+/// target lightness when there is none. Desaturated to [`MAX_NEUTRAL_CHROMA`].
+fn neutral(colors: &[(Oklab, f32)], dark: bool, target: f32) -> Oklab {
+    let in_range = |c: &Oklab| if dark { c.l <= target } else { c.l >= target };
+    let by_lightness = |a: &&(Oklab, f32), b: &&(Oklab, f32)| a.0.l.total_cmp(&b.0.l);
+    let mut color = match colors
+        .iter()
+        .filter(|(c, _)| in_range(c))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+    {
+        Some((color, _)) => *color,
+        None => {
+            let extreme = if dark {
+                colors.iter().min_by(by_lightness)
+            } else {
+                colors.iter().max_by(by_lightness)
+            };
+            let color = extreme.map_or(
+                Oklab {
+                    l: 0.,
+                    a: 0.,
+                    b: 0.,
+                },
+                |(c, _)| *c,
+            );
+            Oklab { l: target, ..color }
+        },
+    };
+
+    let chroma = chroma(&color);
+    if chroma > MAX_NEUTRAL_CHROMA {
+        color.a *= MAX_NEUTRAL_CHROMA / chroma;
+        color.b *= MAX_NEUTRAL_CHROMA / chroma;
+    }
+    color
+}
+
+/// Closest chromatic color to each accent hue, without reusing colors, or a synthesized color at
+/// the accent hue.
+fn accents(colors: &[(Oklab, f32)], dark: bool) -> Vec<(Accent, Oklab)> {
+    let chromatic: Vec<&(Oklab, f32)> = colors
+        .iter()
+        .filter(|(c, _)| chroma(c) >= MIN_CHROMA)
+        .collect();
+
+    // Synthesized accents use the weighted mean chroma and lightness of the image's accents
+    let total: f32 = chromatic.iter().map(|(_, w)| w).sum();
+    let (chroma_mean, lightness) = if total > 0. {
+        (
+            chromatic.iter().map(|(c, w)| chroma(c) * w).sum::<f32>() / total,
+            chromatic.iter().map(|(c, w)| c.l * w).sum::<f32>() / total,
+        )
+    } else {
+        (0.12, if dark { 0.72 } else { 0.55 })
+    };
+
+    let mut used = Vec::new();
+    ACCENTS
+        .iter()
+        .map(|&(accent, target)| {
+            let closest = chromatic
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !used.contains(i))
+                .map(|(i, (c, w))| (i, c, hue_distance(hue(c), target), w))
+                .filter(|(_, _, distance, _)| *distance <= MAX_HUE_SHIFT)
+                .min_by(|a, b| a.2.total_cmp(&b.2).then(b.3.total_cmp(a.3)));
+            let color = match closest {
+                Some((i, color, ..)) => {
+                    used.push(i);
+                    *color
+                },
+                None => {
+                    let (sin, cos) = target.to_radians().sin_cos();
+                    fit_gamut(Oklab {
+                        l: lightness,
+                        a: chroma_mean * cos,
+                        b: chroma_mean * sin,
+                    })
+                },
+            };
+            (accent, color)
+        })
+        .collect()
+}
+
+/// Move the lightness away from the background until the color has the minimum contrast, or
+/// reaches black or white.
+fn contrast(mut color: Oklab, bg: [u8; 3], min: f32, direction: f32) -> [u8; 3] {
+    let mut rgb = to_srgb(color);
+    while wcag_contrast(rgb, bg) < min && (0. ..=1.).contains(&color.l) {
+        color.l += 0.01 * direction;
+        rgb = to_srgb(fit_gamut(color));
+    }
+    rgb
+}
+
+/// Reduce the chroma until the color is inside sRGB, keeping the lightness and hue.
+fn fit_gamut(mut color: Oklab) -> Oklab {
+    for _ in 0..32 {
+        let rgb = oklab_to_linear_srgb(color);
+        if [rgb.r, rgb.g, rgb.b]
+            .iter()
+            .all(|v| (-1e-3..=1. + 1e-3).contains(v))
+        {
+            break;
+        }
+        color.a *= 0.9;
+        color.b *= 0.9;
+    }
+    color
+}
+
+fn shift(color: Oklab, lightness: f32) -> Oklab {
+    fit_gamut(Oklab {
+        l: (color.l + lightness).clamp(0., 1.),
+        ..color
+    })
+}
+
+fn chroma(c: &Oklab) -> f32 {
+    (c.a * c.a + c.b * c.b).sqrt()
+}
+
+/// Hue in degrees, from 0 to 360
+fn hue(c: &Oklab) -> f32 {
+    c.b.atan2(c.a).to_degrees().rem_euclid(360.)
+}
+
+fn hue_distance(a: f32, b: f32) -> f32 {
+    let d = (a - b).abs() % 360.;
+    d.min(360. - d)
+}
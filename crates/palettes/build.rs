@@ -1,14 +1,23 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
+use std::fmt::{Debug, Display};
 use std::fs::{read_to_string, write};
 use std::path::Path;
 
-use serde::Serialize;
-use tinytemplate::TinyTemplate;
+use serde::{Deserialize, Serialize};
+use tinytemplate::{format_unescaped, TinyTemplate};
 
 const TEMPLATE: &str = r#"
 use strum::\{Display, EnumString, IntoStaticStr, VariantArray};
 
+/// Whether a palette is meant for a dark or light background
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Display, EnumString, IntoStaticStr)]
+#[strum(serialize_all = "kebab-case", ascii_case_insensitive)]
+pub enum Variant \{
+    Dark,
+    Light,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Display, EnumString, IntoStaticStr, VariantArray)]
 #[strum(serialize_all = "kebab-case")]
 pub enum Palette \{
@@ -23,7 +32,7 @@ impl Palette \{
 
     /// Get the colors for the palette
     pub fn get(&self) -> &'static [[u8; 3]] \{
-        match self \{
+        match *self \{
           {{ for item in palettes }}
             Palette::{ item.name } => &[
               {{ for color in item.palette }}
@@ -33,6 +42,70 @@ impl Palette \{
           {{ endfor }}
         }
     }
+
+    /// Get the variant of the palette. Builtin palettes without a variant in `palettes.toml` have
+    /// one derived from their background role or name, when either is known.
+    pub fn variant(&self) -> Option<Variant> \{
+        match *self \{
+          {{ for item in palettes }}
+            Palette::{ item.name } => { item.variant },
+          {{ endfor }}
+        }
+    }
+
+    /// Get the family of the palette, ie `gruvbox` for `gruvbox-dark` and `gruvbox-light`
+    pub fn family(&self) -> Option<&'static str> \{
+        match *self \{
+          {{ for item in palettes }}
+            Palette::{ item.name } => { item.family },
+          {{ endfor }}
+        }
+    }
+
+    /// Get the collection the palette was taken from, ie `base16` or `terminal-sexy`
+    pub fn collection(&self) -> Option<&'static str> \{
+        match *self \{
+          {{ for item in palettes }}
+            Palette::{ item.name } => { item.collection },
+          {{ endfor }}
+        }
+    }
+
+    /// Get the author of the palette
+    pub fn author(&self) -> Option<&'static str> \{
+        match *self \{
+          {{ for item in palettes }}
+            Palette::{ item.name } => { item.author },
+          {{ endfor }}
+        }
+    }
+
+    /// Get the url of the palette's source
+    pub fn url(&self) -> Option<&'static str> \{
+        match *self \{
+          {{ for item in palettes }}
+            Palette::{ item.name } => { item.url },
+          {{ endfor }}
+        }
+    }
+
+    /// Get the colors with a role name, ie `bg`, `red`, or `base08`
+    pub fn roles(&self) -> &'static [(&'static str, [u8; 3])] \{
+        match *self \{
+          {{ for item in palettes }}
+            Palette::{ item.name } => &[
+              {{ for role in item.roles }}
+                ({ role.name }, [ { role.color.r }u8, { role.color.g }, { role.color.b } ]),
+              {{ endfor }}
+            ],
+          {{ endfor }}
+        }
+    }
+
+    /// Get the color for a role name, or any equivalent role (ie `base08` and `red`)
+    pub fn role(&self, name: &str) -> Option<[u8; 3]> \{
+        crate::roles::find(self.roles(), name)
+    }
 }
 
 impl std::hash::Hash for Palette \{
@@ -40,50 +113,375 @@ impl std::hash::Hash for Palette \{
         self.to_string().hash(state);
         self.get().hash(state);
     }
+}
+
+#[cfg(all(test, not(no_palettes)))]
+mod generated_tests \{
+    use std::str::FromStr;
+
+    use super::Palette;
+
+    /// Every palette name parses back to the same palette
+    #[test]
+    fn round_trip() \{
+      {{ for item in palettes }}
+        assert_eq!(Palette::from_str(&Palette::{ item.name }.to_string()), Ok(Palette::{ item.name }));
+      {{ endfor }}
+    }
 }"#;
 
-#[derive(Serialize)]
+/// Palette entry in `palettes.toml`, either a list of colors or a table with metadata
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Colors(Vec<String>),
+    Table(Table),
+}
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Table {
+    colors: Vec<String>,
+    variant: Option<String>,
+    family: Option<String>,
+    collection: Option<String>,
+    author: Option<String>,
+    url: Option<String>,
+    #[serde(default)]
+    roles: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, PartialEq)]
 struct Color {
     r: u8,
     g: u8,
     b: u8,
 }
 #[derive(Serialize)]
+struct Role {
+    /// Quoted role name
+    name: String,
+    color: Color,
+}
+#[derive(Serialize)]
 struct Palette {
     name: String,
     palette: Vec<Color>,
+    // Metadata, as rust expressions
+    variant: String,
+    family: String,
+    collection: String,
+    author: String,
+    url: String,
+    roles: Vec<Role>,
 }
 #[derive(Serialize)]
 struct Context {
     palettes: Vec<Palette>,
 }
 
+/// Palettes included by the `minimal` feature
+const MINIMAL: &[&str] = &[
+    "carburetor",
+    "catppuccin_latte",
+    "catppuccin_mocha",
+    "dracula",
+    "everforest_dark_medium",
+    "gruvbox_dark",
+    "gruvbox_light",
+    "kanagawa",
+    "nord",
+    "one_dark",
+    "rose_pine",
+    "solarized_dark",
+    "solarized_light",
+    "tokyo_night_storm",
+];
+
+/// Palettes repeating a color in their source. Their data is kept as is, and a duplicate color in
+/// any other palette is an error.
+const DUPLICATES: &[&str] = &[
+    "everforest_dark_hard",
+    "everforest_light_medium",
+    "oxocarbon_light",
+    "swamp_light",
+];
+
+/// Collections palettes were taken from, and the feature including every palette in each
+const COLLECTIONS: &[(&str, &str)] =
+    &[("base16", "base16-all"), ("terminal-sexy", "terminal-sexy")];
+
+/// Words in a palette name marking it as light, ie `gruvbox_light` or `rose_pine_dawn`
+const LIGHT_WORDS: &[&str] = &[
+    "light", "lighter", "day", "dawn", "latte", "paper", "white", "morning",
+];
+/// Words marking a palette as dark when found anywhere in its name, ie `gruvbox_dark`,
+/// `tokyo_night`, or `onehalfdark`
+const DARK_WORDS: &[&str] = &["dark", "night", "dusk", "black"];
+
+/// Check if a cargo feature is enabled for the crate
+fn enabled(feature: &str) -> bool {
+    let var = format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+    std::env::var_os(var).is_some()
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     println!("cargo:rerun-if-changed=palettes.toml");
+    println!("cargo:rerun-if-changed=Cargo.toml");
     println!("cargo:rerun-if-changed=src/lib.template");
 
     let out_dir = std::env::var("OUT_DIR")?;
     let mut tt = TinyTemplate::new();
+    tt.set_default_formatter(&format_unescaped);
     tt.add_template("lib.rs", TEMPLATE)?;
 
-    let ctx = Context {
-        palettes: toml::from_str::<BTreeMap<String, Vec<String>>>(&read_to_string(
-            "palettes.toml",
-        )?)?
-        .into_iter()
-        .map(|(name, palette)| Palette {
-            name: pascal_case(name),
-            palette: palette.into_iter().map(parse_color).collect(),
-        })
-        .collect(),
-    };
+    let entries = toml::from_str::<BTreeMap<String, Entry>>(&read_to_string("palettes.toml")?)?;
+    let manifest: toml::Table = toml::from_str(&read_to_string("Cargo.toml")?)?;
+    let features: Vec<&String> = manifest
+        .get("features")
+        .and_then(|f| f.as_table())
+        .map(|f| f.keys().collect())
+        .unwrap_or_default();
+    let (all, minimal) = (enabled("all"), enabled("minimal"));
 
-    let rust_code = tt.render("lib.rs", &ctx)?;
+    let mut errors = Vec::new();
+    for name in MINIMAL {
+        if !entries.contains_key(*name) {
+            errors.push(format!(
+                "`{name}`: in the minimal set, but missing from palettes.toml"
+            ));
+        }
+    }
+
+    for name in DUPLICATES {
+        if !entries.contains_key(*name) {
+            errors.push(format!(
+                "`{name}`: allowed to have duplicate colors, but missing from palettes.toml"
+            ));
+        }
+    }
+
+    let mut names: HashMap<String, String> = HashMap::new();
+    let mut palettes = Vec::with_capacity(entries.len());
+    for (key, entry) in entries {
+        let name = pascal_case(key.clone());
+        if !is_identifier(&name) {
+            errors.push(format!("`{key}`: `{name}` is not a valid rust identifier"));
+        } else if let Some(other) = names.insert(name.clone(), key.clone()) {
+            errors.push(format!("`{key}`: name collides with `{other}` as `{name}`"));
+        }
+
+        // Every family can be enabled on its own with a feature
+        let family = match &entry {
+            Entry::Table(table) => table.family.clone(),
+            Entry::Colors(_) => None,
+        };
+        if let Some(family) = &family {
+            if !features.contains(&family) {
+                errors.push(format!(
+                    "`{key}` family `{family}`: missing a `{family}` feature in Cargo.toml"
+                ));
+            }
+        }
+        // Collections are enabled by their feature in `COLLECTIONS`
+        let collection = match &entry {
+            Entry::Table(table) => table.collection.as_deref(),
+            Entry::Colors(_) => None,
+        };
+        let collection_feature = collection.and_then(|collection| {
+            let feature = COLLECTIONS.iter().find(|(c, _)| *c == collection);
+            if feature.is_none() {
+                let expected: Vec<_> = COLLECTIONS.iter().map(|(c, _)| *c).collect();
+                errors.push(format!(
+                    "`{key}` collection `{collection}`: unknown collection, expected one of: {}",
+                    expected.join(", ")
+                ));
+            }
+            feature.map(|(_, feature)| *feature)
+        });
+
+        let included = all
+            || (minimal && MINIMAL.contains(&key.as_str()))
+            || family.as_deref().is_some_and(enabled)
+            || collection_feature.is_some_and(enabled);
+
+        let mut validate = Validate {
+            palette: &key,
+            errors: &mut errors,
+        };
+        let mut palette = match entry {
+            Entry::Colors(colors) => Palette {
+                name,
+                palette: validate.colors(colors),
+                variant: "None".into(),
+                family: "None".into(),
+                collection: "None".into(),
+                author: "None".into(),
+                url: "None".into(),
+                roles: Vec::new(),
+            },
+            Entry::Table(table) => validate.table(name, table),
+        };
+        if palette.variant == "None" {
+            palette.variant = derive_variant(&key, &palette.roles).into();
+        }
+        if included {
+            palettes.push(palette);
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(InvalidPalettes(errors).into());
+    }
+
+    // Strum derives and the generated tests warn when no palettes are enabled, ie loader only
+    println!("cargo:rustc-check-cfg=cfg(no_palettes)");
+    if palettes.is_empty() {
+        println!("cargo:rustc-cfg=no_palettes");
+    }
+
+    let rust_code = tt.render("lib.rs", &Context { palettes })?;
     write(Path::new(&out_dir).join("lib.rs"), rust_code)?;
 
     Ok(())
 }
 
+/// Validation errors, listed one per line when the build script fails
+struct InvalidPalettes(Vec<String>);
+
+impl Debug for InvalidPalettes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} invalid palette(s) in palettes.toml:", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  - {error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for InvalidPalettes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl Error for InvalidPalettes {}
+
+/// Collects errors for a palette entry, so every problem in the file is reported at once
+struct Validate<'a> {
+    palette: &'a str,
+    errors: &'a mut Vec<String>,
+}
+
+impl Validate<'_> {
+    fn error(&mut self, message: String) {
+        self.errors.push(format!("`{}` {message}", self.palette));
+    }
+
+    /// Parse colors, rejecting invalid hex codes and duplicates outside of [`DUPLICATES`]
+    fn colors(&mut self, colors: Vec<String>) -> Vec<Color> {
+        let allowed = DUPLICATES.contains(&self.palette);
+        let mut duplicates = false;
+        let mut parsed: Vec<(usize, Color)> = Vec::with_capacity(colors.len());
+        for (i, code) in colors.into_iter().enumerate() {
+            match parse_color(&code) {
+                Ok(color) => {
+                    if let Some((first, _)) = parsed.iter().find(|(_, c)| *c == color) {
+                        duplicates = true;
+                        if !allowed {
+                            self.error(format!("color {i} (`{code}`): duplicate of color {first}"));
+                            continue;
+                        }
+                    }
+                    parsed.push((i, color));
+                },
+                Err(e) => self.error(format!("color {i} (`{code}`): {e}")),
+            }
+        }
+        if allowed && !duplicates {
+            self.error("allowed to have duplicate colors, but has none".into());
+        }
+        parsed.into_iter().map(|(_, c)| c).collect()
+    }
+
+    fn table(&mut self, name: String, table: Table) -> Palette {
+        let palette = self.colors(table.colors);
+        let mut roles = Vec::with_capacity(table.roles.len());
+        for (role, code) in table.roles {
+            match parse_color(&code) {
+                Ok(color) if palette.contains(&color) => roles.push(Role {
+                    name: format!("{:?}", role.to_ascii_lowercase()),
+                    color,
+                }),
+                Ok(_) => self.error(format!(
+                    "role `{role}` (`{code}`): not one of the palette's colors"
+                )),
+                Err(e) => self.error(format!("role `{role}` (`{code}`): {e}")),
+            }
+        }
+        let variant = match table.variant.as_deref() {
+            None => "None",
+            Some("dark") => "Some(Variant::Dark)",
+            Some("light") => "Some(Variant::Light)",
+            Some(v) => {
+                self.error(format!(
+                    "variant: unknown variant `{v}`, expected dark or light"
+                ));
+                "None"
+            },
+        };
+
+        Palette {
+            name,
+            palette,
+            variant: variant.into(),
+            family: format!("{:?}", table.family),
+            collection: format!("{:?}", table.collection),
+            author: format!("{:?}", table.author),
+            url: format!("{:?}", table.url),
+            roles,
+        }
+    }
+}
+
+/// Derive the variant of a palette without one, from the luminance of its `bg` role, or otherwise
+/// from its name. The colors alone can't tell which one is the background, so palettes without
+/// either are left without a variant, unless they set one explicitly.
+fn derive_variant(key: &str, roles: &[Role]) -> &'static str {
+    let words = key.trim_start_matches('_').split('_').collect::<Vec<_>>();
+    let light = if let Some(bg) = roles.iter().find(|r| r.name == "\"bg\"") {
+        luminance(&bg.color) > 0.18
+    } else if words.iter().any(|w| LIGHT_WORDS.contains(w))
+        || words.last().is_some_and(|w| w.ends_with("light"))
+    {
+        true
+    } else if words
+        .iter()
+        .any(|w| DARK_WORDS.iter().any(|d| w.contains(d)))
+    {
+        false
+    } else {
+        return "None";
+    };
+    if light {
+        "Some(Variant::Light)"
+    } else {
+        "Some(Variant::Dark)"
+    }
+}
+
+/// Relative luminance of a color, from 0 to 1
+fn luminance(Color { r, g, b }: &Color) -> f64 {
+    let linear = |c: u8| {
+        let c = c as f64 / 255.;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(*r) + 0.7152 * linear(*g) + 0.0722 * linear(*b)
+}
+
 pub fn pascal_case(s: String) -> String {
     let sections: Vec<_> = s.split('_').collect();
     let mut buf = String::new();
@@ -99,22 +497,27 @@ pub fn pascal_case(s: String) -> String {
     buf
 }
 
-fn parse_color(s: String) -> Color {
-    let hex_string = match s.strip_prefix('#') {
-        Some(s) => s,
-        None => panic!("expected hex string starting with `#`"),
-    };
+fn is_identifier(s: &str) -> bool {
+    s.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && s != "_"
+}
+
+fn parse_color(s: &str) -> Result<Color, String> {
+    let hex_string = s
+        .strip_prefix('#')
+        .ok_or("expected a hex string starting with `#`")?;
     if hex_string.len() != 6 {
-        panic!("expected a 6 digit hex string");
+        return Err("expected a 6 digit hex string".into());
     }
-    let channel_bytes = match u32::from_str_radix(hex_string, 16) {
-        Ok(n) => n,
-        Err(_) => panic!("expected a valid hex string"),
-    };
+    let channel_bytes =
+        u32::from_str_radix(hex_string, 16).map_err(|_| "expected a valid hex string")?;
 
-    Color {
+    Ok(Color {
         r: ((channel_bytes >> 16) & 0xFF) as u8,
         g: ((channel_bytes >> 8) & 0xFF) as u8,
         b: (channel_bytes & 0xFF) as u8,
-    }
+    })
 }
@@ -0,0 +1,528 @@
+//! Writers for palette and terminal theme file formats.
+//!
+//! Terminal themes use the palette's roles when available, otherwise the nearest palette colors
+//! to the default terminal colors.
+//!
+//! ```
+//! use lutgen_palettes::export::{export, Format};
+//!
+//! let colors = [[0x28, 0x28, 0x28], [0xcc, 0x24, 0x1d], [0xeb, 0xdb, 0xb2]];
+//! let roles = [("bg", colors[0]), ("red", colors[1]), ("fg", colors[2])];
+//! let theme = export(Format::Kitty, "gruvbox-dark", &colors, &roles);
+//! assert!(theme.starts_with(b"# gruvbox-dark\nforeground #ebdbb2\nbackground #282828\n"));
+//! ```
+
+use std::fmt::{Display, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::roles;
+
+/// Supported export formats
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// GIMP and Inkscape palette
+    Gpl,
+    /// Adobe swatch exchange
+    Ase,
+    Json,
+    /// Css custom properties
+    CssVars,
+    /// Scss variables
+    Scss,
+    /// base16 scheme
+    Base16Yaml,
+    Kitty,
+    Alacritty,
+    Xresources,
+    /// Svg image of swatches
+    Svg,
+    /// Png image of swatches
+    PngSwatch,
+}
+
+impl Format {
+    /// Collection of all formats
+    pub const ALL: &'static [Format] = &[
+        Format::Gpl,
+        Format::Ase,
+        Format::Json,
+        Format::CssVars,
+        Format::Scss,
+        Format::Base16Yaml,
+        Format::Kitty,
+        Format::Alacritty,
+        Format::Xresources,
+        Format::Svg,
+        Format::PngSwatch,
+    ];
+
+    /// File extension for the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Gpl => "gpl",
+            Format::Ase => "ase",
+            Format::Json => "json",
+            Format::CssVars => "css",
+            Format::Scss => "scss",
+            Format::Base16Yaml => "yaml",
+            Format::Kitty => "conf",
+            Format::Alacritty => "toml",
+            Format::Xresources => "Xresources",
+            Format::Svg => "svg",
+            Format::PngSwatch => "png",
+        }
+    }
+
+    /// Get the format for a file's extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|f| f.extension().eq_ignore_ascii_case(&extension))
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Format::Gpl => "gpl",
+            Format::Ase => "ase",
+            Format::Json => "json",
+            Format::CssVars => "css-vars",
+            Format::Scss => "scss",
+            Format::Base16Yaml => "base16-yaml",
+            Format::Kitty => "kitty",
+            Format::Alacritty => "alacritty",
+            Format::Xresources => "xresources",
+            Format::Svg => "svg",
+            Format::PngSwatch => "png-swatch",
+        })
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|f| f.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let formats: Vec<_> = Self::ALL.iter().map(ToString::to_string).collect();
+                format!(
+                    "unknown export format `{s}`, expected one of: {}",
+                    formats.join(", ")
+                )
+            })
+    }
+}
+
+/// Export a palette to a file format.
+pub fn export(
+    format: Format,
+    name: &str,
+    colors: &[[u8; 3]],
+    roles: &[(&str, [u8; 3])],
+) -> Vec<u8> {
+    match format {
+        Format::Gpl => gpl(name, colors, roles).into_bytes(),
+        Format::Ase => ase(colors, roles),
+        Format::Json => json(name, colors, roles).into_bytes(),
+        Format::CssVars => variables(name, colors, roles, ":root {\n", "  --", ";\n", "}\n"),
+        Format::Scss => variables(name, colors, roles, "", "$", ";\n", ""),
+        Format::Base16Yaml => base16(name, colors, roles).into_bytes(),
+        Format::Kitty => kitty(name, &Terminal::new(colors, roles)).into_bytes(),
+        Format::Alacritty => alacritty(name, &Terminal::new(colors, roles)).into_bytes(),
+        Format::Xresources => xresources(name, &Terminal::new(colors, roles)).into_bytes(),
+        Format::Svg => svg(colors).into_bytes(),
+        Format::PngSwatch => png(colors),
+    }
+}
+
+fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// Relative luminance, from 0 to 1
+fn luma([r, g, b]: [u8; 3]) -> f32 {
+    (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32) / 255.
+}
+
+/// Palette color closest to a reference color, using a weighted rgb distance
+fn nearest(colors: &[[u8; 3]], reference: [u8; 3]) -> [u8; 3] {
+    let distance = |c: &[u8; 3]| {
+        let mean = (c[0] as f32 + reference[0] as f32) / 2.;
+        let [dr, dg, db] = [0, 1, 2].map(|i| c[i] as f32 - reference[i] as f32);
+        (2. + mean / 256.) * dr * dr + 4. * dg * dg + (2. + (255. - mean) / 256.) * db * db
+    };
+    colors
+        .iter()
+        .copied()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .unwrap_or(reference)
+}
+
+/// Role names for a color, or its hex code
+fn label(color: [u8; 3], roles: &[(&str, [u8; 3])]) -> String {
+    let names: Vec<_> = roles
+        .iter()
+        .filter(|(_, c)| *c == color)
+        .map(|(r, _)| *r)
+        .collect();
+    if names.is_empty() {
+        hex(color)
+    } else {
+        names.join(", ")
+    }
+}
+
+/// Lowercase name with any other characters replaced by dashes, for variable names
+fn slug(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// Escape a json or yaml string
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Default xterm colors, used to pick terminal colors for palettes without roles
+const XTERM: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0xcd, 0x00, 0x00],
+    [0x00, 0xcd, 0x00],
+    [0xcd, 0xcd, 0x00],
+    [0x00, 0x00, 0xee],
+    [0xcd, 0x00, 0xcd],
+    [0x00, 0xcd, 0xcd],
+    [0xe5, 0xe5, 0xe5],
+    [0x7f, 0x7f, 0x7f],
+    [0xff, 0x00, 0x00],
+    [0x00, 0xff, 0x00],
+    [0xff, 0xff, 0x00],
+    [0x5c, 0x5c, 0xff],
+    [0xff, 0x00, 0xff],
+    [0x00, 0xff, 0xff],
+    [0xff, 0xff, 0xff],
+];
+
+const ANSI_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// Colors for a terminal theme
+struct Terminal {
+    bg: [u8; 3],
+    fg: [u8; 3],
+    ansi: [[u8; 3]; 16],
+}
+
+impl Terminal {
+    fn new(colors: &[[u8; 3]], roles: &[(&str, [u8; 3])]) -> Self {
+        let by_luma = |a: &&[u8; 3], b: &&[u8; 3]| luma(**a).total_cmp(&luma(**b));
+        let darkest = colors.iter().min_by(by_luma).copied().unwrap_or([0; 3]);
+        let lightest = colors.iter().max_by(by_luma).copied().unwrap_or([255; 3]);
+        Self {
+            bg: roles::find(roles, "bg").unwrap_or(darkest),
+            fg: roles::find(roles, "fg").unwrap_or(lightest),
+            ansi: std::array::from_fn(|i| {
+                roles::find(roles, &format!("color{i}"))
+                    .unwrap_or_else(|| nearest(colors, XTERM[i]))
+            }),
+        }
+    }
+}
+
+fn gpl(name: &str, colors: &[[u8; 3]], roles: &[(&str, [u8; 3])]) -> String {
+    let mut out = format!("GIMP Palette\nName: {name}\nColumns: 8\n#\n");
+    for &color in colors {
+        let [r, g, b] = color;
+        writeln!(out, "{r:3} {g:3} {b:3}\t{}", label(color, roles)).unwrap();
+    }
+    out
+}
+
+fn ase(colors: &[[u8; 3]], roles: &[(&str, [u8; 3])]) -> Vec<u8> {
+    let mut out = b"ASEF\0\x01\0\0".to_vec();
+    out.extend((colors.len() as u32).to_be_bytes());
+    for &color in colors {
+        // Utf-16 name with a null terminator
+        let name: Vec<u16> = label(color, roles).encode_utf16().chain([0]).collect();
+        let mut block = (name.len() as u16).to_be_bytes().to_vec();
+        block.extend(name.iter().flat_map(|c| c.to_be_bytes()));
+        block.extend(b"RGB ");
+        block.extend(color.iter().flat_map(|&c| (c as f32 / 255.).to_be_bytes()));
+        // Global color type
+        block.extend([0, 0]);
+
+        out.extend([0, 1]);
+        out.extend((block.len() as u32).to_be_bytes());
+        out.extend(block);
+    }
+    out
+}
+
+fn json(name: &str, colors: &[[u8; 3]], roles: &[(&str, [u8; 3])]) -> String {
+    let colors: Vec<_> = colors.iter().map(|&c| quote(&hex(c))).collect();
+    let roles: Vec<_> = roles
+        .iter()
+        .map(|(r, c)| format!("    {}: {}", quote(r), quote(&hex(*c))))
+        .collect();
+    format!(
+        "{{\n  \"name\": {},\n  \"colors\": [{}],\n  \"roles\": {{\n{}\n  }}\n}}\n",
+        quote(name),
+        colors.join(", "),
+        roles.join(",\n")
+    )
+}
+
+/// Css or scss variables, prefixed with the palette name
+fn variables(
+    name: &str,
+    colors: &[[u8; 3]],
+    roles: &[(&str, [u8; 3])],
+    start: &str,
+    prefix: &str,
+    end: &str,
+    close: &str,
+) -> Vec<u8> {
+    let palette = slug(name);
+    let mut out = start.to_string();
+    for (i, &color) in colors.iter().enumerate() {
+        write!(out, "{prefix}{palette}-{i}: {}{end}", hex(color)).unwrap();
+    }
+    for (role, color) in roles {
+        write!(out, "{prefix}{palette}-{}: {}{end}", slug(role), hex(*color)).unwrap();
+    }
+    out.push_str(close);
+    out.into_bytes()
+}
+
+fn base16(name: &str, colors: &[[u8; 3]], roles: &[(&str, [u8; 3])]) -> String {
+    // Shades from darkest to lightest, and accents by hue
+    let mut shades = colors.to_vec();
+    shades.sort_by(|a, b| luma(*a).total_cmp(&luma(*b)));
+    let accents = [
+        [0xcc, 0x33, 0x33],
+        [0xff, 0x88, 0x00],
+        [0xff, 0xcc, 0x00],
+        [0x33, 0xcc, 0x33],
+        [0x33, 0xcc, 0xcc],
+        [0x33, 0x66, 0xff],
+        [0xcc, 0x33, 0xcc],
+        [0x99, 0x66, 0x33],
+    ];
+
+    let mut out = format!("scheme: {}\nauthor: \"lutgen\"\n", quote(name));
+    for i in 0..16 {
+        let role = format!("base{i:02x}");
+        let color = roles::find(roles, &role).unwrap_or_else(|| match i {
+            0..8 => shades
+                .get(i * shades.len().saturating_sub(1) / 7)
+                .copied()
+                .unwrap_or_default(),
+            _ => nearest(colors, accents[i - 8]),
+        });
+        writeln!(out, "base{i:02X}: \"{}\"", &hex(color)[1..]).unwrap();
+    }
+    out
+}
+
+fn kitty(name: &str, t: &Terminal) -> String {
+    let mut out = format!(
+        "# {name}\nforeground {}\nbackground {}\ncursor {}\nselection_foreground {}\n\
+         selection_background {}\n",
+        hex(t.fg),
+        hex(t.bg),
+        hex(t.fg),
+        hex(t.bg),
+        hex(t.fg)
+    );
+    for (i, &color) in t.ansi.iter().enumerate() {
+        writeln!(out, "color{i} {}", hex(color)).unwrap();
+    }
+    out
+}
+
+fn alacritty(name: &str, t: &Terminal) -> String {
+    let mut out = format!(
+        "# {name}\n[colors.primary]\nbackground = \"{}\"\nforeground = \"{}\"\n",
+        hex(t.bg),
+        hex(t.fg)
+    );
+    for (section, colors) in [("normal", &t.ansi[..8]), ("bright", &t.ansi[8..])] {
+        write!(out, "\n[colors.{section}]\n").unwrap();
+        for (name, &color) in ANSI_NAMES.iter().zip(colors) {
+            writeln!(out, "{name} = \"{}\"", hex(color)).unwrap();
+        }
+    }
+    out
+}
+
+fn xresources(name: &str, t: &Terminal) -> String {
+    let mut out = format!(
+        "! {name}\n*.background: {}\n*.foreground: {}\n*.cursorColor: {}\n",
+        hex(t.bg),
+        hex(t.fg),
+        hex(t.fg)
+    );
+    for (i, &color) in t.ansi.iter().enumerate() {
+        writeln!(out, "*.color{i}: {}", hex(color)).unwrap();
+    }
+    out
+}
+
+/// Swatches per row in images
+const COLUMNS: usize = 8;
+
+fn svg(colors: &[[u8; 3]]) -> String {
+    const SIZE: usize = 64;
+    let columns = colors.len().clamp(1, COLUMNS);
+    let rows = colors.len().div_ceil(COLUMNS).max(1);
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         font-family=\"monospace\" font-size=\"10\" text-anchor=\"middle\">\n",
+        columns * SIZE,
+        rows * SIZE
+    );
+    for (i, &color) in colors.iter().enumerate() {
+        let (x, y) = (i % COLUMNS * SIZE, i / COLUMNS * SIZE);
+        let text = if luma(color) < 0.5 {
+            "#ffffff"
+        } else {
+            "#000000"
+        };
+        writeln!(
+            out,
+            "<rect x=\"{x}\" y=\"{y}\" width=\"{SIZE}\" height=\"{SIZE}\" fill=\"{color}\"/>\n\
+             <text x=\"{}\" y=\"{}\" fill=\"{text}\">{color}</text>",
+            x + SIZE / 2,
+            y + SIZE / 2 + 4,
+            color = hex(color),
+        )
+        .unwrap();
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Png image of swatches, encoded without compression to avoid any dependencies
+fn png(colors: &[[u8; 3]]) -> Vec<u8> {
+    const SIZE: usize = 32;
+    let columns = colors.len().clamp(1, COLUMNS);
+    let rows = colors.len().div_ceil(COLUMNS).max(1);
+    let (width, height) = (columns * SIZE, rows * SIZE);
+
+    // Scanlines, each starting with a filter type
+    let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+    for y in 0..height {
+        raw.push(0);
+        for x in 0..width {
+            let i = y / SIZE * COLUMNS + x / SIZE;
+            raw.extend(colors.get(i).unwrap_or(&[0; 3]));
+        }
+    }
+
+    // Zlib stream with stored deflate blocks
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(u16::MAX as usize).peekable();
+    while let Some(block) = blocks.next() {
+        zlib.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend(block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in &raw {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    zlib.extend(((b << 16) | a).to_be_bytes());
+
+    let mut header = (width as u32).to_be_bytes().to_vec();
+    header.extend((height as u32).to_be_bytes());
+    // 8 bit rgb, default compression, filtering, and no interlacing
+    header.extend([8, 2, 0, 0, 0]);
+
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, data) in [(b"IHDR", header), (b"IDAT", zlib), (b"IEND", Vec::new())] {
+        out.extend((data.len() as u32).to_be_bytes());
+        let start = out.len();
+        out.extend(kind);
+        out.extend(data);
+        let crc = crc32(&out[start..]);
+        out.extend(crc.to_be_bytes());
+    }
+    out
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import;
+
+    #[test]
+    fn palette_formats() {
+        // Terminal themes round trip through the importer, keeping roles
+        let colors = [[0x1e, 0x1e, 0x2e], [0xcd, 0xd6, 0xf4], [0xf3, 0x8b, 0xa8]];
+        let palette_roles = [
+            ("bg", colors[0]),
+            ("fg", colors[1]),
+            ("red", colors[2]),
+            ("bright-white", colors[1]),
+        ];
+        let kitty = export(Format::Kitty, "test", &colors, &palette_roles);
+        let imported = import::parse(import::Format::Kitty, &kitty).unwrap();
+        let imported: Vec<_> = imported
+            .roles
+            .iter()
+            .map(|(r, c)| (r.as_str(), *c))
+            .collect();
+        for (role, color) in palette_roles {
+            assert_eq!(roles::find(&imported, role), Some(color));
+        }
+
+        // Palettes without roles use the closest colors
+        let colors = [[0x10, 0x10, 0x10], [0xe0, 0x20, 0x20], [0xf0, 0xf0, 0xf0]];
+        let xresources = export(Format::Xresources, "test", &colors, &[]);
+        let xresources = String::from_utf8(xresources).unwrap();
+        assert!(xresources.contains("*.background: #101010\n*.foreground: #f0f0f0\n"));
+        assert!(xresources.contains("*.color1: #e02020\n"));
+
+        let ase = export(Format::Ase, "test", &colors, &[]);
+        assert_eq!(
+            import::parse(import::Format::Ase, &ase).unwrap().colors,
+            colors
+        );
+        let png = export(Format::PngSwatch, "test", &colors, &[]);
+        let image = image::load_from_memory(&png).unwrap().to_rgb8();
+        assert_eq!(image.dimensions(), (96, 32));
+        assert_eq!(image.get_pixel(40, 10).0, colors[1]);
+    }
+}
@@ -0,0 +1,598 @@
+//! Readers for common palette and terminal theme file formats.
+//!
+//! ```no_run
+//! let palette = lutgen_palettes::import::read("colors.json".as_ref()).unwrap();
+//! println!("{:?}", palette.roles);
+//! ```
+
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Colors read from a palette file, and role names for some of them (ie `bg`, `color1`, or
+/// `base08`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ImportedPalette {
+    pub colors: Vec<[u8; 3]>,
+    pub roles: Vec<(String, [u8; 3])>,
+}
+
+impl ImportedPalette {
+    /// Add a color, skipping duplicates
+    fn push(&mut self, color: [u8; 3]) {
+        if !self.colors.contains(&color) {
+            self.colors.push(color);
+        }
+    }
+
+    /// Add a color with a role. The first color for a role is kept.
+    fn push_role(&mut self, role: String, color: [u8; 3]) {
+        self.push(color);
+        if !self.roles.iter().any(|(r, _)| *r == role) {
+            self.roles.push((role, color));
+        }
+    }
+}
+
+/// Supported palette file formats
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// Whitespace separated hex colors, optionally with roles (ie `bg=#282828`). Also reads
+    /// Lospec `.hex` files.
+    Plain,
+    /// GIMP `.gpl`
+    Gpl,
+    /// Adobe swatch exchange `.ase`
+    Ase,
+    /// Adobe color swatch `.aco`
+    Aco,
+    /// Paint.NET `.txt`
+    PaintNet,
+    /// base16 or base24 scheme `.yaml`
+    Base16,
+    /// pywal `colors.json`
+    Pywal,
+    Kitty,
+    Alacritty,
+    Foot,
+    Wezterm,
+    Xresources,
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Plain => "plain",
+            Self::Gpl => "gpl",
+            Self::Ase => "ase",
+            Self::Aco => "aco",
+            Self::PaintNet => "paint-net",
+            Self::Base16 => "base16",
+            Self::Pywal => "pywal",
+            Self::Kitty => "kitty",
+            Self::Alacritty => "alacritty",
+            Self::Foot => "foot",
+            Self::Wezterm => "wezterm",
+            Self::Xresources => "xresources",
+        })
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "plain" | "hex" | "lospec" => Ok(Self::Plain),
+            "gpl" | "gimp" => Ok(Self::Gpl),
+            "ase" => Ok(Self::Ase),
+            "aco" => Ok(Self::Aco),
+            "paint-net" | "paintnet" => Ok(Self::PaintNet),
+            "base16" | "base24" => Ok(Self::Base16),
+            "pywal" => Ok(Self::Pywal),
+            "kitty" => Ok(Self::Kitty),
+            "alacritty" => Ok(Self::Alacritty),
+            "foot" => Ok(Self::Foot),
+            "wezterm" => Ok(Self::Wezterm),
+            "xresources" => Ok(Self::Xresources),
+            _ => Err(format!("unknown palette format `{s}`")),
+        }
+    }
+}
+
+impl Format {
+    /// Detect the format of a palette file from its name and contents.
+    pub fn detect(path: &Path, bytes: &[u8]) -> Self {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        let text = String::from_utf8_lossy(bytes);
+
+        if bytes.starts_with(b"ASEF") {
+            return Self::Ase;
+        }
+        if text.starts_with("GIMP Palette") {
+            return Self::Gpl;
+        }
+        match extension.as_deref() {
+            Some("gpl") => Self::Gpl,
+            Some("ase") => Self::Ase,
+            Some("aco") => Self::Aco,
+            Some("yaml" | "yml") => Self::Base16,
+            Some("json") => Self::Pywal,
+            Some("conf") => Self::Kitty,
+            Some("ini") => Self::Foot,
+            Some("xresources" | "xdefaults") => Self::Xresources,
+            // Wezterm schemes use `ansi` and `brights` arrays, otherwise assume alacritty
+            Some("toml") if text.lines().any(|l| l.trim_start().starts_with("ansi")) => {
+                Self::Wezterm
+            },
+            Some("toml") => Self::Alacritty,
+            // Paint.NET palettes start with `;` comments
+            Some("txt") if text.trim_start().starts_with(';') => Self::PaintNet,
+            _ if matches!(name.as_str(), ".xresources" | ".xdefaults" | "xresources") => {
+                Self::Xresources
+            },
+            _ if name == "foot.ini" => Self::Foot,
+            _ => Self::Plain,
+        }
+    }
+}
+
+/// Read a palette file, detecting its format.
+pub fn read(path: &Path) -> Result<ImportedPalette, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("failed to read {path:?}: {e}"))?;
+    parse(Format::detect(path, &bytes), &bytes).map_err(|e| format!("{path:?}: {e}"))
+}
+
+/// Parse the contents of a palette file.
+pub fn parse(format: Format, bytes: &[u8]) -> Result<ImportedPalette, String> {
+    let text = || std::str::from_utf8(bytes).map_err(|e| format!("invalid utf-8: {e}"));
+    let palette = match format {
+        Format::Plain => plain(text()?)?,
+        Format::Gpl => gpl(text()?)?,
+        Format::Ase => ase(bytes)?,
+        Format::Aco => aco(bytes)?,
+        Format::PaintNet => paint_net(text()?)?,
+        Format::Pywal => pywal(text()?)?,
+        _ => theme(format, text()?),
+    };
+    if palette.colors.is_empty() {
+        return Err(format!("no colors found in {format} palette"));
+    }
+    Ok(palette)
+}
+
+/// Parse a hex color, with an optional `#` or `0x` prefix. Also supports X11 `rgb:rr/gg/bb`.
+/// Short hex colors are only supported with a prefix.
+fn parse_hex(s: &str) -> Option<[u8; 3]> {
+    if let Some(rgb) = s.strip_prefix("rgb:") {
+        let mut channels = rgb.split('/').map(|c| {
+            // Scale channels with 1 to 4 hex digits to 8 bits
+            let max = 16u32.checked_pow(c.len() as u32)?.checked_sub(1)?;
+            let v = u32::from_str_radix(c, 16).ok()?;
+            Some((v * 255 / max.max(1)) as u8)
+        });
+        let color = [channels.next()??, channels.next()??, channels.next()??];
+        return channels.next().is_none().then_some(color);
+    }
+
+    let (prefixed, hex) = match s.strip_prefix('#').or_else(|| s.strip_prefix("0x")) {
+        Some(hex) => (true, hex),
+        None => (false, s),
+    };
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let v = u32::from_str_radix(hex, 16).ok()?;
+    match hex.len() {
+        3 if prefixed => {
+            let [r, g, b] = [(v >> 8) & 0xf, (v >> 4) & 0xf, v & 0xf].map(|c| (c * 17) as u8);
+            Some([r, g, b])
+        },
+        6 => Some([(v >> 16) as u8, (v >> 8) as u8, v as u8]),
+        _ => None,
+    }
+}
+
+/// Whitespace separated hex colors, optionally with a role (ie `bg=#282828`)
+fn plain(text: &str) -> Result<ImportedPalette, String> {
+    let mut palette = ImportedPalette::default();
+    for token in text.split_whitespace() {
+        let (role, hex) = token
+            .split_once('=')
+            .map_or((None, token), |(r, c)| (Some(r), c));
+        let color = parse_hex(&format!("#{}", hex.trim_start_matches('#')))
+            .ok_or_else(|| format!("invalid hex color `{hex}`"))?;
+        match role {
+            Some(role) => palette.push_role(role.to_lowercase().replace('_', "-"), color),
+            None => palette.push(color),
+        }
+    }
+    Ok(palette)
+}
+
+/// GIMP palette, with `R G B name` lines after a header
+fn gpl(text: &str) -> Result<ImportedPalette, String> {
+    let mut palette = ImportedPalette::default();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("GIMP Palette")
+            || line.contains(':')
+        {
+            continue;
+        }
+        let mut channels = line.split_whitespace().map(u8::from_str);
+        match (channels.next(), channels.next(), channels.next()) {
+            (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => palette.push([r, g, b]),
+            _ => return Err(format!("invalid gpl color `{line}`")),
+        }
+    }
+    Ok(palette)
+}
+
+/// Paint.NET palette, with `AARRGGBB` lines and `;` comments
+fn paint_net(text: &str) -> Result<ImportedPalette, String> {
+    let mut palette = ImportedPalette::default();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        // Skip the alpha channel, rejecting anything but ascii before slicing
+        let color = (line.len() == 8 && line.is_ascii())
+            .then(|| line.get(2..).and_then(parse_hex))
+            .flatten()
+            .ok_or_else(|| format!("invalid paint.net color `{line}`"))?;
+        palette.push(color);
+    }
+    Ok(palette)
+}
+
+/// Pywal `colors.json`, with roles from the `special` and `colors` objects
+fn pywal(text: &str) -> Result<ImportedPalette, String> {
+    let json: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| format!("invalid json: {e}"))?;
+    let mut palette = ImportedPalette::default();
+    for section in ["special", "colors"] {
+        let Some(object) = json.get(section).and_then(|v| v.as_object()) else {
+            continue;
+        };
+        // Keys are sorted as strings, so put `color2` before `color10`
+        let mut entries: Vec<_> = object.iter().collect();
+        entries.sort_by_key(|(key, _)| (key.len(), key.as_str()));
+        for (key, value) in entries {
+            if let Some(color) = value.as_str().and_then(parse_hex) {
+                palette.push_role(key.to_lowercase().replace('_', "-"), color);
+            }
+        }
+    }
+    Ok(palette)
+}
+
+/// Big endian reader for binary formats
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        if self.0.len() < len {
+            return Err("unexpected end of file".into());
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// Adobe swatch exchange, with rgb, cmyk, lab, and gray colors
+fn ase(bytes: &[u8]) -> Result<ImportedPalette, String> {
+    let mut reader = Reader(bytes);
+    if reader.take(4)? != b"ASEF" {
+        return Err("missing ase signature".into());
+    }
+    reader.take(4)?; // version
+    let blocks = reader.u32()?;
+
+    let mut palette = ImportedPalette::default();
+    for _ in 0..blocks {
+        let kind = reader.u16()?;
+        let len = reader.u32()? as usize;
+        let mut block = Reader(reader.take(len)?);
+        // Skip group start and end blocks
+        if kind != 0x0001 {
+            continue;
+        }
+
+        let name_len = block.u16()? as usize;
+        block.take(name_len * 2)?;
+        let model = block.take(4)?.to_vec();
+        let color = match &model[..] {
+            b"RGB " => [block.f32()?, block.f32()?, block.f32()?].map(unit_to_u8),
+            b"Gray" => [unit_to_u8(block.f32()?); 3],
+            b"CMYK" => cmyk_to_rgb([block.f32()?, block.f32()?, block.f32()?, block.f32()?]),
+            // Lightness is stored from 0 to 1
+            b"LAB " => lab_to_rgb([block.f32()? * 100., block.f32()?, block.f32()?]),
+            _ => {
+                return Err(format!(
+                    "unsupported ase color model `{}`",
+                    String::from_utf8_lossy(&model)
+                ))
+            },
+        };
+        palette.push(color);
+    }
+    Ok(palette)
+}
+
+/// Adobe color swatch, reading the version 1 section (or version 2 if it's the only one)
+fn aco(bytes: &[u8]) -> Result<ImportedPalette, String> {
+    let mut reader = Reader(bytes);
+    let version = reader.u16()?;
+    if version != 1 && version != 2 {
+        return Err(format!("unsupported aco version {version}"));
+    }
+    let count = reader.u16()?;
+
+    let mut palette = ImportedPalette::default();
+    for _ in 0..count {
+        let space = reader.u16()?;
+        let [w, x, y, z] = [reader.u16()?, reader.u16()?, reader.u16()?, reader.u16()?];
+        let color = match space {
+            0 => [w, x, y].map(|c| (c / 257) as u8),
+            1 => hsv_to_rgb(
+                w as f32 / 65535. * 360.,
+                x as f32 / 65535.,
+                y as f32 / 65535.,
+            ),
+            // Cmyk values are inverted, 0 is full ink
+            2 => cmyk_to_rgb([w, x, y, z].map(|c| 1. - c as f32 / 65535.)),
+            7 => lab_to_rgb([
+                w as f32 / 100.,
+                x as i16 as f32 / 100.,
+                y as i16 as f32 / 100.,
+            ]),
+            // Grayscale is the amount of black ink
+            8 => [unit_to_u8(1. - w as f32 / 10000.); 3],
+            _ => return Err(format!("unsupported aco color space {space}")),
+        };
+        if version == 2 {
+            // Skip the utf-16 name, which is prefixed with its length in characters
+            let len = reader.u32()? as usize;
+            reader.take(len * 2)?;
+        }
+        palette.push(color);
+    }
+    Ok(palette)
+}
+
+fn unit_to_u8(v: f32) -> u8 {
+    (v.clamp(0., 1.) * 255.).round() as u8
+}
+
+fn cmyk_to_rgb([c, m, y, k]: [f32; 4]) -> [u8; 3] {
+    [c, m, y].map(|v| unit_to_u8((1. - v) * (1. - k)))
+}
+
+fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [u8; 3] {
+    let c = v * s;
+    let h = (h % 360.) / 60.;
+    let x = c * (1. - (h % 2. - 1.).abs());
+    let (r, g, b) = match h as u8 {
+        0 => (c, x, 0.),
+        1 => (x, c, 0.),
+        2 => (0., c, x),
+        3 => (0., x, c),
+        4 => (x, 0., c),
+        _ => (c, 0., x),
+    };
+    [r, g, b].map(|ch| unit_to_u8(ch + v - c))
+}
+
+/// Convert CIELAB (D50) to srgb
+fn lab_to_rgb([l, a, b]: [f32; 3]) -> [u8; 3] {
+    let fy = (l + 16.) / 116.;
+    let fx = fy + a / 500.;
+    let fz = fy - b / 200.;
+    let inv = |t: f32| {
+        if t > 6. / 29. {
+            t.powi(3)
+        } else {
+            3. * (6f32 / 29.).powi(2) * (t - 4. / 29.)
+        }
+    };
+    let [x, y, z] = [inv(fx) * 0.9642, inv(fy), inv(fz) * 0.8251];
+
+    // Bradford adapted D50 XYZ to linear srgb
+    let linear = [
+        3.133856 * x - 1.616867 * y - 0.490615 * z,
+        -0.978768 * x + 1.916142 * y + 0.033454 * z,
+        0.071945 * x - 0.228991 * y + 1.405243 * z,
+    ];
+    linear.map(|c| {
+        let c = c.clamp(0., 1.);
+        unit_to_u8(if c <= 0.0031308 {
+            12.92 * c
+        } else {
+            1.055 * c.powf(1. / 2.4) - 0.055
+        })
+    })
+}
+
+/// Terminal themes and color schemes, read as `key = value` lines with optional sections
+fn theme(format: Format, text: &str) -> ImportedPalette {
+    let mut palette = ImportedPalette::default();
+    let mut section = String::new();
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') && line.ends_with(']') && !line.contains(['"', '#']) {
+            section = line.trim_matches(['[', ']']).trim().to_lowercase();
+            continue;
+        }
+        // Xresources defines, ie `#define base00 #282828`
+        let line = line.strip_prefix("#define").unwrap_or(line);
+        if line.starts_with(['#', '!', ';']) || line.starts_with("//") {
+            continue;
+        }
+
+        // Keys end at a separator, or whitespace for kitty
+        let (key, value) = match line.find(['=', ':']) {
+            Some(i) if format != Format::Kitty => (&line[..i], &line[i + 1..]),
+            _ => match line.split_once(char::is_whitespace) {
+                Some(kv) => kv,
+                None => continue,
+            },
+        };
+        let key = key
+            .trim()
+            .trim_matches(['"', '\''])
+            .rsplit(['.', '*'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase()
+            .replace('_', "-");
+
+        // Arrays need prefixed colors, while single values can also be bare hex
+        let value = strip_comment(value.trim()).trim_end().trim_end_matches(',');
+        let unquoted = value.trim_matches(['"', '\'']);
+        let colors: Vec<_> = match parse_hex(unquoted) {
+            Some(color) => vec![color],
+            None => value
+                .split(|c: char| c.is_whitespace() || matches!(c, ',' | '[' | ']' | '"' | '\''))
+                .filter(|t| t.starts_with('#') || t.starts_with("0x") || t.starts_with("rgb:"))
+                .filter_map(parse_hex)
+                .collect(),
+        };
+
+        for (i, color) in colors.into_iter().enumerate() {
+            match role(format, &section, &key, i) {
+                Some(role) => palette.push_role(role, color),
+                None => palette.push(color),
+            }
+        }
+    }
+    palette
+}
+
+/// Strip a trailing comment, which starts with a `#` after whitespace and outside of quotes
+fn strip_comment(value: &str) -> &str {
+    let mut quote = None;
+    let mut prev = ' ';
+    for (i, c) in value.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '#') if i > 0 && prev.is_whitespace() => return &value[..i],
+            _ => {},
+        }
+        prev = c;
+    }
+    value
+}
+
+/// Role name for a theme's key, normalized to terminal names (ie `color1`) or base16 names
+fn role(format: Format, section: &str, key: &str, index: usize) -> Option<String> {
+    let role = match format {
+        Format::Base16 if key.starts_with("base") => key.to_string(),
+        Format::Base16 => return None,
+        Format::Foot => {
+            let (offset, n) = match (key.strip_prefix("regular"), key.strip_prefix("bright")) {
+                (Some(n), _) => (0, n),
+                (_, Some(n)) => (8, n),
+                _ => return Some(key.to_string()),
+            };
+            format!("color{}", offset + n.parse::<usize>().ok()?)
+        },
+        Format::Wezterm => match key {
+            "ansi" => format!("color{index}"),
+            "brights" => format!("color{}", index + 8),
+            _ => key.to_string(),
+        },
+        Format::Alacritty => match section.rsplit('.').next() {
+            Some("normal") => key.to_string(),
+            Some("bright") => format!("bright-{key}"),
+            Some("dim") => format!("dim-{key}"),
+            Some("primary") | None | Some("") => key.to_string(),
+            Some(section) => format!("{section}-{key}"),
+        },
+        _ => key.to_string(),
+    };
+    Some(role)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_formats() {
+        let roles = |format, text: &str| parse(format, text.as_bytes()).unwrap().roles;
+        let red = [0xcc, 0x24, 0x1d];
+
+        let gpl = "GIMP Palette\nName: test\n#\n204  36  29\tred\n 40 40 40\n";
+        assert_eq!(
+            parse(Format::Gpl, gpl.as_bytes()).unwrap().colors,
+            vec![red, [40, 40, 40]]
+        );
+        let paint_net = "; paint.net Palette File\nFFCC241D\n";
+        assert_eq!(
+            parse(Format::PaintNet, paint_net.as_bytes())
+                .unwrap()
+                .colors,
+            vec![red]
+        );
+        // Multibyte characters are an error rather than a panic
+        assert!(parse(Format::PaintNet, "aé12345".as_bytes()).is_err());
+
+        let base16 = "scheme: \"Gruvbox\"\nbase00: \"282828\" # bg\nbase08: \"cc241d\"\n";
+        assert_eq!(roles(Format::Base16, base16)[1], ("base08".into(), red));
+        let pywal = "{\n  \"special\": {\n    \"background\": \"#282828\"\n  },\n  \"colors\": {\n    \"color1\": \"#cc241d\"\n  }\n}";
+        assert_eq!(roles(Format::Pywal, pywal)[1], ("color1".into(), red));
+        let minified = r##"{"special":{"background":"#282828"},"colors":{"color10":"#98971a","color1":"#cc241d","color2":"#98971a"}}"##;
+        let pywal = roles(Format::Pywal, minified);
+        assert_eq!(pywal[1], ("color1".into(), red));
+        assert_eq!(pywal[3].0, "color10");
+        assert!(parse(Format::Pywal, b"{\"colors\": [").is_err());
+        let kitty = "# theme\nbackground #282828\ncolor1     #cc241d\n";
+        assert_eq!(roles(Format::Kitty, kitty)[1], ("color1".into(), red));
+        let alacritty =
+            "[colors.primary]\nbackground = '#282828'\n[colors.bright]\nred = '0xcc241d'\n";
+        assert_eq!(
+            roles(Format::Alacritty, alacritty)[1],
+            ("bright-red".into(), red)
+        );
+        let foot = "[colors]\nbackground=282828\nbright1=cc241d\n";
+        assert_eq!(roles(Format::Foot, foot)[1], ("color9".into(), red));
+        let wezterm = "[colors]\nansi = [\"#282828\", \"#cc241d\"]\n";
+        assert_eq!(roles(Format::Wezterm, wezterm)[1], ("color1".into(), red));
+        let xresources = "! comment\n*.background: #282828\nURxvt*color1: rgb:cc/24/1d\n";
+        assert_eq!(
+            roles(Format::Xresources, xresources)[1],
+            ("color1".into(), red)
+        );
+
+        // Adobe swatch exchange with a single rgb color
+        let mut ase = b"ASEF\0\x01\0\0\0\0\0\x01\0\x01".to_vec();
+        let mut block = b"\0\x02\0r\0\0RGB ".to_vec();
+        for c in [0.8f32, 36. / 255., 29. / 255.] {
+            block.extend(c.to_be_bytes());
+        }
+        block.extend([0, 2]);
+        ase.extend((block.len() as u32).to_be_bytes());
+        ase.extend(block);
+        assert_eq!(parse(Format::Ase, &ase).unwrap().colors, vec![red]);
+    }
+}
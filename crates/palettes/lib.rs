@@ -1,2 +1,50 @@
 #![doc = include_str!("README.md")]
+#![cfg_attr(no_palettes, allow(unreachable_code))]
+
+pub mod export;
+pub mod import;
+pub mod roles;
+
+#[cfg(feature = "loader")]
+pub mod loader;
+#[cfg(feature = "registry")]
+pub mod registry;
+
 include!(concat!(env!("OUT_DIR"), "/lib.rs"));
+
+#[cfg(all(test, feature = "all"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_metadata() {
+        let palette = Palette::GruvboxLight;
+        assert_eq!(palette.variant(), Some(Variant::Light));
+        assert_eq!(palette.family(), Some("gruvbox"));
+        assert_eq!(palette.role("red"), Some([0xcc, 0x24, 0x1d]));
+
+        // Roles can be looked up by equivalent base16 names
+        let palette = Palette::CatppuccinMocha;
+        assert_eq!(palette.role("base08"), Some([0xf3, 0x8b, 0xa8]));
+
+        // Variants are derived from the name, or set explicitly, for palettes without roles
+        assert!(Palette::Carburetor.roles().is_empty());
+        let light = [
+            Palette::RosePineDawn,
+            Palette::MaterialLighter,
+            Palette::Tomorrow,
+            Palette::Alabaster,
+        ];
+        let dark = [
+            Palette::Carburetor,
+            Palette::Nord,
+            Palette::Onehalfdark,
+            Palette::Frontenddelight,
+        ];
+        assert!(light.iter().all(|p| p.variant() == Some(Variant::Light)));
+        assert!(dark.iter().all(|p| p.variant() == Some(Variant::Dark)));
+
+        // Palettes without any hint have no variant, rather than a guess
+        assert_eq!(Palette::Wombat.variant(), None);
+    }
+}
@@ -0,0 +1,199 @@
+//! Runtime loader for the `palettes.toml` format, for apps that only compile a few builtin
+//! palettes (see the crate features) and read the rest from disk.
+//!
+//! ```rust
+//! use lutgen_palettes::loader;
+//!
+//! let palettes = loader::parse(
+//!     r##"
+//!     nord = ["#2e3440", "#bf616a", "#eceff4"]
+//!     gruvbox_dark.variant = "dark"
+//!     gruvbox_dark.roles.bg = "#282828"
+//!     gruvbox_dark.colors = ["#282828", "#cc241d", "#ebdbb2"]
+//!     "##,
+//! )
+//! .unwrap();
+//!
+//! assert_eq!(palettes[0].name, "gruvbox-dark");
+//! assert_eq!(palettes[0].role("background"), Some([0x28, 0x28, 0x28]));
+//! assert_eq!(palettes[1].colors[1], [0xbf, 0x61, 0x6a]);
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::{roles, Variant};
+
+/// Palette entry, either a list of colors or a table with metadata
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Colors(Vec<String>),
+    Table(Table),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Table {
+    colors: Vec<String>,
+    variant: Option<String>,
+    family: Option<String>,
+    collection: Option<String>,
+    author: Option<String>,
+    url: Option<String>,
+    #[serde(default)]
+    roles: BTreeMap<String, String>,
+}
+
+/// Palette read at runtime, with the same metadata as builtin [`crate::Palette`]s.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadedPalette {
+    /// Kebab-case name, matching the name of the equivalent builtin palette
+    pub name: String,
+    pub colors: Vec<[u8; 3]>,
+    pub variant: Option<Variant>,
+    pub family: Option<String>,
+    pub collection: Option<String>,
+    pub author: Option<String>,
+    pub url: Option<String>,
+    pub roles: Vec<(String, [u8; 3])>,
+}
+
+impl LoadedPalette {
+    /// Get the color for a role name, or any equivalent role (ie `base08` and `red`)
+    pub fn role(&self, name: &str) -> Option<[u8; 3]> {
+        let roles: Vec<_> = self.roles.iter().map(|(r, c)| (r.as_str(), *c)).collect();
+        roles::find(&roles, name)
+    }
+}
+
+/// Read palettes from a file in the `palettes.toml` format.
+pub fn load(path: &Path) -> Result<Vec<LoadedPalette>, String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|e| format!("failed to read {path:?}: {e}"))?;
+    parse(&text).map_err(|e| format!("{path:?}: {e}"))
+}
+
+/// Parse palettes in the `palettes.toml` format, sorted by name. Every invalid entry is reported
+/// in the error, one per line.
+pub fn parse(text: &str) -> Result<Vec<LoadedPalette>, String> {
+    let entries: BTreeMap<String, Entry> =
+        toml::from_str(text).map_err(|e| format!("invalid toml: {e}"))?;
+
+    let mut errors = Vec::new();
+    let mut palettes = Vec::with_capacity(entries.len());
+    for (key, entry) in entries {
+        let table = match entry {
+            Entry::Colors(colors) => Table {
+                colors,
+                variant: None,
+                family: None,
+                collection: None,
+                author: None,
+                url: None,
+                roles: BTreeMap::new(),
+            },
+            Entry::Table(table) => table,
+        };
+
+        // Duplicate colors are kept, since some builtin palettes repeat a color from their source
+        let mut colors = Vec::with_capacity(table.colors.len());
+        for (i, code) in table.colors.iter().enumerate() {
+            match parse_color(code) {
+                Ok(color) => colors.push(color),
+                Err(e) => errors.push(format!("`{key}` color {i} (`{code}`): {e}")),
+            }
+        }
+
+        let mut roles = Vec::with_capacity(table.roles.len());
+        for (role, code) in table.roles {
+            match parse_color(&code) {
+                Ok(color) if colors.contains(&color) => {
+                    roles.push((role.to_ascii_lowercase(), color))
+                },
+                Ok(_) => errors.push(format!(
+                    "`{key}` role `{role}` (`{code}`): not one of the palette's colors"
+                )),
+                Err(e) => errors.push(format!("`{key}` role `{role}` (`{code}`): {e}")),
+            }
+        }
+
+        let variant = match table.variant.as_deref().map(Variant::from_str) {
+            None => None,
+            Some(Ok(variant)) => Some(variant),
+            Some(Err(_)) => {
+                errors.push(format!(
+                    "`{key}` variant: unknown variant `{}`, expected dark or light",
+                    table.variant.unwrap_or_default()
+                ));
+                None
+            },
+        };
+
+        palettes.push(LoadedPalette {
+            name: kebab_case(&key),
+            colors,
+            variant,
+            family: table.family,
+            collection: table.collection,
+            author: table.author,
+            url: table.url,
+            roles,
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(palettes)
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+/// Kebab-case name for a key, ie `gruvbox_dark` to `gruvbox-dark`. Numbers are kept with the
+/// previous word, the same as builtin palette names (ie `windows_10_light` to `windows10-light`).
+fn kebab_case(key: &str) -> String {
+    let mut name = String::with_capacity(key.len());
+    for word in key.split('_').filter(|w| !w.is_empty()) {
+        let number = word.starts_with(|c: char| c.is_ascii_digit());
+        if !name.is_empty() && !number {
+            name.push('-');
+        }
+        name.push_str(&word.to_lowercase());
+    }
+    name
+}
+
+fn parse_color(code: &str) -> Result<[u8; 3], String> {
+    let hex = code
+        .strip_prefix('#')
+        .ok_or("expected a hex string starting with `#`")?;
+    if hex.len() != 6 {
+        return Err("expected a 6 digit hex string".into());
+    }
+    let n = u32::from_str_radix(hex, 16).map_err(|_| "expected a valid hex string")?;
+    Ok([(n >> 16) as u8, (n >> 8) as u8, n as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixed_case_roles() {
+        let palettes = parse(
+            r##"
+            test.roles.Base0D = "#458588"
+            test.colors = ["#282828", "#458588"]
+            "##,
+        )
+        .unwrap();
+        assert_eq!(
+            palettes[0].roles,
+            vec![("base0d".into(), [0x45, 0x85, 0x88])]
+        );
+        assert_eq!(palettes[0].role("BLUE"), Some([0x45, 0x85, 0x88]));
+    }
+}
@@ -0,0 +1,327 @@
+//! Registry of every available palette, merging builtins with palette files in a directory and
+//! palettes added at runtime.
+//!
+//! ```rust
+//! use lutgen_palettes::import::ImportedPalette;
+//! use lutgen_palettes::registry::{PaletteRegistry, RegistryError, Source};
+//!
+//! let mut registry = PaletteRegistry::new();
+//! registry.add(
+//!     "my-palette",
+//!     ImportedPalette {
+//!         colors: vec![[0x28, 0x28, 0x28], [0xeb, 0xdb, 0xb2]],
+//!         roles: vec![],
+//!     },
+//! );
+//!
+//! // Names are case-insensitive
+//! let entry = registry.resolve("My-Palette").unwrap();
+//! assert!(matches!(entry.source, Source::User(_)));
+//! let nord = registry.resolve("nord").unwrap().load().unwrap();
+//! assert!(nord.colors.contains(&[0x2e, 0x34, 0x40]));
+//!
+//! // Unknown names suggest the closest match
+//! match registry.resolve("gruvbox-drak") {
+//!     Err(RegistryError::Unknown { suggestion, .. }) => {
+//!         assert_eq!(suggestion.as_deref(), Some("gruvbox-dark"))
+//!     },
+//!     _ => unreachable!(),
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use crate::import::{self, ImportedPalette};
+use crate::Palette;
+
+/// Minimum jaro-winkler similarity for a palette to be included in search results.
+const MIN_SIMILARITY: f64 = 0.7;
+
+static BUILTINS: OnceLock<Vec<Entry>> = OnceLock::new();
+
+/// Where a registered palette comes from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Source {
+    Builtin(Palette),
+    /// Palette file in the registry directory, read when loaded
+    File(PathBuf),
+    /// Palette added with [`PaletteRegistry::add`]
+    User(ImportedPalette),
+}
+
+/// Named palette in a [`PaletteRegistry`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Entry {
+    pub name: String,
+    pub source: Source,
+}
+
+impl Entry {
+    /// Get the palette colors and roles, reading palette files from disk.
+    pub fn load(&self) -> Result<ImportedPalette, RegistryError> {
+        match &self.source {
+            Source::Builtin(palette) => Ok(ImportedPalette {
+                colors: palette.get().to_vec(),
+                roles: palette
+                    .roles()
+                    .iter()
+                    .map(|(role, color)| (role.to_string(), *color))
+                    .collect(),
+            }),
+            Source::File(path) => import::read(path).map_err(|error| RegistryError::Read {
+                path: path.clone(),
+                error,
+            }),
+            Source::User(palette) => Ok(palette.clone()),
+        }
+    }
+}
+
+/// Errors from resolving and loading palettes.
+#[derive(Debug)]
+pub enum RegistryError {
+    /// No palette with the name, with the closest match if there is a similar one
+    Unknown {
+        name: String,
+        suggestion: Option<String>,
+    },
+    /// Failed to list the palette directory
+    Dir {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// Failed to read or parse a palette file
+    Read { path: PathBuf, error: String },
+}
+
+impl Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistryError::Unknown {
+                name,
+                suggestion: Some(suggestion),
+            } => write!(f, "unknown palette `{name}`, did you mean `{suggestion}`?"),
+            RegistryError::Unknown { name, .. } => write!(f, "unknown palette `{name}`"),
+            RegistryError::Dir { path, error } => {
+                write!(f, "failed to read palette directory {path:?}: {error}")
+            },
+            RegistryError::Read { path, error } => {
+                write!(f, "failed to read palette file {path:?}: {error}")
+            },
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+/// Builtin palettes, palette files in a directory, and palettes added at runtime.
+///
+/// Names are case-insensitive. Added palettes take precedence over palette files, which take
+/// precedence over builtins with the same name. Palette files are named by their file stem, ie
+/// `My-palette.txt` is available as `my-palette`.
+#[derive(Clone, Debug, Default)]
+pub struct PaletteRegistry {
+    dir: Option<PathBuf>,
+    files: Vec<Entry>,
+    user: Vec<Entry>,
+    modified: Option<SystemTime>,
+}
+
+impl PaletteRegistry {
+    /// Registry with only builtin palettes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Default palette directory, `$LUTGEN_DIR` or `<CONFIG DIR>/lutgen`.
+    pub fn default_dir() -> Option<PathBuf> {
+        std::env::var_os("LUTGEN_DIR")
+            .map(Into::into)
+            .or_else(|| dirs::config_dir().map(|dir| dir.join("lutgen")))
+    }
+
+    /// Registry with builtin palettes and palette files in a directory. A missing directory is
+    /// treated as empty, so it can be created later.
+    pub fn with_dir(dir: impl Into<PathBuf>) -> Result<Self, RegistryError> {
+        let mut registry = Self {
+            dir: Some(dir.into()),
+            ..Default::default()
+        };
+        registry.rescan()?;
+        Ok(registry)
+    }
+
+    /// Registry with builtin palettes and palette files in the [`Self::default_dir`], if any.
+    pub fn with_default_dir() -> Result<Self, RegistryError> {
+        match Self::default_dir() {
+            Some(dir) => Self::with_dir(dir),
+            None => Ok(Self::new()),
+        }
+    }
+
+    /// Directory palette files are read from.
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Add a palette, replacing any previously added palette with the same name.
+    pub fn add(&mut self, name: impl Into<String>, palette: ImportedPalette) {
+        let name = name.into();
+        self.user.retain(|e| !e.name.eq_ignore_ascii_case(&name));
+        self.user.push(Entry {
+            name,
+            source: Source::User(palette),
+        });
+    }
+
+    /// Remove an added palette, returning whether it existed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.user.len();
+        self.user.retain(|e| !e.name.eq_ignore_ascii_case(name));
+        self.user.len() != len
+    }
+
+    /// Re-read the list of palette files in the directory.
+    pub fn rescan(&mut self) -> Result<(), RegistryError> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let error = |error| RegistryError::Dir {
+            path: dir.clone(),
+            error,
+        };
+
+        self.files.clear();
+        self.modified = None;
+        if !dir.is_dir() {
+            return Ok(());
+        }
+        self.modified = dir.metadata().and_then(|m| m.modified()).ok();
+        for entry in std::fs::read_dir(dir).map_err(error)? {
+            let path = entry.map_err(error)?.path();
+            let Some(stem) = path.file_stem().filter(|_| path.is_file()) else {
+                continue;
+            };
+            self.files.push(Entry {
+                name: stem.to_string_lossy().into_owned(),
+                source: Source::File(path),
+            });
+        }
+        self.files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(())
+    }
+
+    /// Rescan the directory if files were added, removed, or renamed since the last scan, and
+    /// return whether it changed. Cheap enough to poll, ie once per frame. Palette files are read
+    /// when loaded, so edits to existing files are always picked up.
+    pub fn refresh(&mut self) -> Result<bool, RegistryError> {
+        let Some(dir) = &self.dir else {
+            return Ok(false);
+        };
+        let modified = dir.metadata().and_then(|m| m.modified()).ok();
+        if modified == self.modified {
+            return Ok(false);
+        }
+        self.rescan()?;
+        Ok(true)
+    }
+
+    /// Every available palette sorted by name, without palettes shadowed by another source.
+    pub fn entries(&self) -> Vec<&Entry> {
+        let mut entries = BTreeMap::new();
+        for entry in self.sources() {
+            entries.entry(entry.name.to_lowercase()).or_insert(entry);
+        }
+        entries.into_values().collect()
+    }
+
+    /// Find a palette by name.
+    pub fn resolve(&self, name: &str) -> Result<&Entry, RegistryError> {
+        self.find(name).ok_or_else(|| RegistryError::Unknown {
+            name: name.to_string(),
+            suggestion: self.search(name).first().map(|(_, e)| e.name.clone()),
+        })
+    }
+
+    /// Find a palette by name, if it exists.
+    pub fn find(&self, name: &str) -> Option<&Entry> {
+        self.sources().find(|e| e.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Fuzzy search for palettes similar to the input, with a score from 0 to 1. Best matches
+    /// are first.
+    pub fn search(&self, input: &str) -> Vec<(f64, &Entry)> {
+        let input = input.to_lowercase();
+        let mut results: Vec<_> = self
+            .entries()
+            .into_iter()
+            .filter_map(|entry| {
+                let score = strsim::jaro_winkler(&input, &entry.name.to_lowercase());
+                (score > MIN_SIMILARITY).then_some((score, entry))
+            })
+            .collect();
+        results.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.name.cmp(&b.1.name)));
+        results
+    }
+
+    /// All entries in order of precedence, including shadowed ones
+    fn sources(&self) -> impl Iterator<Item = &Entry> {
+        let builtins = BUILTINS.get_or_init(|| {
+            Palette::VARIANTS
+                .iter()
+                .map(|&palette| Entry {
+                    name: palette.to_string(),
+                    source: Source::Builtin(palette),
+                })
+                .collect()
+        });
+        self.user.iter().chain(&self.files).chain(builtins)
+    }
+}
+
+#[cfg(all(test, not(no_palettes)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_files() {
+        let builtin = Palette::VARIANTS[0];
+        let dir = std::env::temp_dir().join(format!("lutgen-registry-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("My-Palette.txt"), "#282828 #ebdbb2").unwrap();
+        std::fs::write(dir.join(format!("{builtin}.txt")), "#000000").unwrap();
+        let mut registry = PaletteRegistry::with_dir(&dir).unwrap();
+
+        // Palette files are named by their stem, and shadow builtins
+        let entry = registry.resolve("my-palette").unwrap();
+        assert_eq!(entry.name, "My-Palette");
+        assert_eq!(
+            entry.load().unwrap().colors,
+            [[0x28, 0x28, 0x28], [0xeb, 0xdb, 0xb2]]
+        );
+        assert!(matches!(
+            registry.resolve(&builtin.to_string()).unwrap().source,
+            Source::File(_)
+        ));
+        assert_eq!(registry.entries().len(), Palette::VARIANTS.len() + 1);
+
+        std::fs::remove_file(dir.join(format!("{builtin}.txt"))).unwrap();
+        registry.rescan().unwrap();
+        assert_eq!(
+            registry
+                .resolve(&builtin.to_string().to_uppercase())
+                .unwrap()
+                .source,
+            Source::Builtin(builtin)
+        );
+        assert!(matches!(
+            registry.resolve("my-paltete"),
+            Err(RegistryError::Unknown { suggestion: Some(s), .. }) if s == "My-Palette"
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,64 @@
+//! Role names for palette colors, ie `bg`, `red`, `color1`, or `base08`.
+
+/// Groups of equivalent role names, so base16 and terminal palettes can be mapped to each other.
+pub const ALIASES: &[&[&str]] = &[
+    &["bg", "background", "base00"],
+    &["fg", "foreground", "base05"],
+    &["black", "color0"],
+    &["red", "color1", "base08"],
+    &["green", "color2", "base0b"],
+    &["yellow", "color3", "base0a"],
+    &["blue", "color4", "base0d"],
+    &["magenta", "color5", "base0e", "purple"],
+    &["cyan", "color6", "base0c"],
+    &["orange", "base09"],
+    &["brown", "base0f"],
+    &["white", "color7"],
+    &["bright-black", "color8"],
+    &["bright-red", "color9"],
+    &["bright-green", "color10"],
+    &["bright-yellow", "color11"],
+    &["bright-blue", "color12"],
+    &["bright-magenta", "color13", "bright-purple"],
+    &["bright-cyan", "color14"],
+    &["bright-white", "color15"],
+];
+
+/// Find the color for a role, or any equivalent role (ie `base08` and `red`). Role names are
+/// case-insensitive, so `base0D` matches `base0d`.
+pub fn find(roles: &[(&str, [u8; 3])], role: &str) -> Option<[u8; 3]> {
+    let aliases = ALIASES
+        .iter()
+        .find(|group| group.iter().any(|alias| alias.eq_ignore_ascii_case(role)))
+        .copied()
+        .unwrap_or_default();
+    std::iter::once(role)
+        .chain(aliases.iter().copied())
+        .find_map(|alias| {
+            roles
+                .iter()
+                .find(|(r, _)| r.eq_ignore_ascii_case(alias))
+                .map(|(_, c)| *c)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_aliases() {
+        let (blue, red) = ([0x45, 0x85, 0x88], [0xcc, 0x24, 0x1d]);
+        let roles = [("blue", blue), ("base08", red)];
+        assert_eq!(find(&roles, "base0d"), Some(blue));
+        assert_eq!(find(&roles, "base0D"), Some(blue));
+        assert_eq!(find(&roles, "Red"), Some(red));
+        assert_eq!(find(&roles, "green"), None);
+
+        // Stored role names can be mixed case too
+        let roles = [("base0D", blue), ("Red", red)];
+        assert_eq!(find(&roles, "base0d"), Some(blue));
+        assert_eq!(find(&roles, "blue"), Some(blue));
+        assert_eq!(find(&roles, "base08"), Some(red));
+    }
+}
@@ -36,6 +36,19 @@ impl FileDialog {
         }
     }
 
+    pub fn export(ctx: egui::Context) -> Self {
+        let mut inner = egui_file_dialog::FileDialog::new().title("Export Palette As");
+        for format in lutgen_palettes::export::Format::ALL {
+            inner = inner.add_save_extension(&format.to_string(), format.extension());
+        }
+        inner = inner.default_save_extension("gpl");
+        Self {
+            inner,
+            ctx,
+            save: true,
+        }
+    }
+
     pub fn pick(ctx: egui::Context) -> Self {
         let inner = egui_file_dialog::FileDialog::new()
             .add_file_filter_extensions("Images", IMAGE_EXTENSIONS.to_vec())
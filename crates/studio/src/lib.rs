@@ -6,7 +6,7 @@ use crate::file_picker::FileDialog;
 use crate::state::{LutAlgorithm, UiState};
 use crate::ui::left::{PaletteEditor, PaletteFilterBox};
 pub use crate::worker::Worker;
-use crate::worker::{LutAlgorithmArgs, WorkerHandle};
+use crate::worker::{BackendEvent, LutAlgorithmArgs, WorkerHandle};
 
 mod color;
 mod file_picker;
@@ -29,10 +29,16 @@ pub struct App {
     /// Filter box for selecting palettes
     palette_box: PaletteFilterBox,
     palette_edit: PaletteEditor,
+    /// Analysis of the current palette and the hash of the arguments it was computed with
+    analysis: Option<(u64, lutgen::analysis::Analysis)>,
+    /// Hash of the arguments for the analysis LUT currently being generated by the worker
+    pending_analysis: Option<u64>,
     // File pickers
     pub open_picker: FileDialog,
     #[cfg(not(target_arch = "wasm32"))]
     pub save_picker: FileDialog,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub export_picker: FileDialog,
     /// Lutgen icon
     icon: egui::TextureHandle,
 }
@@ -69,11 +75,15 @@ impl App {
         let mut this = Self {
             palette_box: PaletteFilterBox::new(&state.palette_selection),
             palette_edit: PaletteEditor::new(&state.palette_selection),
+            analysis: None,
+            pending_analysis: None,
             inline_layout: false,
             scene_rect: egui::Rect::NOTHING,
             open_picker: FileDialog::pick(cc.egui_ctx.clone()),
             #[cfg(not(target_arch = "wasm32"))]
             save_picker: FileDialog::save(cc.egui_ctx.clone()),
+            #[cfg(not(target_arch = "wasm32"))]
+            export_picker: FileDialog::export(cc.egui_ctx.clone()),
             state,
             icon,
             worker,
@@ -93,7 +103,14 @@ impl App {
     pub fn apply(&mut self) {
         // Show the spinner until we receive an edited image
         self.state.processing = true;
-        let args = match self.state.current_alg {
+        let args = self.lut_args();
+        self.worker
+            .apply_palette(self.state.palette.clone(), self.state.common, args);
+    }
+
+    /// Arguments for the currently selected algorithm
+    pub fn lut_args(&self) -> LutAlgorithmArgs {
+        match self.state.current_alg {
             LutAlgorithm::GaussianRbf => LutAlgorithmArgs::GaussianRbf {
                 rbf: self.state.common_rbf,
                 args: self.state.guassian_rbf,
@@ -109,9 +126,7 @@ impl App {
                 args: self.state.gaussian_blur,
             },
             LutAlgorithm::NearestNeighbor => LutAlgorithmArgs::NearestNeighbor,
-        };
-        self.worker
-            .apply_palette(self.state.palette.clone(), self.state.common, args);
+        }
     }
 }
 
@@ -125,7 +140,12 @@ impl eframe::App for App {
 
         // Handle any incoming events from the backend
         if let Some(event) = self.worker.poll_event() {
-            self.state.handle_event(ctx, event);
+            match event {
+                BackendEvent::AnalysisLut { hash, lut, dim, .. } => {
+                    self.finish_analysis(hash, &lut, dim)
+                },
+                event => self.state.handle_event(ctx, event),
+            }
         }
 
         #[cfg(not(target_arch = "wasm32"))]
@@ -140,6 +160,15 @@ impl eframe::App for App {
             self.worker.save_as(item);
         }
 
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = self.export_picker.poll() {
+            let palette = &self.state.palette_selection;
+            self.state.last_event = match palette.export(&self.state.palette, &path) {
+                Ok(()) => format!("Exported palette to {}", path.display()),
+                Err(e) => format!("Failed to export palette: {e}"),
+            };
+        }
+
         #[cfg(target_arch = "wasm32")]
         if let Some((path, bytes)) = self.open_picker.poll() {
             self.worker.load_file(path.clone(), bytes);
@@ -147,6 +176,9 @@ impl eframe::App for App {
             self.apply();
         }
 
+        // Pick up palette files added or removed outside of the app
+        self.palette_box.refresh(&self.state.palette_selection);
+
         // Show UI
         self.show(ctx);
     }
@@ -1,10 +1,8 @@
-use std::borrow::Cow;
 use std::fmt::Display;
-use std::fs::File;
-use std::io::Read;
 use std::path::PathBuf;
 use std::str::FromStr;
 
+use lutgen_palettes::registry::{Entry, PaletteRegistry, Source};
 use serde::{Deserialize, Serialize};
 
 use crate::color::Color;
@@ -21,76 +19,63 @@ impl Display for DynamicPalette {
     }
 }
 
-pub fn lutgen_dir() -> PathBuf {
-    std::env::var("LUTGEN_DIR")
-        .map(Into::into)
-        .unwrap_or(dirs::config_dir().unwrap().join("lutgen"))
-}
-
 impl DynamicPalette {
-    /// Get all palettes from disk & builtin
-    pub fn get_all() -> std::io::Result<Vec<Self>> {
-        let mut palettes = lutgen_palettes::Palette::VARIANTS
-            .iter()
-            .map(|v| DynamicPalette::Builtin(*v))
-            .collect::<Vec<_>>();
-
-        let path = std::env::var("LUTGEN_DIR")
-            .map(Into::into)
-            .unwrap_or(dirs::config_dir().unwrap_or_default().join("lutgen"));
-        if path.is_dir() {
-            for entry in std::fs::read_dir(path)? {
-                let path = entry?.path();
-                palettes.push(DynamicPalette::Custom(
-                    path.file_stem()
-                        .expect("missing file stem")
-                        .display()
-                        .to_string(),
-                ));
-            }
+    /// Get a palette for a registry entry
+    pub fn from_entry(entry: &Entry) -> Self {
+        match entry.source {
+            Source::Builtin(palette) => DynamicPalette::Builtin(palette),
+            _ => DynamicPalette::Custom(entry.name.clone()),
         }
-        palettes.sort_by_cached_key(|a| a.to_string());
+    }
 
-        Ok(palettes)
+    /// Save colors to a custom palette file in the registry directory
+    pub fn save(&self, registry: &PaletteRegistry, palette: &[[u8; 3]]) -> Result<PathBuf, String> {
+        let Self::Custom(name) = self else {
+            return Err("builtin palettes cannot be saved".into());
+        };
+        let dir = registry
+            .dir()
+            .ok_or("failed to find the config directory, set `$LUTGEN_DIR`")?;
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let path = match registry.find(name).map(|e| &e.source) {
+            Some(Source::File(path)) => path.clone(),
+            _ => dir.join(name),
+        };
+        let text = palette
+            .iter()
+            .map(|c| Color(*c).to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        std::fs::write(&path, text).map_err(|e| e.to_string())?;
+        Ok(path)
     }
 
-    pub fn save(&self, palette: &[[u8; 3]]) -> std::io::Result<()> {
-        if let Self::Custom(name) = self {
-            let path = lutgen_dir().join(name);
-            std::fs::write(
-                path,
-                palette
-                    .iter()
-                    .map(|c| Color(*c).to_string())
-                    .collect::<Vec<_>>()
-                    .join("\n"),
-            )?;
+    /// Get the palette colors, reading custom palettes from disk
+    pub fn load(&self, registry: &PaletteRegistry) -> Result<Vec<[u8; 3]>, String> {
+        match self {
+            DynamicPalette::Builtin(palette) => Ok(palette.get().to_vec()),
+            DynamicPalette::Custom(name) => registry
+                .resolve(name)
+                .and_then(|entry| entry.load())
+                .map(|p| p.colors)
+                .map_err(|e| e.to_string()),
         }
-        Ok(())
     }
 
-    pub fn get(&self) -> Cow<'static, [[u8; 3]]> {
-        match self {
-            DynamicPalette::Builtin(palette) => palette.get().into(),
-            DynamicPalette::Custom(name) => {
-                // read palette from disk
-                let path = lutgen_dir().join(name);
-                let mut contents = String::new();
-                File::open(path)
-                    .unwrap()
-                    .read_to_string(&mut contents)
-                    .unwrap();
+    /// Export palette colors to a file, in the format for the file's extension
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export(&self, palette: &[[u8; 3]], path: &std::path::Path) -> Result<(), String> {
+        use lutgen_palettes::export;
 
-                // split by whitespace and parse colors
-                contents
-                    .split_whitespace()
-                    .map(Color::from_str)
-                    .map(|v| v.map(|v| v.0))
-                    .collect::<Result<Vec<_>, _>>()
-                    .unwrap()
-                    .into()
-            },
-        }
+        let format = export::Format::from_path(path)
+            .ok_or_else(|| format!("unsupported format for {}", path.display()))?;
+        // Roles only apply if the builtin palette wasn't edited
+        let roles = match self {
+            DynamicPalette::Builtin(p) if p.get() == palette => p.roles(),
+            _ => &[],
+        };
+        std::fs::write(path, export::export(format, self.as_str(), palette, roles))
+            .map_err(|e| e.to_string())
     }
 
     pub fn as_str(&self) -> &str {
@@ -15,6 +15,8 @@ pub struct UiState {
     // main window state
     pub show_about: bool,
     #[serde(skip)]
+    pub show_analysis: bool,
+    #[serde(skip)]
     pub processing: bool,
     #[cfg_attr(target_arch = "wasm32", serde(skip))]
     pub current_image: Option<PathBuf>,
@@ -46,6 +48,7 @@ impl Default for UiState {
         Self {
             // default is true for first starts
             show_about: true,
+            show_analysis: false,
             processing: false,
             update: None,
             last_event: "Started.".to_string(),
@@ -116,6 +119,8 @@ impl UiState {
             BackendEvent::Update(update) => {
                 self.update = Some(update);
             },
+            // Analysis state lives on the app, which handles these before the ui state
+            BackendEvent::AnalysisLut { .. } => {},
             #[cfg(target_arch = "wasm32")]
             BackendEvent::SaveData(_, data, format) => {
                 use web_sys::wasm_bindgen::JsCast;
@@ -0,0 +1,197 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use lutgen::analysis::{Analysis, Distribution, NEAR_DUPLICATE};
+
+use crate::color::Color;
+use crate::palette::DynamicPalette;
+use crate::state::Common;
+use crate::App;
+
+/// Hald clut level used for analysis, low enough to regenerate while editing
+const ANALYSIS_LEVEL: u8 = 6;
+
+/// Small color square, with the hex code on hover
+fn swatch(ui: &mut egui::Ui, [r, g, b]: [u8; 3]) {
+    let (rect, res) = ui.allocate_exact_size(egui::vec2(14., 14.), egui::Sense::hover());
+    ui.painter()
+        .rect_filled(rect, 2., egui::Color32::from_rgb(r, g, b));
+    res.on_hover_text(Color([r, g, b]).to_string());
+}
+
+fn distribution(ui: &mut egui::Ui, label: &str, dist: &Distribution) {
+    ui.label(label);
+    ui.label(format!(
+        "{:.2} – {:.2}, mean {:.2}",
+        dist.min, dist.max, dist.mean
+    ));
+    let max = dist.histogram.iter().copied().max().unwrap_or(0).max(1);
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(64., 14.), egui::Sense::hover());
+    let width = rect.width() / dist.histogram.len() as f32;
+    for (i, &n) in dist.histogram.iter().enumerate() {
+        let height = rect.height() * n as f32 / max as f32;
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(rect.left() + i as f32 * width, rect.bottom() - height),
+            egui::pos2(rect.left() + (i + 1) as f32 * width - 1., rect.bottom()),
+        );
+        ui.painter()
+            .rect_filled(bar, 0., ui.visuals().strong_text_color());
+    }
+    ui.end_row();
+}
+
+impl App {
+    /// Request a new analysis LUT from the worker if the palette or algorithm arguments changed
+    fn update_analysis(&mut self) {
+        let args = self.lut_args();
+        let common = Common {
+            level: ANALYSIS_LEVEL,
+            ..self.state.common
+        };
+        let mut hasher = DefaultHasher::new();
+        self.state.palette.hash(&mut hasher);
+        common.hash(&mut hasher);
+        args.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if self.analysis.as_ref().is_some_and(|(h, _)| *h == hash)
+            || self.pending_analysis == Some(hash)
+        {
+            return;
+        }
+        if self.state.palette.is_empty() {
+            self.analysis = Some((hash, Analysis::default()));
+            self.pending_analysis = None;
+            return;
+        }
+        self.pending_analysis = Some(hash);
+        self.worker
+            .analyze(hash, self.state.palette.clone(), common, args);
+    }
+
+    /// Analyze the palette with a LUT from the worker, unless a newer one has been requested since
+    pub fn finish_analysis(&mut self, hash: u64, lut: &[u8], dim: u32) {
+        if self.pending_analysis != Some(hash) {
+            return;
+        }
+        self.pending_analysis = None;
+
+        // Roles are only known for unedited builtin palettes
+        let background = match self.state.palette_selection {
+            DynamicPalette::Builtin(p) => p.role("bg"),
+            DynamicPalette::Custom(_) => None,
+        };
+        let lut = lutgen::RgbImage::from_raw(dim, dim, lut.to_vec());
+        let analysis = lutgen::analysis::analyze(&self.state.palette, background, lut.as_ref());
+        self.analysis = Some((hash, analysis));
+    }
+
+    fn show_analysis(&mut self, ui: &mut egui::Ui) {
+        self.update_analysis();
+        let Some((_, analysis)) = self.analysis.clone() else {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Analyzing palette");
+            });
+            return;
+        };
+        if analysis.contrast.is_empty() {
+            ui.label("Add colors to the palette to analyze it");
+            return;
+        }
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::CollapsingHeader::new("Contrast")
+                .default_open(true)
+                .show(ui, |ui| {
+                    egui::Grid::new("contrast").striped(true).show(ui, |ui| {
+                        ui.label("fg");
+                        ui.label("bg");
+                        ui.label("WCAG");
+                        ui.label("APCA");
+                        ui.end_row();
+                        for c in &analysis.contrast {
+                            swatch(ui, c.fg);
+                            swatch(ui, c.bg);
+                            ui.label(format!("{:.2}", c.wcag));
+                            ui.label(format!("{:.1}", c.apca));
+                            ui.end_row();
+                        }
+                    });
+                });
+
+            egui::CollapsingHeader::new("Distinctness")
+                .default_open(true)
+                .show(ui, |ui| {
+                    if let Some(pair) = analysis.closest {
+                        ui.horizontal(|ui| {
+                            ui.label("Closest pair");
+                            swatch(ui, pair.a);
+                            swatch(ui, pair.b);
+                            ui.label(format!("ΔE {:.2}", pair.delta_e));
+                        });
+                    }
+                    if analysis.near_duplicates.is_empty() {
+                        ui.label(format!("No near duplicates under ΔE {NEAR_DUPLICATE}"));
+                    }
+                    for pair in &analysis.near_duplicates {
+                        ui.horizontal(|ui| {
+                            ui.label("Near duplicate");
+                            swatch(ui, pair.a);
+                            swatch(ui, pair.b);
+                            ui.label(format!("ΔE {:.2}", pair.delta_e));
+                        });
+                    }
+                });
+
+            egui::CollapsingHeader::new("Distribution")
+                .default_open(true)
+                .show(ui, |ui| {
+                    egui::Grid::new("distribution").show(ui, |ui| {
+                        distribution(ui, "Lightness", &analysis.lightness);
+                        distribution(ui, "Chroma", &analysis.chroma);
+                    });
+                    if analysis.hue_gaps.is_empty() {
+                        ui.label("No hue gaps");
+                    }
+                    for gap in &analysis.hue_gaps {
+                        ui.label(format!(
+                            "Hue gap {:.0}° – {:.0}° ({:.0}° wide)",
+                            gap.start,
+                            (gap.start + gap.size).rem_euclid(360.),
+                            gap.size
+                        ));
+                    }
+                });
+
+            egui::CollapsingHeader::new("Remapping")
+                .default_open(true)
+                .show(ui, |ui| {
+                    ui.label(format!(
+                        "Mean ΔE {:.2} over an sRGB grid with the current algorithm",
+                        analysis.remap_error
+                    ));
+                    for region in &analysis.poor_regions {
+                        ui.horizontal(|ui| {
+                            swatch(ui, region.color);
+                            ui.label("→");
+                            swatch(ui, region.mapped);
+                            ui.label(format!("ΔE {:.2}", region.delta_e));
+                        });
+                    }
+                });
+        });
+    }
+
+    pub fn show_analysis_window(&mut self, ctx: &egui::Context) {
+        let mut show = self.state.show_analysis;
+        egui::Window::new(format!(
+            "Palette Analysis: {}",
+            self.state.palette_selection
+        ))
+        .id(egui::Id::new("palette_analysis"))
+        .open(&mut show)
+        .default_width(320.)
+        .show(ctx, |ui| self.show_analysis(ui));
+        self.state.show_analysis = show;
+    }
+}
@@ -1,9 +1,10 @@
 use std::ops::RangeInclusive;
 use std::rc::Rc;
 
+use lutgen_palettes::registry::PaletteRegistry;
 use strum::VariantArray;
 
-use crate::palette::{lutgen_dir, DynamicPalette};
+use crate::palette::DynamicPalette;
 use crate::state::LutAlgorithm;
 use crate::utils::floor_to_pixels;
 use crate::App;
@@ -34,6 +35,8 @@ fn labeled_slider<Num: egui::emath::Numeric>(
 }
 
 pub struct PaletteFilterBox {
+    /// Builtin and custom palettes
+    pub registry: PaletteRegistry,
     items: Vec<Rc<DynamicPalette>>,
     idx: usize,
     filter: String,
@@ -42,28 +45,28 @@ pub struct PaletteFilterBox {
 
 impl PaletteFilterBox {
     pub fn new(current: &DynamicPalette) -> Self {
-        let items: Vec<_> = DynamicPalette::get_all()
-            .unwrap()
-            .into_iter()
-            .map(Rc::new)
-            .collect();
-
-        Self {
+        let registry = PaletteRegistry::with_default_dir().unwrap_or_else(|e| {
+            log::error!("{e}");
+            PaletteRegistry::new()
+        });
+        let mut this = Self {
+            registry,
+            items: Vec::new(),
+            idx: 0,
             filter: String::new(),
-            filtered: items.clone(),
-            idx: items
-                .iter()
-                .position(|v| **v == *current)
-                .unwrap_or_default(),
-            items,
-        }
+            filtered: Vec::new(),
+        };
+        this.reindex(current);
+        this
     }
 
+    /// Rebuild the list of palettes from the registry
     pub fn reindex(&mut self, current: &DynamicPalette) {
-        self.items = DynamicPalette::get_all()
-            .unwrap()
+        self.items = self
+            .registry
+            .entries()
             .into_iter()
-            .map(Rc::new)
+            .map(|entry| Rc::new(DynamicPalette::from_entry(entry)))
             .collect();
         self.filter();
         self.idx = self
@@ -73,16 +76,34 @@ impl PaletteFilterBox {
             .unwrap_or_default();
     }
 
+    /// Reindex if palette files were added or removed from the palette directory
+    pub fn refresh(&mut self, current: &DynamicPalette) {
+        match self.registry.refresh() {
+            Ok(true) => self.reindex(current),
+            Ok(false) => {},
+            Err(e) => log::error!("{e}"),
+        }
+    }
+
     fn filter(&mut self) {
         if self.filter.is_empty() {
             self.filtered = self.items.clone();
-        } else {
-            self.filtered = self
-                .items
-                .iter()
-                .filter(|palette| palette.as_str().contains(&self.filter.to_lowercase()))
-                .cloned()
-                .collect();
+            return;
+        }
+
+        // Substring matches first, then fuzzy matches to handle typos
+        let filter = self.filter.to_lowercase();
+        self.filtered = self
+            .items
+            .iter()
+            .filter(|palette| palette.as_str().to_lowercase().contains(&filter))
+            .cloned()
+            .collect();
+        for (_, entry) in self.registry.search(&filter) {
+            let palette = DynamicPalette::from_entry(entry);
+            if !self.filtered.iter().any(|p| **p == palette) {
+                self.filtered.push(Rc::new(palette));
+            }
         }
     }
 
@@ -206,7 +227,6 @@ impl PaletteEditor {
 
                 if ui.add_enabled(enabled, egui::Button::new("save")).clicked() {
                     *current = DynamicPalette::Custom(self.name.clone());
-                    current.save(palette).unwrap();
                     saved = true;
                 }
             });
@@ -465,7 +485,14 @@ impl App {
             .show(ui, &mut self.state.palette_selection)
             .changed()
         {
-            self.state.palette = self.state.palette_selection.get().to_vec();
+            self.state.palette = self
+                .state
+                .palette_selection
+                .load(&self.palette_box.registry)
+                .unwrap_or_else(|e| {
+                    log::error!("failed to read custom palette: {e}");
+                    Vec::new()
+                });
             self.palette_edit.name = self.state.palette_selection.to_string();
             apply = true;
         }
@@ -478,11 +505,16 @@ impl App {
         );
         apply |= changed;
         if saved {
+            let selection = &self.state.palette_selection;
+            self.state.last_event =
+                match selection.save(&self.palette_box.registry, &self.state.palette) {
+                    Ok(path) => format!("Saved custom palette to {}", path.display()),
+                    Err(e) => format!("Failed to save custom palette: {e}"),
+                };
+            if let Err(e) = self.palette_box.registry.rescan() {
+                log::error!("{e}");
+            }
             self.palette_box.reindex(&self.state.palette_selection);
-            self.state.last_event = format!(
-                "Saved custom palette to {}",
-                lutgen_dir().join(&self.palette_edit.name).display()
-            );
         }
 
         // settings panel
@@ -3,6 +3,7 @@ use egui::Context;
 use crate::App;
 
 mod about;
+mod analysis;
 mod central;
 pub mod left;
 pub mod scene;
@@ -13,6 +14,7 @@ impl App {
     /// Render ui state
     pub fn show(&mut self, ctx: &Context) {
         self.show_about_dialog(ctx);
+        self.show_analysis_window(ctx);
         self.show_topbar(ctx);
         self.show_statusline(ctx);
         self.show_sidebar(ctx);
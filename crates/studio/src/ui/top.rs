@@ -37,6 +37,19 @@ impl App {
                         ui.close();
                     }
 
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if ui.button("🖌  Export Palette As").clicked() {
+                        let name = format!("{}.gpl", self.state.palette_selection);
+                        let hint = std::env::current_dir().unwrap_or_default().join(name);
+                        self.export_picker.trigger(Some(hint));
+                        ui.close();
+                    }
+
+                    if ui.button("📊  Analyze Palette").clicked() {
+                        self.state.show_analysis = !self.state.show_analysis;
+                        ui.close();
+                    }
+
                     #[cfg(target_arch = "wasm32")]
                     ui.add_enabled_ui(self.state.current_image.is_some(), |ui| {
                         egui::containers::menu::SubMenuButton::new("💾  Export").ui(ui, |ui| {
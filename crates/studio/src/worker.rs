@@ -24,13 +24,14 @@ use crate::updates::UpdateInfo;
 pub enum FrontendEvent {
     LoadFile(PathBuf, #[cfg(target_arch = "wasm32")] Vec<u8>),
     Apply(Vec<[u8; 3]>, Common, LutAlgorithmArgs, Arc<AtomicBool>),
+    Analyze(u64, Vec<[u8; 3]>, Common, LutAlgorithmArgs, Arc<AtomicBool>),
     SaveAs(
         #[cfg(not(target_arch = "wasm32"))] PathBuf,
         #[cfg(target_arch = "wasm32")] image::ImageFormat,
     ),
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Hash, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Hash, Debug)]
 pub enum LutAlgorithmArgs {
     GaussianRbf {
         rbf: CommonRbf,
@@ -59,6 +60,13 @@ pub enum BackendEvent {
         image: Arc<[u8]>,
         dim: (u32, u32),
     },
+    /// Hald clut for palette analysis, tagged with the hash of the request arguments
+    AnalysisLut {
+        time: Duration,
+        hash: u64,
+        lut: Arc<[u8]>,
+        dim: u32,
+    },
     #[cfg(target_arch = "wasm32")]
     SaveData(Duration, String, image::ImageFormat),
 }
@@ -68,6 +76,9 @@ impl Display for BackendEvent {
         match self {
             BackendEvent::Error(e) => format!("Error: {e}").fmt(f),
             BackendEvent::Update(_) => Ok(()),
+            BackendEvent::AnalysisLut { time, .. } => {
+                format!("Generated analysis LUT in {time:.2?}").fmt(f)
+            },
             BackendEvent::SetImage {
                 time,
                 dim: (x, y),
@@ -111,6 +122,7 @@ pub struct WorkerHandle {
 
     rx: std::sync::mpsc::Receiver<BackendEvent>,
     abort: Arc<AtomicBool>,
+    analysis_abort: Arc<AtomicBool>,
 }
 
 impl WorkerHandle {
@@ -146,7 +158,12 @@ impl WorkerHandle {
             }
         });
 
-        WorkerHandle { tx, rx, abort }
+        WorkerHandle {
+            tx,
+            rx,
+            abort,
+            analysis_abort: Arc::new(AtomicBool::new(false)),
+        }
     }
 
     #[cfg(target_arch = "wasm32")]
@@ -163,7 +180,12 @@ impl WorkerHandle {
             })
             .spawn("worker.js");
 
-        Self { rx, bridge, abort }
+        Self {
+            rx,
+            bridge,
+            abort,
+            analysis_abort: Arc::new(AtomicBool::new(false)),
+        }
     }
 
     fn send(&self, event: FrontendEvent) {
@@ -203,6 +225,28 @@ impl WorkerHandle {
         ))
     }
 
+    /// Request a hald clut for analysis, answered with [`BackendEvent::AnalysisLut`]
+    pub fn analyze(
+        &mut self,
+        hash: u64,
+        palette: Vec<[u8; 3]>,
+        common: Common,
+        args: LutAlgorithmArgs,
+    ) {
+        // cancel previous run and init a new abort signal
+        self.analysis_abort
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+        self.analysis_abort = Arc::new(AtomicBool::new(false));
+
+        self.send(FrontendEvent::Analyze(
+            hash,
+            palette,
+            common,
+            args,
+            self.analysis_abort.clone(),
+        ))
+    }
+
     pub fn poll_event(&self) -> Option<BackendEvent> {
         self.rx.try_recv().ok()
     }
@@ -228,6 +272,9 @@ impl Worker {
             FrontendEvent::Apply(palette, common, args, abort) => {
                 self.apply_palette(palette, common, args, abort)
             },
+            FrontendEvent::Analyze(hash, palette, common, args, abort) => {
+                Ok(Self::analyze(hash, palette, common, args, abort))
+            },
         };
         match res {
             Ok(event) => event,
@@ -391,58 +438,8 @@ impl Worker {
         );
 
         // generate lut from arguments
-        let lut = match args {
-            LutAlgorithmArgs::GaussianRbf { rbf, args } => {
-                lutgen::interpolation::GaussianRemapper::new(
-                    &palette,
-                    *args.shape,
-                    rbf.nearest,
-                    *common.lum_factor,
-                    common.preserve,
-                )
-                .par_generate_lut_with_interrupt(common.level, abort)
-            },
-            LutAlgorithmArgs::ShepardsMethod { rbf, args } => {
-                lutgen::interpolation::ShepardRemapper::new(
-                    &palette,
-                    *args.power,
-                    rbf.nearest,
-                    *common.lum_factor,
-                    common.preserve,
-                )
-                .par_generate_lut_with_interrupt(common.level, abort)
-            },
-            LutAlgorithmArgs::GaussianSampling { args } => {
-                lutgen::interpolation::GaussianSamplingRemapper::new(
-                    &palette,
-                    *args.mean,
-                    *args.std_dev,
-                    args.iterations,
-                    *common.lum_factor,
-                    args.seed,
-                    common.preserve,
-                )
-                .par_generate_lut_with_interrupt(common.level, abort)
-            },
-            LutAlgorithmArgs::GaussianBlur { args } => {
-                lutgen::interpolation::GaussianBlurRemapper::new(
-                    &palette,
-                    *args.radius,
-                    *common.lum_factor,
-                    common.preserve,
-                )
-                .par_generate_lut_with_interrupt(common.level, abort)
-            },
-            LutAlgorithmArgs::NearestNeighbor => {
-                lutgen::interpolation::NearestNeighborRemapper::new(
-                    &palette,
-                    *common.lum_factor,
-                    common.preserve,
-                )
-                .par_generate_lut_with_interrupt(common.level, abort)
-            },
-        }
-        .ok_or("Cancelled generating hald clut".to_string())?;
+        let lut = generate_lut(&palette, common, args, abort)
+            .ok_or("Cancelled generating hald clut".to_string())?;
 
         // remap image
         lutgen::identity::correct_image_with_level(&mut image, &lut, common.level);
@@ -455,6 +452,84 @@ impl Worker {
             dim: (image.height(), image.width()),
         }))
     }
+
+    /// Generate a hald clut for palette analysis. Nothing is sent back when cancelled, since a
+    /// newer request replaced this one.
+    fn analyze(
+        hash: u64,
+        palette: Vec<[u8; 3]>,
+        common: Common,
+        args: LutAlgorithmArgs,
+        abort: Arc<AtomicBool>,
+    ) -> Option<BackendEvent> {
+        let time = Instant::now();
+        let lut = generate_lut(&palette, common, args, abort)?;
+        Some(BackendEvent::AnalysisLut {
+            time: time.elapsed(),
+            hash,
+            dim: lut.width(),
+            lut: lut.into_raw().into(),
+        })
+    }
+}
+
+/// Generate a hald clut for a palette with the given algorithm arguments, returning nothing if
+/// aborted.
+fn generate_lut(
+    palette: &[[u8; 3]],
+    common: Common,
+    args: LutAlgorithmArgs,
+    abort: Arc<AtomicBool>,
+) -> Option<lutgen::RgbImage> {
+    match args {
+        LutAlgorithmArgs::GaussianRbf { rbf, args } => {
+            lutgen::interpolation::GaussianRemapper::new(
+                palette,
+                *args.shape,
+                rbf.nearest,
+                *common.lum_factor,
+                common.preserve,
+            )
+            .par_generate_lut_with_interrupt(common.level, abort)
+        },
+        LutAlgorithmArgs::ShepardsMethod { rbf, args } => {
+            lutgen::interpolation::ShepardRemapper::new(
+                palette,
+                *args.power,
+                rbf.nearest,
+                *common.lum_factor,
+                common.preserve,
+            )
+            .par_generate_lut_with_interrupt(common.level, abort)
+        },
+        LutAlgorithmArgs::GaussianSampling { args } => {
+            lutgen::interpolation::GaussianSamplingRemapper::new(
+                palette,
+                *args.mean,
+                *args.std_dev,
+                args.iterations,
+                *common.lum_factor,
+                args.seed,
+                common.preserve,
+            )
+            .par_generate_lut_with_interrupt(common.level, abort)
+        },
+        LutAlgorithmArgs::GaussianBlur { args } => {
+            lutgen::interpolation::GaussianBlurRemapper::new(
+                palette,
+                *args.radius,
+                *common.lum_factor,
+                common.preserve,
+            )
+            .par_generate_lut_with_interrupt(common.level, abort)
+        },
+        LutAlgorithmArgs::NearestNeighbor => lutgen::interpolation::NearestNeighborRemapper::new(
+            palette,
+            *common.lum_factor,
+            common.preserve,
+        )
+        .par_generate_lut_with_interrupt(common.level, abort),
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -128,7 +128,7 @@
               commonArgs
               // {
                 inherit cargoArtifacts;
-                cargoTextExtraArgs = "--doc";
+                cargoTestExtraArgs = "--doc --all-features";
               }
             );
           };